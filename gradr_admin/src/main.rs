@@ -0,0 +1,82 @@
+// Maintenance tasks for a worker host.
+//
+// Usage: gradr_admin purge-retained [build_root]
+//        gradr_admin enqueue <path> <github_username> <assignment>
+//
+// `enqueue` queues up a build of a submission which didn't come from
+// GitHub: a directory, a `.tar.gz`/`.tgz`/`.zip` archive, or a git
+// bundle.  The path has to stay put until the build is done.
+
+extern crate libgradr;
+
+use libgradr::builder::github::BUILD_ROOT;
+use libgradr::builder::local::LocalSource;
+use libgradr::builder::workspace::purge_retained;
+use libgradr::database::Database;
+use libgradr::database::postgres_db::PostgresDatabase;
+
+use std::os;
+
+fn usage() {
+    println!("Usage: gradr_admin purge-retained [build_root]");
+    println!("       gradr_admin enqueue <path> <github_username> <assignment>");
+    os::set_exit_status(1);
+}
+
+fn enqueue(path: &str, username: &str, assignment: &str) {
+    let source = match LocalSource::from_path(&Path::new(path)) {
+        Some(s) => s,
+        None => {
+            println!("Not a directory, archive or bundle: {}", path);
+            os::set_exit_status(1);
+            return;
+        }
+    };
+    match PostgresDatabase::new_development() {
+        Some(db) => {
+            match db.add_pending_local(source, username, assignment) {
+                Ok(_) => (),
+                Err(e) => {
+                    println!("Could not enqueue {}: {}", path, e);
+                    os::set_exit_status(1);
+                }
+            }
+        },
+        None => {
+            println!("Could not connect to the database");
+            os::set_exit_status(1);
+        }
+    }
+}
+
+#[cfg(not(test))]
+fn main() {
+    let args = os::args();
+
+    if args.len() < 2 {
+        usage();
+        return;
+    }
+
+    match args[1].as_slice() {
+        "purge-retained" if args.len() <= 3 => {
+            let root =
+                if args.len() == 3 {
+                    Path::new(args[2].as_slice())
+                } else {
+                    Path::new(BUILD_ROOT)
+                };
+            match purge_retained(&root) {
+                Ok(n) => println!("Removed {} retained workspace(s)", n),
+                Err(e) => {
+                    println!("Could not purge {}: {}", root.display(), e);
+                    os::set_exit_status(1);
+                }
+            }
+        },
+        "enqueue" if args.len() == 5 => {
+            enqueue(args[2].as_slice(), args[3].as_slice(), args[4].as_slice())
+        },
+        _ => usage()
+    }
+}
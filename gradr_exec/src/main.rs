@@ -0,0 +1,182 @@
+// Usage: gradr_exec [--profile <profile>] -- <program> [args...]
+//
+// Installs the given seccomp profile, if any, then runs the program
+// and waits for it.  This is how the worker gets a filter in place
+// between `fork` and `exec`.
+//
+// It also tells the worker why the program failed, which the worker
+// can't see for itself when the culprit is a grandchild (e.g., the
+// `a.out` which `make` runs).  If anything under the profile made a
+// denied syscall, this dies of SIGSYS.  Otherwise, if the program
+// failed, and it and its descendants had used up the CPU limit between
+// them, this dies of SIGXCPU.  Otherwise, it exits the same way as the
+// program did.
+
+extern crate libc;
+extern crate libgradr;
+
+use libgradr::seccomp::{Profile, Listener, SIGSYS};
+
+use self::libc::{c_int, c_long, pid_t};
+use std::io;
+use std::io::Command;
+use std::io::process::{ExitStatus, ExitSignal};
+use std::os;
+use std::sync::atomic::{AtomicBool, INIT_ATOMIC_BOOL, SeqCst};
+
+// Same as what shells use for "couldn't run it"
+static SETUP_FAILED: int = 126;
+static EXEC_FAILED: int = 127;
+
+static SIGXCPU: int = 24;
+
+/// Set before whatever made a denied syscall is killed, so it's set
+/// by the time the program could have noticed.
+static BLOCKED: AtomicBool = INIT_ATOMIC_BOOL;
+
+static RUSAGE_CHILDREN: c_int = -1;
+static RLIMIT_CPU: c_int = 0;
+static RLIMIT_CORE: c_int = 4;
+static RLIM_INFINITY: u64 = !0;
+static SIG_DFL: libc::size_t = 0;
+
+#[repr(C)]
+struct Timeval {
+    tv_sec: c_long,
+    tv_usec: c_long
+}
+
+#[repr(C)]
+struct Rusage {
+    ru_utime: Timeval,
+    ru_stime: Timeval,
+    // the rest isn't needed
+    ru_other: [c_long, ..14]
+}
+
+#[repr(C)]
+struct Rlimit {
+    rlim_cur: u64,
+    rlim_max: u64
+}
+
+extern {
+    fn getrusage(who: c_int, usage: *mut Rusage) -> c_int;
+    fn getrlimit(resource: c_int, rlim: *mut Rlimit) -> c_int;
+    fn setrlimit(resource: c_int, rlim: *const Rlimit) -> c_int;
+    fn signal(signum: c_int, handler: libc::size_t) -> libc::size_t;
+}
+
+fn parse_args(args: &[String]) -> Option<(Option<Profile>, &[String])> {
+    if args.len() >= 4 && args[0].as_slice() == "--profile" && args[2].as_slice() == "--" {
+        Profile::from_name(args[1].as_slice()).map(|p| (Some(p), args.slice_from(3)))
+    } else if args.len() >= 2 && args[0].as_slice() == "--" {
+        Some((None, args.slice_from(1)))
+    } else {
+        None
+    }
+}
+
+/// CPU time used by every descendant which has been waited for, in
+/// seconds.  Grandchildren only count once their own parent waits for
+/// them, which `make` and shells do.
+fn children_cpu_seconds() -> u64 {
+    let mut usage = Rusage {
+        ru_utime: Timeval { tv_sec: 0, tv_usec: 0 },
+        ru_stime: Timeval { tv_sec: 0, tv_usec: 0 },
+        ru_other: [0, ..14]
+    };
+    unsafe {
+        getrusage(RUSAGE_CHILDREN, &mut usage);
+    }
+    (usage.ru_utime.tv_sec + usage.ru_stime.tv_sec) as u64
+}
+
+/// The soft CPU limit, which is what sends SIGXCPU, if there is one.
+fn cpu_limit() -> Option<u64> {
+    let mut limit = Rlimit { rlim_cur: 0, rlim_max: 0 };
+    match unsafe { getrlimit(RLIMIT_CPU, &mut limit) } {
+        0 if limit.rlim_cur != RLIM_INFINITY => Some(limit.rlim_cur),
+        _ => None
+    }
+}
+
+/// Dies of `sig`, as the program did, without leaving a core file in
+/// the workspace.  Returns the exit status to use if that didn't work,
+/// as with a signal which doesn't kill.
+fn die_of(sig: int) -> int {
+    let no_core = Rlimit { rlim_cur: 0, rlim_max: 0 };
+    unsafe {
+        setrlimit(RLIMIT_CORE, &no_core);
+        signal(sig as c_int, SIG_DFL);
+        libc::kill(libc::getpid() as pid_t, sig as c_int);
+    }
+    128 + sig
+}
+
+/// Handles denied syscalls for as long as this process is around.
+fn watch(listener: Listener) {
+    spawn(proc() {
+        loop {
+            match listener.next() {
+                Ok(notification) => {
+                    BLOCKED.store(true, SeqCst);
+                    listener.deny(&notification);
+                },
+                // e.g., a signal came in while waiting
+                Err(ref e) if e.kind == io::Interrupted => (),
+                Err(_) => break
+            }
+        }
+    });
+}
+
+/// Returns the exit status to exit with.
+fn run(command: &[String]) -> int {
+    let exit = Command::new(command[0].as_slice()).args(command.slice_from(1)).status();
+    let used_up_cpu = cpu_limit().map(|l| children_cpu_seconds() >= l).unwrap_or(false);
+    match exit {
+        _ if BLOCKED.load(SeqCst) => die_of(SIGSYS),
+        Ok(ExitStatus(0)) => 0,
+        Ok(_) if used_up_cpu => die_of(SIGXCPU),
+        Ok(ExitStatus(status)) => status,
+        Ok(ExitSignal(sig)) => die_of(sig),
+        Err(e) => {
+            let _ = writeln!(&mut io::stderr(), "gradr_exec: could not run {}: {}", command[0], e);
+            EXEC_FAILED
+        }
+    }
+}
+
+#[cfg(not(test))]
+fn main() {
+    let args = os::args();
+
+    let status =
+        match parse_args(args.slice_from(1)) {
+            Some((Some(profile), command)) => {
+                match profile.install() {
+                    Ok(listener) => {
+                        watch(listener);
+                        run(command)
+                    },
+                    Err(e) => {
+                        let _ = writeln!(&mut io::stderr(),
+                                         "gradr_exec: could not install profile: {}", e);
+                        SETUP_FAILED
+                    }
+                }
+            },
+            Some((None, command)) => run(command),
+            None => {
+                let _ = writeln!(&mut io::stderr(),
+                                 "Usage: gradr_exec [--profile <profile>] -- <program> [args...]");
+                SETUP_FAILED
+            }
+        };
+    // not by returning, as that would wait on the task handling
+    // denied syscalls, which never finishes
+    unsafe {
+        libc::exit(status as c_int);
+    }
+}
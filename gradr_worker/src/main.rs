@@ -1,13 +1,112 @@
 extern crate libgradr;
+extern crate time;
 
+use libgradr::builder::{shut_down, kill_running, is_shutting_down};
+use libgradr::builder::github::BUILD_ROOT;
+use libgradr::builder::workspace::{Retention, set_retention, set_worker_id,
+                                   collect_garbage};
 use libgradr::database::postgres_db::PostgresDatabase;
+use libgradr::mirror::set_mirror_budget;
+use libgradr::sandbox::{UidRange, set_uid_range};
 use libgradr::worker::worker_loop_step;
 
+use std::io::signal::{Listener, Interrupt, HangUp, User1};
+use std::os;
+
+/// Environment variable holding the uids jobs may run as, like
+/// `20000-20099`.  Without it, jobs run as the worker.
+static UID_RANGE_VAR: &'static str = "GRADR_UID_RANGE";
+
+/// Environment variable which, if set, makes the worker keep the
+/// workspaces of all failed builds, up to this many megabytes.
+static RETAIN_VAR: &'static str = "GRADR_RETAIN_MB";
+
+/// Environment variable which, if set, makes builds clone through
+/// local mirrors taking up to this many megabytes.
+static MIRROR_VAR: &'static str = "GRADR_MIRROR_MB";
+
+/// Environment variable holding this worker's id, which must be unique
+/// among workers sharing a build directory.
+static WORKER_ID_VAR: &'static str = "GRADR_WORKER_ID";
+
+/// How often leftover workspaces are looked for, in nanoseconds.
+static GC_INTERVAL: u64 = 10 * 60 * 1000 * 1000 * 1000;
+
+fn remove_stale_workspaces() {
+    match collect_garbage(&Path::new(BUILD_ROOT)) {
+        Ok(0) => (),
+        Ok(n) => println!("Removed {} stale workspace(s)", n),
+        Err(e) => println!("Could not look for stale workspaces: {}", e)
+    }
+}
+
 #[cfg(not(test))]
 fn main() {
     let db = PostgresDatabase::new_development().unwrap();
+
+    match os::getenv(UID_RANGE_VAR) {
+        Some(s) => {
+            match UidRange::parse(s.as_slice()) {
+                Some(range) => set_uid_range(range),
+                None => panic!("{} should look like 20000-20099, not {}", UID_RANGE_VAR, s)
+            }
+        },
+        None => ()
+    }
+
+    match os::getenv(WORKER_ID_VAR) {
+        Some(id) => set_worker_id(id.as_slice()).unwrap(),
+        None => ()
+    }
+
+    match os::getenv(RETAIN_VAR) {
+        Some(s) => {
+            match from_str::<u64>(s.as_slice().trim()) {
+                Some(mb) => set_retention(Retention { budget: mb * 1024 * 1024, all: true }),
+                None => panic!("{} should be a number of megabytes, not {}", RETAIN_VAR, s)
+            }
+        },
+        None => ()
+    }
+
+    match os::getenv(MIRROR_VAR) {
+        Some(s) => {
+            match from_str::<u64>(s.as_slice().trim()) {
+                Some(mb) => set_mirror_budget(mb * 1024 * 1024),
+                None => panic!("{} should be a number of megabytes, not {}", MIRROR_VAR, s)
+            }
+        },
+        None => ()
+    }
+
+    // On ^C or hangup, kill whatever is building, along with anything
+    // it forked, rather than leaving it all running.  SIGUSR1 cancels
+    // the build in progress the same way, but the worker carries on;
+    // the build is recorded as cancelled.
+    let mut listener = Listener::new();
+    listener.register(Interrupt).unwrap();
+    listener.register(HangUp).unwrap();
+    listener.register(User1).unwrap();
+    spawn(proc() {
+        loop {
+            match listener.rx.recv() {
+                User1 => kill_running(),
+                _ => {
+                    shut_down();
+                    break;
+                }
+            }
+        }
+    });
     
-    loop {
+    remove_stale_workspaces();
+    let mut last_gc = time::precise_time_ns();
+
+    while !is_shutting_down() {
         worker_loop_step(&db);
+        if time::precise_time_ns() - last_gc > GC_INTERVAL {
+            remove_stale_workspaces();
+            last_gc = time::precise_time_ns();
+        }
     }
 }
@@ -0,0 +1,875 @@
+// Per-assignment configuration.  Each assignment may have a JSON file
+// named after its `git_project_name` in `CONFIG_DIR`, like so:
+//
+// {
+//     "timeouts": { "env": 300, "build": 120, "test": 60 },
+//     "limits": { "cpu_seconds": 10, "memory_mb": 512, "file_size_mb": 16,
+//                 "open_files": 64, "processes": 32 },
+//     "sandbox": { "toolchain": ["/usr", "/bin", "/lib", "/lib64"] },
+//     "seccomp": "pure_computation",
+//     "retain_on_failure": true,
+//     "recipe": "make",
+//     "files": "hw1",
+//     "commands": { "env": [["pip", "install", "-r", "requirements.txt"]],
+//                   "build": [["make", "-s", "build"]],
+//                   "test": ["make", "-s", "test"] },
+//     "parser": { "format": "junit", "reports": "reports/*.xml" },
+//     "scoring": { "default_weight": 1, "weights": { "test_io": 5 } },
+//     "io_tests": { "cases": "hw1_cases", "program": ["./a.out"],
+//                   "compare": "float", "tolerance": 0.001, "timeout": 2 },
+//     "differential": { "reference": "hw1_reference", "generator": ["python3", "gen.py"],
+//                       "program": ["./a.out"], "runs": 20, "seed": 1234,
+//                       "compare": "whitespace", "timeout": 2 },
+//     "memcheck": { "tool": "sanitizers",
+//                   "build": [["gcc", "-g", "-fsanitize=address,undefined", "main.c"]],
+//                   "command": ["./a.out"] },
+//     "stages": [
+//         { "name": "lint", "commands": [["cppcheck", "--error-exitcode=1", "."]],
+//           "timeout": 30, "on_failure": "penalty", "penalty": 0.1 },
+//         { "name": "build" },
+//         { "name": "test" },
+//         { "name": "memcheck", "timeout": 600, "on_failure": "penalty", "penalty": 0.2 },
+//         { "name": "benchmark", "commands": [["make", "-s", "bench"]],
+//           "on_failure": "continue" }
+//     ]
+// }
+//
+// Timeouts are in seconds.  Anything left out gets the global default.
+// The sandbox is off unless `sandbox` is present; if it's present
+// without a `toolchain`, the default toolchain is made visible.
+// `seccomp` is one of "pure_computation", "file_io" or "networking";
+// without it, the test command's syscalls aren't filtered at all.
+// With `retain_on_failure`, the workspaces of failed builds are kept
+// around for inspection, within the worker's retention budget.
+//
+// `recipe` is one of "make", "cargo", "gradle", "pytest" or "cmake",
+// and gives the default commands and parser; it's "make" if left out.
+// `files` is a directory under `CONFIG_DIR`, everything in which is
+// copied over the submission, subdirectories and hidden files included;
+// without it, the "make" recipe copies `DEFAULT_MAKEFILE` over as
+// `makefile`, and the others copy nothing.  These files are protected:
+// submissions which replace or change them are flagged in their
+// results.  Commands are given as argument lists, and run in the
+// workspace.  Any left out are the recipe's.  `format` is one of
+// "line", "strict_line", "tap", "junit" or "libtest"; "junit" needs
+// `reports`, a pattern relative to the workspace.  Without `scoring`,
+// every test is worth one point.
+//
+// `stages` run in order after environment setup; without it, there's
+// a build stage, then a test stage.  "build" and "test" are the
+// built-in stages, whose `commands` and `timeout`, if given, replace
+// those from above; the test stage takes a single command.  Any other
+// stage needs `commands`, and runs like the test command does, under
+// its seccomp profile and, unless it has its own, its timeout.
+// `on_failure` is "abort" (the default), "continue" or "penalty",
+// which takes `penalty`, a fraction of the possible score, off of the
+// final score.
+//
+// With `io_tests`, the test stage runs `program` (by default, `./a.out`)
+// once for each `name.in` in `cases`, a directory under `CONFIG_DIR`,
+// instead of running the test command.  Its output has to match
+// `name.out`, per `compare`: "exact" (the default), "whitespace",
+// "float", which takes `tolerance` (by default, 1e-6), or "regex".
+// `timeout` is per case, in seconds.
+//
+// With `differential`, the test stage also runs `program` on inputs
+// made by `generator`, and compares its output with that of the
+// instructor's solution in `reference`, a directory under `CONFIG_DIR`.
+// The reference is built with the build commands from above, and the
+// generator runs in the reference build, given a seed as its last
+// argument.  There are `runs` runs (by default, 10); without `seed`,
+// each build gets a random one.  `compare` and `timeout` are as with
+// `io_tests`.
+//
+// With `memcheck`, the "memcheck" stage runs `command` (by default, the
+// test command) under `tool`, "valgrind" or "sanitizers", after running
+// `build`, if given, to make a build for it.  The stage takes no
+// `commands`, and fails if anything is found; if it's not listed in
+// `stages`, it runs last, and its failure doesn't count against the
+// score.  Its timeout defaults to ten minutes.
+
+extern crate serialize;
+
+use self::serialize::json;
+use std::collections::HashMap;
+use std::default::Default;
+use std::io::{File, IoResult, IoError, InvalidInput, FileNotFound};
+
+use builder::{Timeouts, ResourceLimits, StageCommands, Overlay, CommandLine, Rubric,
+              TestOutputParser, LineParser, Stage, StagePlan, FailurePolicy};
+use builder::FailurePolicy::{Abort, Continue, Penalty};
+use builder::Stage::{Build, Test};
+use builder::Overlay::{Makefile, Files};
+use builder::iotest::{IoTests, Comparison, DEFAULT_CASE_TIMEOUT};
+use builder::iotest::Comparison::{Exact, Whitespace, Float, Pattern};
+use builder::junit::JUnitParser;
+use builder::libtest::LibTestParser;
+use builder::recipe::Recipe;
+use differential::{Differential, DEFAULT_RUNS};
+use builder::tap::TapParser;
+use memcheck::{MemCheck, Tool, DEFAULT_MEMCHECK_TIMEOUT};
+use sandbox::Sandbox;
+use seccomp::Profile;
+
+use self::ParserSpec::{Line, StrictLine, Tap, JUnit, LibTest};
+
+/// Where assignment configuration files live, relative to the
+/// worker's working directory.
+pub static CONFIG_DIR: &'static str = "assignments";
+
+/// Copied over as `makefile` for assignments without `files`.
+pub static DEFAULT_MAKEFILE: &'static str = "test/makefile";
+
+#[deriving(Decodable)]
+struct TimeoutsSpec {
+    env: Option<u64>,
+    build: Option<u64>,
+    test: Option<u64>
+}
+
+#[deriving(Decodable)]
+struct LimitsSpec {
+    cpu_seconds: Option<u64>,
+    memory_mb: Option<u64>,
+    file_size_mb: Option<u64>,
+    open_files: Option<u64>,
+    processes: Option<u64>
+}
+
+#[deriving(Decodable)]
+struct SandboxSpec {
+    enabled: Option<bool>,
+    toolchain: Option<Vec<String>>
+}
+
+#[deriving(Decodable)]
+struct CommandsSpec {
+    env: Option<Vec<Vec<String>>>,
+    build: Option<Vec<Vec<String>>>,
+    test: Option<Vec<String>>
+}
+
+#[deriving(Decodable)]
+struct ParserConfigSpec {
+    format: String,
+    reports: Option<String>
+}
+
+#[deriving(Decodable)]
+struct ScoringSpec {
+    default_weight: Option<f64>,
+    weights: Option<HashMap<String, f64>>
+}
+
+#[deriving(Decodable)]
+struct StageSpec {
+    name: String,
+    commands: Option<Vec<Vec<String>>>,
+    timeout: Option<u64>,
+    on_failure: Option<String>,
+    penalty: Option<f64>
+}
+
+#[deriving(Decodable)]
+struct IoTestsSpec {
+    cases: String,
+    program: Option<Vec<String>>,
+    compare: Option<String>,
+    tolerance: Option<f64>,
+    timeout: Option<u64>
+}
+
+#[deriving(Decodable)]
+struct DifferentialSpec {
+    reference: String,
+    generator: Vec<String>,
+    program: Option<Vec<String>>,
+    runs: Option<uint>,
+    seed: Option<u32>,
+    compare: Option<String>,
+    tolerance: Option<f64>,
+    timeout: Option<u64>
+}
+
+#[deriving(Decodable)]
+struct MemCheckSpec {
+    tool: String,
+    build: Option<Vec<Vec<String>>>,
+    command: Option<Vec<String>>
+}
+
+#[deriving(Decodable)]
+struct ConfigSpec {
+    timeouts: Option<TimeoutsSpec>,
+    limits: Option<LimitsSpec>,
+    sandbox: Option<SandboxSpec>,
+    seccomp: Option<String>,
+    retain_on_failure: Option<bool>,
+    recipe: Option<String>,
+    files: Option<String>,
+    commands: Option<CommandsSpec>,
+    parser: Option<ParserConfigSpec>,
+    scoring: Option<ScoringSpec>,
+    stages: Option<Vec<StageSpec>>,
+    io_tests: Option<IoTestsSpec>,
+    differential: Option<DifferentialSpec>,
+    memcheck: Option<MemCheckSpec>
+}
+
+/// Which `TestOutputParser` to use.
+#[deriving(Show, PartialEq, Clone)]
+pub enum ParserSpec {
+    Line,
+    StrictLine,
+    Tap,
+    /// With the pattern reports are found by
+    JUnit(String),
+    LibTest
+}
+
+impl ParserSpec {
+    /// Makes the parser for a build in `build_dir`.
+    pub fn to_parser(&self, build_dir: &Path) -> Box<TestOutputParser + 'static> {
+        match *self {
+            Line => (box LineParser::new()) as Box<TestOutputParser + 'static>,
+            StrictLine => (box LineParser::strict()) as Box<TestOutputParser + 'static>,
+            Tap => (box TapParser) as Box<TestOutputParser + 'static>,
+            LibTest => (box LibTestParser) as Box<TestOutputParser + 'static>,
+            JUnit(ref pattern) => {
+                (box JUnitParser::from_pattern(build_dir, pattern.as_slice()))
+                    as Box<TestOutputParser + 'static>
+            }
+        }
+    }
+}
+
+#[deriving(Show, Clone)]
+pub struct AssignmentConfig {
+    pub timeouts: Timeouts,
+    pub limits: ResourceLimits,
+    pub sandbox: Option<Sandbox>,
+    pub seccomp: Option<Profile>,
+    pub retain_on_failure: bool,
+    pub overlay: Overlay,
+    pub commands: StageCommands,
+    pub parser: ParserSpec,
+    pub rubric: Rubric,
+    pub stages: Vec<StagePlan>,
+    pub io_tests: Option<IoTests>,
+    pub differential: Option<Differential>,
+    pub memcheck: Option<MemCheck>
+}
+
+impl Default for AssignmentConfig {
+    fn default() -> AssignmentConfig {
+        AssignmentConfig {
+            timeouts: Default::default(),
+            limits: Default::default(),
+            sandbox: None,
+            seccomp: None,
+            retain_on_failure: false,
+            overlay: Makefile(Path::new(DEFAULT_MAKEFILE)),
+            commands: Default::default(),
+            parser: Line,
+            rubric: Rubric::uniform(),
+            stages: StagePlan::defaults(),
+            io_tests: None,
+            differential: None,
+            memcheck: None
+        }
+    }
+}
+
+fn invalid(desc: &'static str, detail: String) -> IoError {
+    IoError {
+        kind: InvalidInput,
+        desc: desc,
+        detail: Some(detail)
+    }
+}
+
+fn command_line(argv: &Vec<String>) -> IoResult<CommandLine> {
+    CommandLine::from_argv(argv.as_slice()).ok_or(
+        invalid("Empty command", "commands need at least a program".to_string()))
+}
+
+fn command_lines(argvs: &Vec<Vec<String>>) -> IoResult<Vec<CommandLine>> {
+    let mut lines = Vec::new();
+    for argv in argvs.iter() {
+        lines.push(try!(command_line(argv)));
+    }
+    Ok(lines)
+}
+
+fn failure_policy(spec: &StageSpec) -> IoResult<FailurePolicy> {
+    match (spec.on_failure.as_ref().map(|p| p.as_slice()), spec.penalty) {
+        (None, _) | (Some("abort"), _) => Ok(Abort),
+        (Some("continue"), _) => Ok(Continue),
+        (Some("penalty"), Some(p)) if p >= 0.0 && p <= 1.0 => Ok(Penalty(p)),
+        (Some("penalty"), _) =>
+            Err(invalid("Penalty needs to be between 0 and 1", spec.name.clone())),
+        (Some(other), _) => Err(invalid("Unknown failure policy", other.to_string()))
+    }
+}
+
+/// Stages are added to `config` as they're read, as the built-in ones
+/// can change its commands and timeouts.  With `memcheck`, the memory
+/// checking stage is built in, too.
+fn stage_plans(specs: &Vec<StageSpec>, config: &mut AssignmentConfig,
+               memcheck: bool) -> IoResult<Vec<StagePlan>> {
+    let mut plans: Vec<StagePlan> = Vec::new();
+    for spec in specs.iter() {
+        let stage = try!(Stage::from_name(spec.name.as_slice()).ok_or(
+            invalid("Bad stage name", spec.name.clone())));
+        if plans.iter().any(|p| p.stage == stage) {
+            return Err(invalid("Stage listed twice", spec.name.clone()));
+        }
+        let timeout = spec.timeout.map(|t| t * 1000);
+        let is_memcheck = memcheck && stage == MemCheck::stage();
+
+        let mut plan = StagePlan::built_in(stage.clone());
+        plan.on_failure = try!(failure_policy(spec));
+        match (stage, &spec.commands) {
+            (Build, commands) => {
+                match *commands {
+                    Some(ref c) => config.commands.build = try!(command_lines(c)),
+                    None => ()
+                }
+                config.timeouts.build = timeout.or(config.timeouts.build);
+            },
+            (Test, commands) => {
+                match *commands {
+                    Some(ref c) if c.len() == 1 => config.commands.test = try!(command_line(&c[0])),
+                    Some(_) => return Err(invalid("Test stage takes one command", spec.name.clone())),
+                    None => ()
+                }
+                config.timeouts.test = timeout.or(config.timeouts.test);
+            },
+            (_, &None) if is_memcheck => {
+                plan.timeout = timeout.or(Some(DEFAULT_MEMCHECK_TIMEOUT));
+            },
+            (_, &Some(_)) if is_memcheck =>
+                return Err(invalid("Memory checking stage takes no commands", spec.name.clone())),
+            (_, &Some(ref c)) if !c.is_empty() => {
+                plan.commands = try!(command_lines(c));
+                plan.timeout = timeout;
+            },
+            (_, _) => return Err(invalid("Stage has no commands", spec.name.clone()))
+        }
+        plans.push(plan);
+    }
+
+    // only now is the test stage's timeout final
+    for plan in plans.iter_mut() {
+        if !plan.commands.is_empty() && plan.timeout.is_none() {
+            plan.timeout = config.timeouts.test;
+        }
+    }
+    Ok(plans)
+}
+
+fn parser_spec(spec: &ParserConfigSpec) -> IoResult<ParserSpec> {
+    match (spec.format.as_slice(), &spec.reports) {
+        ("line", _) => Ok(Line),
+        ("strict_line", _) => Ok(StrictLine),
+        ("tap", _) => Ok(Tap),
+        ("libtest", _) => Ok(LibTest),
+        ("junit", &Some(ref reports)) => Ok(JUnit(reports.clone())),
+        ("junit", &None) => Err(invalid("JUnit parser needs `reports`", spec.format.clone())),
+        _ => Err(invalid("Unknown output format", spec.format.clone()))
+    }
+}
+
+fn comparison(compare: &Option<String>, tolerance: Option<f64>) -> IoResult<Comparison> {
+    match (compare.as_ref().map(|c| c.as_slice()), tolerance) {
+        (None, _) | (Some("exact"), _) => Ok(Exact),
+        (Some("whitespace"), _) => Ok(Whitespace),
+        (Some("float"), None) => Ok(Float(1e-6)),
+        (Some("float"), Some(t)) if t >= 0.0 => Ok(Float(t)),
+        (Some("float"), Some(t)) => Err(invalid("Tolerance can't be negative", t.to_string())),
+        (Some("regex"), _) => Ok(Pattern),
+        (Some(other), _) => Err(invalid("Unknown comparison", other.to_string()))
+    }
+}
+
+fn program(argv: &Option<Vec<String>>) -> IoResult<CommandLine> {
+    match *argv {
+        Some(ref argv) => command_line(argv),
+        None => Ok(CommandLine::new("./a.out", &[]))
+    }
+}
+
+fn io_tests(spec: &IoTestsSpec) -> IoResult<IoTests> {
+    Ok(IoTests {
+        cases: Path::new(spec.cases.as_slice()),
+        program: try!(program(&spec.program)),
+        comparison: try!(comparison(&spec.compare, spec.tolerance)),
+        timeout: seconds_to_millis(Some(DEFAULT_CASE_TIMEOUT), spec.timeout)
+    })
+}
+
+fn differential(spec: &DifferentialSpec) -> IoResult<Differential> {
+    let runs = spec.runs.unwrap_or(DEFAULT_RUNS);
+    if runs == 0 {
+        return Err(invalid("Differential testing needs at least one run",
+                           spec.reference.clone()));
+    }
+    Ok(Differential {
+        reference: Path::new(spec.reference.as_slice()),
+        generator: try!(command_line(&spec.generator)),
+        program: try!(program(&spec.program)),
+        runs: runs,
+        seed: spec.seed,
+        comparison: try!(comparison(&spec.compare, spec.tolerance)),
+        timeout: seconds_to_millis(Some(DEFAULT_CASE_TIMEOUT), spec.timeout)
+    })
+}
+
+fn memcheck(spec: &MemCheckSpec, test: &CommandLine) -> IoResult<MemCheck> {
+    Ok(MemCheck {
+        tool: try!(Tool::from_name(spec.tool.as_slice()).ok_or(
+            invalid("Unknown memory checking tool", spec.tool.clone()))),
+        build: match spec.build {
+            Some(ref build) => try!(command_lines(build)),
+            None => Vec::new()
+        },
+        command: match spec.command {
+            Some(ref command) => try!(command_line(command)),
+            None => test.clone()
+        }
+    })
+}
+
+fn megabytes(mb: Option<u64>) -> Option<u64> {
+    mb.map(|m| m * 1024 * 1024)
+}
+
+fn seconds_to_millis(defaults: Option<u64>, secs: Option<u64>) -> Option<u64> {
+    secs.map(|s| s * 1000).or(defaults)
+}
+
+impl AssignmentConfig {
+    pub fn from_json(s: &str) -> IoResult<AssignmentConfig> {
+        let spec: ConfigSpec = try!(json::decode(s).map_err(|e| {
+            IoError {
+                kind: InvalidInput,
+                desc: "Malformed assignment configuration",
+                detail: Some(e.to_string())
+            }
+        }));
+
+        let mut config: AssignmentConfig = Default::default();
+        match spec.timeouts {
+            Some(t) => {
+                config.timeouts = Timeouts {
+                    env: seconds_to_millis(config.timeouts.env, t.env),
+                    build: seconds_to_millis(config.timeouts.build, t.build),
+                    test: seconds_to_millis(config.timeouts.test, t.test)
+                };
+            },
+            None => ()
+        }
+        match spec.limits {
+            Some(l) => {
+                let d = config.limits.clone();
+                config.limits = ResourceLimits {
+                    cpu_seconds: l.cpu_seconds.or(d.cpu_seconds),
+                    address_space: megabytes(l.memory_mb).or(d.address_space),
+                    file_size: megabytes(l.file_size_mb).or(d.file_size),
+                    open_files: l.open_files.or(d.open_files),
+                    processes: l.processes.or(d.processes)
+                };
+            },
+            None => ()
+        }
+        match spec.sandbox {
+            Some(ref sb) if sb.enabled != Some(false) => {
+                let mut sandbox: Sandbox = Default::default();
+                match sb.toolchain {
+                    Some(ref paths) => {
+                        sandbox.toolchain =
+                            paths.iter().map(|p| Path::new(p.as_slice())).collect();
+                    },
+                    None => ()
+                }
+                config.sandbox = Some(sandbox);
+            },
+            _ => ()
+        }
+        match spec.seccomp {
+            Some(ref name) => {
+                config.seccomp = Some(try!(Profile::from_name(name.as_slice()).ok_or(
+                    invalid("Unknown seccomp profile", name.clone()))));
+            },
+            None => ()
+        }
+        config.retain_on_failure = spec.retain_on_failure.unwrap_or(false);
+        match spec.recipe {
+            Some(ref name) => {
+                let recipe = try!(Recipe::from_name(name.as_slice()).ok_or(
+                    invalid("Unknown recipe", name.clone())));
+                config.commands = recipe.commands();
+                config.parser = recipe.parser();
+                config.overlay = recipe.overlay();
+            },
+            None => ()
+        }
+        match spec.files {
+            Some(ref files) => config.overlay = Files(Path::new(files.as_slice())),
+            None => ()
+        }
+        match spec.commands {
+            Some(ref c) => {
+                let d = config.commands.clone();
+                config.commands = StageCommands {
+                    env: match c.env {
+                        Some(ref env) => try!(command_lines(env)),
+                        None => d.env
+                    },
+                    build: match c.build {
+                        Some(ref build) => try!(command_lines(build)),
+                        None => d.build
+                    },
+                    test: match c.test {
+                        Some(ref test) => try!(command_line(test)),
+                        None => d.test
+                    }
+                };
+            },
+            None => ()
+        }
+        match spec.parser {
+            Some(ref p) => config.parser = try!(parser_spec(p)),
+            None => ()
+        }
+        match spec.scoring {
+            Some(ref s) => {
+                config.rubric = Rubric {
+                    weights: s.weights.clone().unwrap_or_else(|| HashMap::new()),
+                    default_weight: s.default_weight.unwrap_or(1.0)
+                };
+            },
+            None => ()
+        }
+        match spec.stages {
+            Some(ref stages) => {
+                let plans = try!(stage_plans(stages, &mut config, spec.memcheck.is_some()));
+                config.stages = plans;
+            },
+            None => ()
+        }
+        match spec.io_tests {
+            Some(ref t) => config.io_tests = Some(try!(io_tests(t))),
+            None => ()
+        }
+        match spec.differential {
+            Some(ref d) => config.differential = Some(try!(differential(d))),
+            None => ()
+        }
+        match spec.memcheck {
+            Some(ref m) => {
+                let check = try!(memcheck(m, &config.commands.test));
+                if !config.stages.iter().any(|p| p.stage == MemCheck::stage()) {
+                    config.stages.push(StagePlan {
+                        stage: MemCheck::stage(),
+                        commands: Vec::new(),
+                        timeout: Some(DEFAULT_MEMCHECK_TIMEOUT),
+                        on_failure: Continue
+                    });
+                }
+                config.memcheck = Some(check);
+            },
+            None => ()
+        }
+        Ok(config)
+    }
+
+    /// Loads the configuration for the given assignment from `dir`.
+    /// An assignment without a configuration file gets the defaults.
+    /// `files`, I/O test `cases` and the differential `reference` are
+    /// relative to `dir`.
+    pub fn load(dir: &Path, assignment: &str) -> IoResult<AssignmentConfig> {
+        let path = dir.join(format!("{}.json", assignment));
+        match File::open(&path).read_to_string() {
+            Ok(s) => {
+                let mut config = try!(AssignmentConfig::from_json(s.as_slice()));
+                let overlay =
+                    match config.overlay {
+                        Files(ref files) => Files(dir.join(files)),
+                        ref other => other.clone()
+                    };
+                config.overlay = overlay;
+                match config.io_tests {
+                    Some(ref mut tests) => {
+                        let cases = dir.join(&tests.cases);
+                        tests.cases = cases;
+                    },
+                    None => ()
+                }
+                match config.differential {
+                    Some(ref mut diff) => {
+                        let reference = dir.join(&diff.reference);
+                        diff.reference = reference;
+                    },
+                    None => ()
+                }
+                Ok(config)
+            },
+            Err(ref e) if e.kind == FileNotFound => Ok(Default::default()),
+            Err(e) => Err(e)
+        }
+    }
+
+    /// Like `load`, from where the worker keeps configurations.  A
+    /// broken configuration is an error, rather than building with
+    /// the defaults, which wouldn't be what the instructor asked for.
+    pub fn resolve(assignment: &str) -> IoResult<AssignmentConfig> {
+        AssignmentConfig::load(&Path::new(CONFIG_DIR), assignment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::default::Default;
+
+    use super::{AssignmentConfig, DEFAULT_MAKEFILE};
+    use super::ParserSpec::{Line, JUnit, LibTest};
+    use builder::{Timeouts, ResourceLimits, StageCommands, CommandLine};
+    use builder::Overlay::{Makefile, Files, Nothing};
+    use builder::FailurePolicy::{Abort, Continue, Penalty};
+    use builder::Stage::{Named, Test};
+    use builder::iotest::DEFAULT_CASE_TIMEOUT;
+    use builder::iotest::Comparison::{Exact, Whitespace, Float};
+    use differential::DEFAULT_RUNS;
+    use memcheck::DEFAULT_MEMCHECK_TIMEOUT;
+    use memcheck::Tool::{Valgrind, Sanitizers};
+    use seccomp::Profile::FileIo;
+
+    use util::MessagingUnwrapper;
+
+    #[test]
+    fn partial_timeouts() {
+        let config = AssignmentConfig::from_json(
+            r#"{ "timeouts": { "test": 5 } }"#);
+        assert!(config.is_ok());
+        let defaults: Timeouts = Default::default();
+        let t = config.unwrap_msg(line!()).timeouts;
+        assert_eq!(t.test, Some(5000));
+        assert_eq!(t.build, defaults.build);
+        assert_eq!(t.env, defaults.env);
+    }
+
+    #[test]
+    fn limits() {
+        let config = AssignmentConfig::from_json(
+            r#"{ "limits": { "cpu_seconds": 10, "memory_mb": 512 } }"#);
+        assert!(config.is_ok());
+        let defaults: ResourceLimits = Default::default();
+        let l = config.unwrap_msg(line!()).limits;
+        assert_eq!(l.cpu_seconds, Some(10));
+        assert_eq!(l.address_space, Some(512 * 1024 * 1024));
+        assert_eq!(l.file_size, defaults.file_size);
+    }
+
+    #[test]
+    fn sandbox() {
+        let config = AssignmentConfig::from_json(
+            r#"{ "sandbox": { "toolchain": ["/usr"] } }"#);
+        assert!(config.is_ok());
+        let sandbox = config.unwrap_msg(line!()).sandbox;
+        assert!(sandbox.is_some());
+        assert_eq!(sandbox.unwrap_msg(line!()).toolchain, vec!(Path::new("/usr")));
+
+        let config = AssignmentConfig::from_json(r#"{ "sandbox": { "enabled": false } }"#);
+        assert!(config.unwrap_msg(line!()).sandbox.is_none());
+    }
+
+    #[test]
+    fn seccomp() {
+        let config = AssignmentConfig::from_json(r#"{ "seccomp": "file_io" }"#);
+        assert_eq!(config.unwrap_msg(line!()).seccomp, Some(FileIo));
+
+        assert!(AssignmentConfig::from_json(r#"{ "seccomp": "anything" }"#).is_err());
+    }
+
+    #[test]
+    fn commands() {
+        let config = AssignmentConfig::from_json(
+            r#"{ "commands": { "build": [["cargo", "build"]], "test": ["cargo", "test"] } }"#);
+        let c = config.unwrap_msg(line!()).commands;
+        let defaults: StageCommands = Default::default();
+        assert_eq!(c.env, defaults.env);
+        assert_eq!(c.build, vec!(CommandLine::new("cargo", &["build"])));
+        assert_eq!(c.test, CommandLine::new("cargo", &["test"]));
+
+        assert!(AssignmentConfig::from_json(r#"{ "commands": { "test": [] } }"#).is_err());
+    }
+
+    #[test]
+    fn overlay() {
+        let config = AssignmentConfig::from_json("{}");
+        assert_eq!(config.unwrap_msg(line!()).overlay, Makefile(Path::new(DEFAULT_MAKEFILE)));
+
+        let config = AssignmentConfig::from_json(r#"{ "files": "hw1" }"#);
+        assert_eq!(config.unwrap_msg(line!()).overlay, Files(Path::new("hw1")));
+    }
+
+    #[test]
+    fn parser() {
+        let config = AssignmentConfig::from_json("{}");
+        assert_eq!(config.unwrap_msg(line!()).parser, Line);
+
+        let config = AssignmentConfig::from_json(
+            r#"{ "parser": { "format": "junit", "reports": "reports/*.xml" } }"#);
+        assert_eq!(config.unwrap_msg(line!()).parser, JUnit("reports/*.xml".to_string()));
+
+        assert!(AssignmentConfig::from_json(r#"{ "parser": { "format": "junit" } }"#).is_err());
+        assert!(AssignmentConfig::from_json(r#"{ "parser": { "format": "xunit" } }"#).is_err());
+    }
+
+    #[test]
+    fn recipe() {
+        let config = AssignmentConfig::from_json(
+            r#"{ "recipe": "cargo", "commands": { "test": ["cargo", "test", "--release"] } }"#)
+            .unwrap_msg(line!());
+        assert_eq!(config.parser, LibTest);
+        assert_eq!(config.overlay, Nothing);
+        assert_eq!(config.commands.build,
+                   vec!(CommandLine::new("cargo", &["test", "--offline", "--no-run"])));
+        assert_eq!(config.commands.test, CommandLine::new("cargo", &["test", "--release"]));
+
+        assert!(AssignmentConfig::from_json(r#"{ "recipe": "ant" }"#).is_err());
+    }
+
+    #[test]
+    fn stages() {
+        let config = AssignmentConfig::from_json(r#"{ "stages": [
+            { "name": "lint", "commands": [["lint", "."]], "timeout": 5,
+              "on_failure": "penalty", "penalty": 0.25 },
+            { "name": "build", "commands": [["make", "all"]] },
+            { "name": "style", "commands": [["style", "."]] },
+            { "name": "test", "timeout": 7 } ] }"#).unwrap_msg(line!());
+        assert_eq!(config.stages.len(), 4);
+        assert_eq!(config.stages[0].stage, Named("lint".to_string()));
+        assert_eq!(config.stages[0].timeout, Some(5000));
+        assert_eq!(config.stages[0].on_failure, Penalty(0.25));
+        assert_eq!(config.stages[1].on_failure, Abort);
+        assert_eq!(config.commands.build, vec!(CommandLine::new("make", &["all"])));
+        assert_eq!(config.timeouts.test, Some(7000));
+        assert_eq!(config.stages[2].timeout, Some(7000));
+
+        for bad in [r#"{ "stages": [{ "name": "lint" }] }"#,
+                    r#"{ "stages": [{ "name": "env" }] }"#,
+                    r#"{ "stages": [{ "name": "test" }, { "name": "test" }] }"#,
+                    r#"{ "stages": [{ "name": "build", "on_failure": "penalty" }] }"#].iter() {
+            assert!(AssignmentConfig::from_json(*bad).is_err());
+        }
+    }
+
+    #[test]
+    fn io_tests() {
+        let config = AssignmentConfig::from_json(
+            r#"{ "io_tests": { "cases": "hw1_cases", "compare": "float", "timeout": 2 } }"#)
+            .unwrap_msg(line!());
+        let tests = config.io_tests.unwrap_msg(line!());
+        assert_eq!(tests.cases, Path::new("hw1_cases"));
+        assert_eq!(tests.program, CommandLine::new("./a.out", &[]));
+        assert_eq!(tests.comparison, Float(1e-6));
+        assert_eq!(tests.timeout, Some(2000));
+
+        let config = AssignmentConfig::from_json(
+            r#"{ "io_tests": { "cases": "c", "program": ["python3", "main.py"] } }"#)
+            .unwrap_msg(line!());
+        let tests = config.io_tests.unwrap_msg(line!());
+        assert_eq!(tests.comparison, Exact);
+        assert_eq!(tests.timeout, Some(DEFAULT_CASE_TIMEOUT));
+
+        assert!(AssignmentConfig::from_json(
+            r#"{ "io_tests": { "cases": "c", "compare": "fuzzy" } }"#).is_err());
+        assert!(AssignmentConfig::from_json(
+            r#"{ "io_tests": { "cases": "c", "compare": "float", "tolerance": -1 } }"#).is_err());
+    }
+
+    #[test]
+    fn differential() {
+        let config = AssignmentConfig::from_json(
+            r#"{ "differential": { "reference": "hw1_reference",
+                                   "generator": ["python3", "gen.py"],
+                                   "seed": 1234, "compare": "whitespace" } }"#)
+            .unwrap_msg(line!());
+        let diff = config.differential.unwrap_msg(line!());
+        assert_eq!(diff.reference, Path::new("hw1_reference"));
+        assert_eq!(diff.generator, CommandLine::new("python3", &["gen.py"]));
+        assert_eq!(diff.program, CommandLine::new("./a.out", &[]));
+        assert_eq!(diff.runs, DEFAULT_RUNS);
+        assert_eq!(diff.seed, Some(1234));
+        assert_eq!(diff.comparison, Whitespace);
+
+        assert!(AssignmentConfig::from_json(
+            r#"{ "differential": { "reference": "r", "generator": [] } }"#).is_err());
+        assert!(AssignmentConfig::from_json(
+            r#"{ "differential": { "reference": "r", "generator": ["gen"], "runs": 0 } }"#)
+                .is_err());
+    }
+
+    #[test]
+    fn memcheck() {
+        let config = AssignmentConfig::from_json(
+            r#"{ "memcheck": { "tool": "valgrind" },
+                 "stages": [{ "name": "test", "commands": [["./run_tests"]] }] }"#)
+            .unwrap_msg(line!());
+        let check = config.memcheck.unwrap_msg(line!());
+        assert_eq!(check.tool, Valgrind);
+        assert!(check.build.is_empty());
+        assert_eq!(check.command, CommandLine::new("./run_tests", &[]));
+        assert_eq!(config.stages.len(), 2);
+        assert_eq!(config.stages[0].stage, Test);
+        assert_eq!(config.stages[1].stage, Named("memcheck".to_string()));
+        assert_eq!(config.stages[1].timeout, Some(DEFAULT_MEMCHECK_TIMEOUT));
+        assert_eq!(config.stages[1].on_failure, Continue);
+
+        let config = AssignmentConfig::from_json(
+            r#"{ "memcheck": { "tool": "sanitizers", "build": [["gcc", "main.c"]],
+                               "command": ["./a.out"] },
+                 "stages": [{ "name": "memcheck", "on_failure": "penalty", "penalty": 0.5 },
+                            { "name": "test" }] }"#)
+            .unwrap_msg(line!());
+        assert_eq!(config.memcheck.unwrap_msg(line!()).tool, Sanitizers);
+        assert_eq!(config.stages.len(), 2);
+        assert_eq!(config.stages[0].on_failure, Penalty(0.5));
+
+        for bad in [r#"{ "memcheck": { "tool": "purify" } }"#,
+                    r#"{ "stages": [{ "name": "memcheck" }] }"#,
+                    r#"{ "memcheck": { "tool": "valgrind" },
+                         "stages": [{ "name": "memcheck", "commands": [["true"]] }] }"#].iter() {
+            assert!(AssignmentConfig::from_json(*bad).is_err());
+        }
+    }
+
+    #[test]
+    fn scoring() {
+        let config = AssignmentConfig::from_json(
+            r#"{ "scoring": { "default_weight": 2, "weights": { "test1": 5 } } }"#);
+        let rubric = config.unwrap_msg(line!()).rubric;
+        assert_eq!(rubric.default_weight, 2.0);
+        assert_eq!(rubric.weights.get(&"test1".to_string()), Some(&5.0));
+    }
+
+    #[test]
+    fn empty_config() {
+        let config = AssignmentConfig::from_json("{}");
+        assert!(config.is_ok());
+        assert_eq!(config.unwrap_msg(line!()).timeouts, Default::default());
+    }
+
+    #[test]
+    fn malformed_config() {
+        assert!(AssignmentConfig::from_json(r#"{ "timeouts": 5 }"#).is_err());
+    }
+
+    #[test]
+    fn missing_config_file() {
+        let config = AssignmentConfig::load(&Path::new("test"), "no_such_assignment");
+        assert!(config.is_ok());
+    }
+}
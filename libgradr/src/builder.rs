@@ -1,42 +1,191 @@
-// Portion that builds and runs tests.  Assumes that this can be
-// done via a call to `make build` and `make test`.  Compiler failure
-// is assumed to be communicated by return value.  Tests are assumed
-// to have the following output format:
+// Portion that builds and runs tests.  Unless an assignment declares
+// its own commands, this is done via a call to `make build` and
+// `make test`.  Compiler failure is assumed to be communicated by
+// return value.  The output of the test command is handed to a
+// `TestOutputParser`, which each assignment may choose.  By default,
+// tests are assumed to have the following output format:
 //
-// some test name: <PASS|FAIL>
+// some test name: <PASS|FAIL|SKIP|ERROR|TIMEOUT>
 //
 // ...where each test result is on its own line.  If multiple tests
-// have the same name, then only the last test is recorded.
+// have the same name, then only the last test is recorded.  Tests
+// worth partial credit may instead report points, like so:
+//
+// some test name: 7/10
+//
+// Assignments may instead give pairs of input and expected output
+// files, in which case the test stage runs the student's program on
+// each input (see `iotest`).
 
+extern crate libc;
 extern crate serialize;
+extern crate time;
 
+use self::libc::pid_t;
 use self::serialize::json::{ToJson, Json};
-use std::collections::HashMap;
-use std::io::{BufferedReader, ByRefReader, RefReader, IoResult,
-              IoError, OtherIoError};
+use std::cmp::min;
+use std::collections::{HashMap, HashSet};
+use std::comm::Receiver;
+use std::default::Default;
+use std::io;
+use std::io::{IoResult, IoError, OtherIoError};
 use std::io::pipe::PipeStream;
 use std::io::process::{Command, Process, ExitStatus, ExitSignal,
                        ProcessExit};
+use std::mem;
+use std::sync::{Mutex, Once, ONCE_INIT};
+
+use assignment::AssignmentConfig;
+use memcheck::Finding;
+use overlay::Tampering;
+use seccomp;
+use seccomp::Profile;
+
+use self::BuildOutcome::{SetupEnvFailure, BuildFailure, TestFailure, StageFailure,
+                         TestSuccess, StageTimedOut, LimitExceeded, SyscallBlocked,
+                         Cancelled};
+use self::Limit::{CpuTime, FileSize};
+use self::TestResult::{Pass, Fail, Skipped, Errored, TimedOut, Crashed};
+use self::Stage::{SetupEnv, Build, Test, Named};
+use self::FailurePolicy::{Abort, Continue, Penalty};
+use self::StageStatus::{Succeeded, Failed, NotRun};
+
+/// Version of the JSON format produced by `BuildResult::consume_to_json`.
+/// Bump this whenever the format changes, so the frontend knows how
+/// to render old results.
+pub static RESULTS_VERSION: i64 = 12;
+
+/// Process groups of every command which is currently running, so
+/// that they can be killed from elsewhere on cancellation or shutdown.
+struct RunningGroups {
+    pgids: HashSet<pid_t>,
+    /// Groups which were killed by `kill_running`, and haven't been
+    /// waited on yet
+    killed: HashSet<pid_t>,
+    /// Set by `kill_running`, until `clear_cancelled`
+    cancelled: bool,
+    shutting_down: bool
+}
+
+static mut RUNNING: *const Mutex<RunningGroups> = 0 as *const Mutex<RunningGroups>;
+static RUNNING_INIT: Once = ONCE_INIT;
+
+fn running_groups() -> &'static Mutex<RunningGroups> {
+    unsafe {
+        RUNNING_INIT.doit(|| {
+            RUNNING = mem::transmute(
+                box Mutex::new(
+                    RunningGroups {
+                        pgids: HashSet::new(),
+                        killed: HashSet::new(),
+                        cancelled: false,
+                        shutting_down: false
+                    }));
+        });
+        &*RUNNING
+    }
+}
+
+/// Kills every process in the given process group.  Anything a command
+/// forks stays in its group, unless it goes out of its way to leave.
+pub fn kill_process_group(pgid: pid_t) {
+    // an error just means there was nothing left to kill
+    let _ = Process::kill(-pgid, SIGKILL);
+}
+
+/// Kills every command which is currently running, along with all of
+/// their descendants.  Whatever was running them sees this as a
+/// `CANCELLED` error.  This is how the build in progress is cancelled:
+/// the build stops after the stage it's in, with a `Cancelled` outcome.
+pub fn kill_running() {
+    let mut groups = running_groups().lock();
+    groups.cancelled = true;
+    let pgids: Vec<pid_t> = groups.pgids.iter().map(|p| *p).collect();
+    for pgid in pgids.into_iter() {
+        kill_process_group(pgid);
+        groups.killed.insert(pgid);
+    }
+}
 
-use util::MessagingUnwrapper;
+/// Whether or not `kill_running` was called since `clear_cancelled`.
+pub fn is_cancelled() -> bool {
+    running_groups().lock().cancelled
+}
+
+/// Called before each build, so that only a cancellation during the
+/// build counts against it.
+pub fn clear_cancelled() {
+    running_groups().lock().cancelled = false;
+}
+
+/// Description of the error which commands killed by `kill_running`
+/// get.
+pub static CANCELLED: &'static str = "Cancelled";
 
-use self::BuildResult::{SetupEnvFailure, BuildFailure, TestFailure, TestSuccess};
-use self::TestResult::{Pass, Fail};
+/// Like `kill_running`, but also prevents any more commands from
+/// being started.  For when the worker is going down.
+pub fn shut_down() {
+    running_groups().lock().shutting_down = true;
+    kill_running();
+}
+
+pub fn is_shutting_down() -> bool {
+    running_groups().lock().shutting_down
+}
 
+/// Each command is started as the leader of a new session, and thus of
+/// a new process group.  This way, it and everything it forks (e.g.,
+/// `make` along with the `a.out` it runs) can be killed at once.
 fn spawn_with_timeout(c: &Command, timeout: Option<u64>) -> IoResult<Process> {
+    let mut c = c.clone();
+    c.detached();
+
+    let mut groups = running_groups().lock();
+    if groups.shutting_down {
+        return Err(
+            IoError {
+                kind: OtherIoError,
+                desc: "Worker is shutting down",
+                detail: None
+            });
+    }
     let mut p = try!(c.spawn());
+    groups.pgids.insert(p.id());
     p.set_timeout(timeout);
     Ok(p)
 }
 
+/// Waits for the process to finish, then kills anything it left behind
+/// in its process group.  If the wait failed (e.g., it timed out), the
+/// process is killed and reaped, as `Process`'s destructor would
+/// otherwise block on it.  If `kill_running` killed it, that's the
+/// error, however it exited.
+fn wait_and_kill_group(p: &mut Process) -> IoResult<ProcessExit> {
+    let res = p.wait();
+    kill_process_group(p.id());
+    if res.is_err() {
+        p.set_timeout(None);
+        let _ = p.wait();
+    }
+    let mut groups = running_groups().lock();
+    groups.pgids.remove(&p.id());
+    if groups.killed.remove(&p.id()) {
+        return Err(
+            IoError {
+                kind: OtherIoError,
+                desc: CANCELLED,
+                detail: None
+            });
+    }
+    res
+}
+
 /// Runs the given command with the given timeout, ignoring the output.
 /// If it returns non-zero, then it's a failure, as with a signal.
 /// Takes what it should return on success.
 pub fn run_command<A>(c: &Command, timeout: Option<u64>, on_success: A) -> IoResult<A> {
-    (try!(
-        (try!(
-            spawn_with_timeout(c, timeout)))
-            .wait())).if_ok(on_success)
+    let mut p = try!(spawn_with_timeout(c, timeout));
+    (try!(wait_and_kill_group(&mut p))).if_ok(on_success)
 }
 
 // Runs the given chain of commands.  Returns the first error.
@@ -47,6 +196,228 @@ pub fn run_commands<A>(commands: &Vec<Command>, timeout: Option<u64>, on_success
     Ok(on_success)
 }
 
+/// Most bytes kept from any one output stream of a command.  Anything
+/// past this is still read (so the command doesn't block on a full
+/// pipe), but is thrown away.
+pub static OUTPUT_CAP: uint = 64 * 1024;
+
+#[deriving(Show, PartialEq, Clone)]
+pub enum Stage {
+    SetupEnv,
+    Build,
+    Test,
+    /// Anything else an assignment runs, like a linter or benchmarks
+    Named(String)
+}
+
+impl Stage {
+    pub fn name<'a>(&'a self) -> &'a str {
+        match *self {
+            SetupEnv => "env",
+            Build => "build",
+            Test => "test",
+            Named(ref name) => name.as_slice()
+        }
+    }
+
+    /// `build` and `test` are the built-in stages; `env` can't be used.
+    pub fn from_name(name: &str) -> Option<Stage> {
+        match name {
+            "env" => None,
+            "build" => Some(Build),
+            "test" => Some(Test),
+            _ if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_') =>
+                Some(Named(name.to_string())),
+            _ => None
+        }
+    }
+}
+
+/// What happens to the rest of the build when a stage fails.
+#[deriving(Show, PartialEq, Clone)]
+pub enum FailurePolicy {
+    /// Later stages are skipped, and the failure is the outcome
+    Abort,
+    /// The failure is only reported
+    Continue,
+    /// The failure is reported, and this fraction of the possible
+    /// score is taken off
+    Penalty(f64)
+}
+
+/// A stage which runs after environment setup, in order.
+#[deriving(Show, PartialEq, Clone)]
+pub struct StagePlan {
+    pub stage: Stage,
+    /// Only for named stages; the built-in ones have their own
+    pub commands: Vec<CommandLine>,
+    /// Only for named stages, in milliseconds
+    pub timeout: Option<u64>,
+    pub on_failure: FailurePolicy
+}
+
+impl StagePlan {
+    /// A built-in stage, which aborts the build on failure.
+    pub fn built_in(stage: Stage) -> StagePlan {
+        StagePlan {
+            stage: stage,
+            commands: Vec::new(),
+            timeout: None,
+            on_failure: Abort
+        }
+    }
+
+    /// Building, then testing.
+    pub fn defaults() -> Vec<StagePlan> {
+        vec!(StagePlan::built_in(Build), StagePlan::built_in(Test))
+    }
+}
+
+/// Output of a single command.  Output is capped at `OUTPUT_CAP`
+/// bytes per stream, with `truncated` set if anything was dropped.
+#[deriving(Show, Clone)]
+pub struct CommandLog {
+    pub stage: Stage,
+    pub command: String,
+    /// `None` if the command never finished, e.g., it couldn't be
+    /// started or it timed out
+    pub exit: Option<ProcessExit>,
+    pub stdout: String,
+    pub stderr: String,
+    pub truncated: bool
+}
+
+impl ToJson for CommandLog {
+    fn to_json(&self) -> Json {
+        let mut map = HashMap::new();
+        map.insert("stage".to_string(), self.stage.name().to_string().to_json());
+        map.insert("command".to_string(), self.command.to_json());
+        map.insert("exit".to_string(), self.exit.map(|e| e.to_string()).to_json());
+        map.insert("stdout".to_string(), self.stdout.to_json());
+        map.insert("stderr".to_string(), self.stderr.to_json());
+        map.insert("truncated".to_string(), self.truncated.to_json());
+        map.to_json()
+    }
+}
+
+fn read_capped(stream: Option<PipeStream>, cap: uint) -> (Vec<u8>, bool) {
+    let mut kept = Vec::new();
+    let mut truncated = false;
+    match stream {
+        Some(mut s) => {
+            let mut buf = [0u8, ..4096];
+            loop {
+                match s.read(buf.as_mut_slice()) {
+                    Ok(n) => {
+                        let room = cap - kept.len();
+                        if n > room {
+                            truncated = true;
+                        }
+                        kept.push_all(buf.slice_to(min(n, room)));
+                    },
+                    Err(_) => break // EOF, or the process went away
+                }
+            }
+        },
+        None => ()
+    }
+    (kept, truncated)
+}
+
+/// Drains the stream in another task, so that neither stdout nor
+/// stderr can fill up and block the command while we wait on it.
+fn read_in_background(stream: Option<PipeStream>) -> Receiver<(Vec<u8>, bool)> {
+    let (tx, rx) = channel();
+    spawn(proc() {
+        tx.send(read_capped(stream, OUTPUT_CAP));
+    });
+    rx
+}
+
+/// Like `run_command`, but the output of the command is kept and
+/// returned regardless of how it exits.  Returns the raw exit status,
+/// since whether or not a non-zero exit is a failure is up to the caller.
+pub fn run_captured(c: &Command, timeout: Option<u64>, stage: Stage) -> (IoResult<ProcessExit>, CommandLog) {
+    run_with_input(c, Vec::new(), timeout, stage)
+}
+
+/// Like `run_captured`, but `input` is fed to the command's stdin.
+/// It's written from another task, so a command which doesn't read
+/// all of it can't block us.
+pub fn run_with_input(c: &Command, input: Vec<u8>, timeout: Option<u64>,
+                      stage: Stage) -> (IoResult<ProcessExit>, CommandLog) {
+    let mut log = CommandLog {
+        stage: stage,
+        command: c.to_string(),
+        exit: None,
+        stdout: String::new(),
+        stderr: String::new(),
+        truncated: false
+    };
+
+    let mut p = match spawn_with_timeout(c, timeout) {
+        Ok(p) => p,
+        Err(e) => return (Err(e), log)
+    };
+    let stdin = p.stdin.take();
+    if input.is_empty() {
+        drop(stdin);
+    } else {
+        spawn(proc() {
+            match stdin {
+                // an error just means the command stopped reading
+                Some(mut s) => { let _ = s.write(input.as_slice()); },
+                None => ()
+            }
+        });
+    }
+    let stdout = read_in_background(p.stdout.take());
+    let stderr = read_in_background(p.stderr.take());
+
+    // Anything left in the group could still be holding on to stdout
+    // or stderr, so it has to be gone before the reads will finish
+    let res = wait_and_kill_group(&mut p);
+
+    let (out, out_truncated) = stdout.recv();
+    let (err, err_truncated) = stderr.recv();
+    log.exit = res.as_ref().ok().map(|e| e.clone());
+    log.stdout = String::from_utf8_lossy(out.as_slice()).into_string();
+    log.stderr = String::from_utf8_lossy(err.as_slice()).into_string();
+    log.truncated = out_truncated || err_truncated;
+    (res, log)
+}
+
+pub fn now_millis() -> u64 {
+    time::precise_time_ns() / 1000000
+}
+
+/// The configuration to build `assignment` with.  A broken one is
+/// returned as well, along with the defaults to stand in for it, so
+/// that the build can fail with it during environment setup.
+fn assignment_config(assignment: &str) -> (AssignmentConfig, Option<IoError>) {
+    match AssignmentConfig::resolve(assignment) {
+        Ok(config) => (config, None),
+        Err(e) => (Default::default(), Some(e))
+    }
+}
+
+/// Runs the given chain of commands as part of the given stage,
+/// recording the output of each in `logs`.  Returns the first error.
+/// The timeout is for the chain as a whole, not for each command.
+pub fn run_logged_commands(commands: &Vec<Command>,
+                           timeout: Option<u64>,
+                           stage: Stage,
+                           logs: &mut Vec<CommandLog>) -> IoResult<()> {
+    let deadline = timeout.map(|t| now_millis() + t);
+    for cmd in commands.iter() {
+        let remaining = deadline.map(|d| d.saturating_sub(now_millis()));
+        let (res, log) = run_captured(cmd, remaining, stage.clone());
+        logs.push(log);
+        try!(try!(res).if_ok(()));
+    }
+    Ok(())
+}
+
 trait ErrorSimplifier {
     fn if_ok<A>(&self, ret_this: A) -> IoResult<A>;
 }
@@ -71,37 +442,119 @@ impl ErrorSimplifier for ProcessExit {
     }
 }
 
-#[deriving(Show, PartialEq)]
+#[deriving(Show, PartialEq, Clone)]
 pub enum TestResult {
     Pass,
-    Fail
+    Fail,
+    Skipped,
+    /// The test harness itself broke, as opposed to the test failing
+    Errored,
+    TimedOut,
+    /// Killed by the given signal
+    Crashed(int)
+}
+
+impl TestResult {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Pass => "pass",
+            Fail => "fail",
+            Skipped => "skipped",
+            Errored => "errored",
+            TimedOut => "timed_out",
+            Crashed(_) => "crashed"
+        }
+    }
 }
 
 impl ToJson for TestResult {
     fn to_json(&self) -> Json {
-        let as_bool =
-            match self {
-                &Pass => true,
-                &Fail => false
-            };
-        as_bool.to_json()
+        self.name().to_string().to_json()
+    }
+}
+
+/// Partial credit reported by a test itself.
+#[deriving(Show, PartialEq, Clone)]
+pub struct Points {
+    pub earned: f64,
+    pub possible: f64
+}
+
+impl Points {
+    /// Fraction of the points which were earned, in `[0, 1]`.
+    pub fn fraction(&self) -> f64 {
+        if self.possible <= 0.0 {
+            0.0
+        } else {
+            (self.earned / self.possible).max(0.0).min(1.0)
+        }
+    }
+}
+
+impl ToJson for Points {
+    fn to_json(&self) -> Json {
+        let mut map = HashMap::new();
+        map.insert("earned".to_string(), self.earned);
+        map.insert("possible".to_string(), self.possible);
+        map.to_json()
     }
 }
 
-struct ProcessReader {
-    p: Process
+/// Everything known about how a single test went.
+#[deriving(Show, PartialEq, Clone)]
+pub struct TestOutcome {
+    pub result: TestResult,
+    pub message: Option<String>,
+    /// In seconds
+    pub duration: Option<f64>,
+    pub points: Option<Points>
 }
 
-impl ProcessReader {
-    fn new(cmd: &Command, timeout: Option<u64>) -> IoResult<ProcessReader> {
-        spawn_with_timeout(cmd, timeout).map(|p| ProcessReader { p: p })
+impl TestOutcome {
+    pub fn new(result: TestResult) -> TestOutcome {
+        TestOutcome {
+            result: result,
+            message: None,
+            duration: None,
+            points: None
+        }
+    }
+
+    /// A test which earned some number of points.  It only counts
+    /// as passing if it earned all of them.
+    pub fn with_points(earned: f64, possible: f64) -> TestOutcome {
+        let result = if earned >= possible { Pass } else { Fail };
+        TestOutcome {
+            points: Some(Points { earned: earned, possible: possible }),
+            ..TestOutcome::new(result)
+        }
+    }
+
+    pub fn with_message(mut self, message: Option<String>) -> TestOutcome {
+        self.message = message;
+        self
     }
 
-    fn output_reader<'a>(&'a mut self) -> BufferedReader<RefReader<'a, PipeStream>> {
-        // unwrap should be ok - the documentation says `Some` is the default,
-        // and we are not messing with any of the defaults
-        BufferedReader::new(
-            self.p.stdout.as_mut().unwrap_msg(line!()).by_ref())
+    pub fn with_duration(mut self, duration: Option<f64>) -> TestOutcome {
+        self.duration = duration;
+        self
+    }
+}
+
+impl ToJson for TestOutcome {
+    fn to_json(&self) -> Json {
+        let mut map = HashMap::new();
+        map.insert("result".to_string(), self.result.to_json());
+        map.insert("message".to_string(), self.message.to_json());
+        map.insert("duration".to_string(), self.duration.to_json());
+        map.insert("points".to_string(), self.points.to_json());
+        match self.result {
+            Crashed(signal) => {
+                map.insert("signal".to_string(), (signal as i64).to_json());
+            },
+            _ => ()
+        }
+        map.to_json()
     }
 }
 
@@ -109,6 +562,9 @@ fn parse_test_result(line: &str) -> IoResult<TestResult> {
     match line {
         "PASS" => Ok(Pass),
         "FAIL" => Ok(Fail),
+        "SKIP" => Ok(Skipped),
+        "ERROR" => Ok(Errored),
+        "TIMEOUT" => Ok(TimedOut),
         _ => Err(
             IoError {
                 kind: OtherIoError,
@@ -118,10 +574,31 @@ fn parse_test_result(line: &str) -> IoResult<TestResult> {
     }
 }
 
-fn parse_line(line: &str) -> IoResult<(String, TestResult)> {
+/// Parses `earned/possible`, e.g., `7/10` or `2.5/5`.
+fn parse_points(s: &str) -> Option<TestOutcome> {
+    let parts: Vec<&str> = s.split('/').map(|p| p.trim()).collect();
+    if parts.len() != 2 {
+        return None;
+    }
+    match (from_str::<f64>(parts[0]), from_str::<f64>(parts[1])) {
+        (Some(earned), Some(possible)) if possible > 0.0 =>
+            Some(TestOutcome::with_points(earned, possible)),
+        _ => None
+    }
+}
+
+fn parse_outcome(s: &str) -> IoResult<TestOutcome> {
+    let s = s.trim();
+    match parse_points(s) {
+        Some(outcome) => Ok(outcome),
+        None => parse_test_result(s).map(|res| TestOutcome::new(res))
+    }
+}
+
+fn parse_line(line: &str) -> IoResult<(String, TestOutcome)> {
     let results: Vec<&str> = line.split_str(":").collect();
     if results.len() == 2 {
-        parse_test_result(results[1]).map(|res| {
+        parse_outcome(results[1]).map(|res| {
             (results[0].to_string(), res)
         })
     } else {
@@ -134,356 +611,4288 @@ fn parse_line(line: &str) -> IoResult<(String, TestResult)> {
     }
 }
 
-#[deriving(Show)]
-pub enum BuildResult {
-    SetupEnvFailure(IoError),
-    BuildFailure(IoError),
-    TestFailure(IoError),
-    TestSuccess(HashMap<String, TestResult>)
+/// Turns the output of a test command into per-test results.
+/// Courses using a different test harness can implement this
+/// instead of modifying the builder.
+pub trait TestOutputParser {
+    fn parse_output(&self, output: &str) -> IoResult<HashMap<String, TestOutcome>>;
+
+    /// Called right before the test command runs, for parsers which
+    /// read anything other than its output.
+    fn prepare(&self) -> IoResult<()> {
+        Ok(())
+    }
 }
 
-impl BuildResult {
-    // Unlike to_json, this consumes the argument.  This avoids copying.
-    pub fn consume_to_json(self) -> Json {
-        fn error_to_json(error_name: &str, error_desc: &IoError) -> Json {
-            let mut map = HashMap::new();
-            map.insert("error".to_string(), error_name.to_string());
-            map.insert("description".to_string(), error_desc.to_string());
-            map.to_json()
-        }
+/// Parses the `some test name:<PASS|FAIL>` format.  Unless `strict`
+/// is set, lines which are not in this format are skipped, so stray
+/// debugging output from a test doesn't sink the whole run.
+pub struct LineParser {
+    pub strict: bool
+}
 
-        match self {
-            SetupEnvFailure(ref e) =>
-                error_to_json("Environment setup", e),
-            BuildFailure(ref e) =>
-                error_to_json("Build failure", e),
-            TestFailure(ref e) =>
-                error_to_json("Testing execution failure", e),
-            TestSuccess(res) => {
-                let mut map = HashMap::new();
-                map.insert("success".to_string(), res);
-                map.to_json()
+/// Parser used by any `WholeBuildable` which doesn't specify its own.
+pub static DEFAULT_PARSER: LineParser = LineParser { strict: false };
+
+impl LineParser {
+    pub fn new() -> LineParser {
+        LineParser { strict: false }
+    }
+
+    pub fn strict() -> LineParser {
+        LineParser { strict: true }
+    }
+}
+
+impl TestOutputParser for LineParser {
+    fn parse_output(&self, output: &str) -> IoResult<HashMap<String, TestOutcome>> {
+        let mut map = HashMap::new();
+        for line in output.lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            match parse_line(line) {
+                Ok((k, v)) => { map.insert(k, v); },
+                Err(e) => {
+                    if self.strict {
+                        return Err(e);
+                    }
+                }
             }
         }
+        Ok(map)
     }
-} // BuildResult
+}
 
-pub trait WholeBuildable {
-    // BEGIN FUNCTIONS TO IMPLEMENT
-    fn env_timeout(&self) -> Option<u64>;
-    fn env_commands(&self) -> Vec<Command>;
+/// Total score for a build.
+#[deriving(Show, PartialEq, Clone)]
+pub struct Score {
+    pub earned: f64,
+    pub possible: f64
+}
 
-    fn build_timeout(&self) -> Option<u64>;
-    fn build_commands(&self) -> Vec<Command>;
+impl ToJson for Score {
+    fn to_json(&self) -> Json {
+        let mut map = HashMap::new();
+        map.insert("earned".to_string(), self.earned);
+        map.insert("possible".to_string(), self.possible);
+        map.to_json()
+    }
+}
 
-    fn test_timeout(&self) -> Option<u64>;
-    fn test_command(&self) -> Command;
-    // END FUNCTIONS TO IMPLEMENT
+/// How test results turn into a score.  Each test is worth its weight,
+/// with `default_weight` used for tests not listed in `weights`.  Tests
+/// which report their own points get that fraction of their weight if
+/// they have an explicit weight, and are otherwise scored as-is.
+/// Weighted tests which never showed up in the results count as zero.
+#[deriving(Show, Clone)]
+pub struct Rubric {
+    pub weights: HashMap<String, f64>,
+    pub default_weight: f64
+}
 
-    /// Gets everything in order for testing to be performed.
-    /// After calling this, it is assumed that we are ready
-    /// to call make
-    fn setup_env(&self) -> IoResult<()> {
-        run_commands(&self.env_commands(), self.env_timeout(), ())
-    }
-    
-    fn do_build(&self) -> IoResult<()> { 
-        run_commands(&self.build_commands(), self.build_timeout(), ())
+impl Rubric {
+    /// Every test is worth one point.
+    pub fn uniform() -> Rubric {
+        Rubric {
+            weights: HashMap::new(),
+            default_weight: 1.0
+        }
     }
 
-    fn do_testing(&self) -> IoResult<HashMap<String, TestResult>> {
-        let mut reader = 
-            try!(ProcessReader::new(&self.test_command(), self.test_timeout()));
-        let mut map = HashMap::new();
-        for op_line in reader.output_reader().lines() {
-            let (k, v) = try!(
-                parse_line(
-                    try!(op_line).as_slice().trim()));
-            map.insert(k, v);
+    pub fn score(&self, results: &HashMap<String, TestOutcome>) -> Score {
+        let mut earned = 0.0;
+        let mut possible = 0.0;
+
+        for (name, outcome) in results.iter() {
+            let (e, p) =
+                match (self.weights.get(name), &outcome.points) {
+                    (Some(&w), &Some(ref pts)) => (w * pts.fraction(), w),
+                    (None, &Some(ref pts)) => (pts.earned.max(0.0).min(pts.possible), pts.possible),
+                    (w, &None) => {
+                        let w = w.map(|w| *w).unwrap_or(self.default_weight);
+                        (if outcome.result == Pass { w } else { 0.0 }, w)
+                    }
+                };
+            earned += e;
+            possible += p;
         }
 
-        Ok(map)
-    }
+        for (name, w) in self.weights.iter() {
+            if !results.contains_key(name) {
+                possible += *w;
+            }
+        }
 
-    fn whole_build(&self) -> BuildResult {
-        // Because we have different results for different kinds
-        // of failures, we cannot use `try!`
-        match self.setup_env() {
-            Ok(_) => {
-                match self.do_build() {
-                    Ok(_) => {
-                        match self.do_testing() {
-                            Ok(res) => TestSuccess(res),
-                            Err(e) => TestFailure(e)
-                        }
-                    },
-                    Err(e) => BuildFailure(e)
-                }
-            },
-            Err(e) => SetupEnvFailure(e)
+        Score {
+            earned: earned,
+            possible: possible
         }
     }
 }
 
-pub trait ToWholeBuildable<A : WholeBuildable> {
-    fn to_whole_buildable(&self) -> A;
+/// How long each stage may take, in milliseconds.  `None` means
+/// forever.
+#[deriving(Show, PartialEq, Clone)]
+pub struct Timeouts {
+    pub env: Option<u64>,
+    pub build: Option<u64>,
+    pub test: Option<u64>
 }
 
-pub mod github {
-    extern crate github;
+impl Default for Timeouts {
+    /// Used for any assignment which doesn't specify its own.
+    /// Environment setup includes cloning, so it gets the longest.
+    fn default() -> Timeouts {
+        Timeouts {
+            env: Some(5 * 60 * 1000),
+            build: Some(2 * 60 * 1000),
+            test: Some(60 * 1000)
+        }
+    }
+}
 
-    use std::io::Command;
-    use std::rand;
+/// Commands for each stage, which run in the workspace.  Build and
+/// test commands run under the same limits, sandbox and user.
+#[deriving(Show, PartialEq, Clone)]
+pub struct StageCommands {
+    pub env: Vec<CommandLine>,
+    pub build: Vec<CommandLine>,
+    pub test: CommandLine
+}
 
-    use self::github::notification::PushNotification;
-    use self::github::clone_url::CloneUrl;
+impl Default for StageCommands {
+    /// Used for any assignment which doesn't specify its own.
+    fn default() -> StageCommands {
+        StageCommands {
+            env: Vec::new(),
+            build: vec!(CommandLine::new("make", &["-s", "build"])),
+            test: CommandLine::new("make", &["-s", "test"])
+        }
+    }
+}
 
-    use super::{WholeBuildable, ToWholeBuildable, run_command};
-    use super::testing::TestingRequest;
+/// Instructor files which are put into the workspace after the
+/// submission, replacing any of the student's files of the same name.
+#[deriving(Show, PartialEq, Clone)]
+pub enum Overlay {
+    /// Copied over as `makefile`
+    Makefile(Path),
+    /// Every file under this directory is copied over, to the same
+    /// place relative to the workspace
+    Files(Path),
+    /// For toolchains which don't need anything from the instructor
+    Nothing
+}
 
-    use database::PendingBuild;
-    use util::MessagingUnwrapper;
+/// Limits whose breaches can be told apart from other failures.
+/// Memory, open files and processes are limited too, but running out
+/// of them just makes calls fail, which programs react to however they
+/// like, so those breaches are reported as plain failures.
+#[deriving(Show, PartialEq, Clone)]
+pub enum Limit {
+    CpuTime,
+    FileSize
+}
 
-    pub struct GitHubRequest {
-        build_dir: Path,
-        branch: String,
-        clone_url: CloneUrl,
-        testing_req: TestingRequest,
+impl Limit {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            CpuTime => "cpu_time",
+            FileSize => "file_size"
+        }
     }
 
-    impl GitHubRequest {
-        pub fn new(pn: &PushNotification, mut build_root: Path, makefile_loc: Path) -> GitHubRequest {
-            // Each build should be isolated in its own directory; simple
-            // hack is to use large random numbers to achieve this
-            build_root.push(rand::random::<u64>().to_string());
-            
-            GitHubRequest {
-                build_dir: build_root.clone(),
-                branch: pn.branch.clone(),
-                clone_url: pn.clone_url.clone(),
-                testing_req: TestingRequest::new(build_root, makefile_loc)
-            }
+    pub fn description(&self) -> &'static str {
+        match *self {
+            CpuTime => "CPU time limit exceeded",
+            FileSize => "file size limit exceeded"
         }
     }
-    
-    impl WholeBuildable for GitHubRequest {
-        fn env_timeout(&self) -> Option<u64> { None }
-        fn build_timeout(&self) -> Option<u64> { None }
-        fn test_timeout(&self) -> Option<u64> { None }
-        
-        fn env_commands(&self) -> Vec<Command> {
-            let mut mkdir = Command::new("mkdir");
-            mkdir.arg(self.build_dir.as_str().unwrap_msg(line!()));
+}
 
-            let mut clone = Command::new("git");
-            clone.arg("clone").arg("-b").arg(self.branch.as_slice());
-            clone.arg(self.clone_url.url.serialize());
-            clone.arg(".");
-            clone.cwd(&self.build_dir);
+static SIGKILL: int = 9;
+static SIGXCPU: int = 24;
+static SIGXFSZ: int = 25;
 
-            let mut retval = vec!(mkdir, clone);
-            retval.push_all(self.testing_req.env_commands().as_slice());
-            retval
-        }
+/// A command as plain data.  Unlike `Command`, this can be picked
+/// apart, so it can be wrapped in other commands (e.g., `prlimit`)
+/// which set things up and then exec it.
+#[deriving(Show, PartialEq, Clone)]
+pub struct CommandLine {
+    pub program: String,
+    pub args: Vec<String>
+}
 
-        fn build_commands(&self) -> Vec<Command> {
-            self.testing_req.build_commands()
+impl CommandLine {
+    /// `None` if `argv` is empty.
+    pub fn from_argv(argv: &[String]) -> Option<CommandLine> {
+        if argv.is_empty() {
+            None
+        } else {
+            Some(CommandLine {
+                program: argv[0].clone(),
+                args: argv.slice_from(1).to_vec()
+            })
         }
+    }
 
-        fn test_command(&self) -> Command {
-            self.testing_req.test_command()
+    pub fn new(program: &str, args: &[&str]) -> CommandLine {
+        CommandLine {
+            program: program.to_string(),
+            args: args.iter().map(|a| a.to_string()).collect()
         }
     }
 
-    impl ToWholeBuildable<GitHubRequest> for PendingBuild {
-        fn to_whole_buildable(&self) -> GitHubRequest {
-            PushNotification {
-                clone_url: self.clone_url.clone(),
-                branch: self.branch.clone()
-            }.to_whole_buildable()
+    /// Runs `program` with `args`, followed by `--` and this command.
+    pub fn wrapped_in(self, program: &str, mut args: Vec<String>) -> CommandLine {
+        args.push("--".to_string());
+        args.push(self.program);
+        args.push_all(self.args.as_slice());
+        CommandLine {
+            program: program.to_string(),
+            args: args
         }
     }
 
-    impl ToWholeBuildable<GitHubRequest> for PushNotification {
-        fn to_whole_buildable(&self) -> GitHubRequest {
-            GitHubRequest::new(
-                self,
-                Path::new("build_test"),
-                Path::new("test/makefile"))
-        }
+    pub fn to_command(&self) -> Command {
+        let mut c = Command::new(self.program.as_slice());
+        c.args(self.args.as_slice());
+        c
     }
-    
-    impl Drop for GitHubRequest {
-        #[allow(unused_must_use)]
-        fn drop(&mut self) {
-            let mut c = Command::new("rm");
-            c.arg("-rf");
-            c.arg(self.build_dir.as_str().unwrap_msg(line!()));
-            run_command(&c, None, ());
+}
+
+/// Resource limits for student code, applied via `prlimit` right
+/// before the command is exec'd.  `None` leaves the worker's own
+/// limit in place.
+#[deriving(Show, PartialEq, Clone)]
+pub struct ResourceLimits {
+    pub cpu_seconds: Option<u64>,
+    /// In bytes
+    pub address_space: Option<u64>,
+    /// In bytes
+    pub file_size: Option<u64>,
+    pub open_files: Option<u64>,
+    /// Note that this limit is per-user, not per-command, so it only
+    /// makes sense if the command runs under its own uid.
+    pub processes: Option<u64>
+}
+
+impl Default for ResourceLimits {
+    /// Memory is left alone by default, as the JVM and friends
+    /// reserve far more address space than they ever use.
+    fn default() -> ResourceLimits {
+        ResourceLimits {
+            cpu_seconds: Some(2 * 60),
+            address_space: None,
+            file_size: Some(64 * 1024 * 1024),
+            open_files: Some(256),
+            processes: None
         }
     }
 }
 
-#[cfg(test)]
-mod process_tests {
-    use std::io::process::Command;
-    use super::{run_command, ProcessReader};
+impl ResourceLimits {
+    pub fn unlimited() -> ResourceLimits {
+        ResourceLimits {
+            cpu_seconds: None,
+            address_space: None,
+            file_size: None,
+            open_files: None,
+            processes: None
+        }
+    }
 
-    use util::MessagingUnwrapper;
+    fn prlimit_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        match self.cpu_seconds {
+            // the hard limit is a second past the soft limit, so that
+            // whatever ignores the soft limit's SIGXCPU is still killed
+            Some(s) => args.push(format!("--cpu={}:{}", s, s + 1)),
+            None => ()
+        }
+        let others = [("as", self.address_space),
+                      ("fsize", self.file_size),
+                      ("nofile", self.open_files),
+                      ("nproc", self.processes)];
+        for &(flag, limit) in others.iter() {
+            match limit {
+                Some(l) => args.push(format!("--{}={}", flag, l)),
+                None => ()
+            }
+        }
+        args
+    }
 
-    #[test]
-    fn echo_ok() {
-        assert!(run_command(&*Command::new("echo").arg("foobar"), None, ()).is_ok());
+    /// Makes the given command run with these limits.
+    pub fn wrap(&self, line: CommandLine) -> CommandLine {
+        let limit_args = self.prlimit_args();
+        if limit_args.is_empty() {
+            line
+        } else {
+            line.wrapped_in("prlimit", limit_args)
+        }
     }
-    
-    #[test]
-    fn false_ok() {
-        assert!(run_command(&Command::new("false"), None, ()).is_err());
+
+    /// Makes a command which runs the given program with these limits.
+    pub fn command(&self, program: &str, args: &[&str]) -> Command {
+        self.wrap(CommandLine::new(program, args)).to_command()
+    }
+
+    /// Figures out if the given command died due to one of these
+    /// limits.  Only the signals which limits, and nothing else, send
+    /// count: SIGXCPU and SIGXFSZ.  A SIGKILL could be from anywhere,
+    /// so it doesn't.  What was printed doesn't count either, as the
+    /// submission can print anything.  Commands run by `gradr_exec`
+    /// die of SIGXCPU whenever they failed after using up the CPU
+    /// limit, even if it was only hit further down (e.g., by the
+    /// `a.out` which `make` runs), or it was the hard limit's SIGKILL.
+    pub fn breached_limit(&self, log: &CommandLog) -> Option<Limit> {
+        match log.exit {
+            Some(ExitSignal(sig)) if sig == SIGXCPU && self.cpu_seconds.is_some() =>
+                Some(CpuTime),
+            Some(ExitSignal(sig)) if sig == SIGXFSZ && self.file_size.is_some() =>
+                Some(FileSize),
+            _ => None
+        }
+    }
+}
+
+#[deriving(Show, Clone)]
+pub enum BuildOutcome {
+    SetupEnvFailure(IoError),
+    BuildFailure(IoError),
+    TestFailure(IoError),
+    /// A named stage failed
+    StageFailure(Stage, IoError),
+    TestSuccess(HashMap<String, TestOutcome>, Score),
+    StageTimedOut(Stage),
+    LimitExceeded(Stage, Limit),
+    SyscallBlocked(Stage, Profile),
+    /// `kill_running` was called during the given stage
+    Cancelled(Stage)
+}
+
+impl BuildOutcome {
+    pub fn is_failure(&self) -> bool {
+        match *self {
+            TestSuccess(..) => false,
+            _ => true
+        }
+    }
+
+    /// Failures due to a timeout get their own outcome, since they
+    /// are (almost always) the fault of the submission rather than
+    /// of the environment.
+    fn from_error(stage: Stage, e: IoError) -> BuildOutcome {
+        if e.kind == io::TimedOut {
+            return StageTimedOut(stage);
+        }
+        match stage {
+            SetupEnv => SetupEnvFailure(e),
+            Build => BuildFailure(e),
+            Test => TestFailure(e),
+            named => StageFailure(named, e)
+        }
+    }
+
+    // Unlike to_json, this consumes the argument.  This avoids copying.
+    fn consume_to_map(self) -> HashMap<String, Json> {
+        fn error_to_json(error_name: &str, error_desc: &IoError) -> HashMap<String, Json> {
+            let mut map = HashMap::new();
+            map.insert("error".to_string(), error_name.to_string().to_json());
+            map.insert("description".to_string(), error_desc.to_string().to_json());
+            map
+        }
+
+        match self {
+            SetupEnvFailure(ref e) =>
+                error_to_json("Environment setup", e),
+            BuildFailure(ref e) =>
+                error_to_json("Build failure", e),
+            TestFailure(ref e) =>
+                error_to_json("Testing execution failure", e),
+            StageFailure(ref stage, ref e) => {
+                let mut map = error_to_json("Stage failure", e);
+                map.insert("stage".to_string(), stage.name().to_string().to_json());
+                map
+            },
+            TestSuccess(res, score) => {
+                let mut map = HashMap::new();
+                map.insert("success".to_string(), res.to_json());
+                map.insert("score".to_string(), score.to_json());
+                map
+            },
+            StageTimedOut(ref stage) => {
+                let mut map = HashMap::new();
+                map.insert("error".to_string(), "Timed out".to_string().to_json());
+                map.insert("stage".to_string(), stage.name().to_string().to_json());
+                map
+            },
+            LimitExceeded(ref stage, ref limit) => {
+                let mut map = HashMap::new();
+                map.insert("error".to_string(), "Limit exceeded".to_string().to_json());
+                map.insert("stage".to_string(), stage.name().to_string().to_json());
+                map.insert("limit".to_string(), limit.name().to_string().to_json());
+                map.insert("description".to_string(),
+                           limit.description().to_string().to_json());
+                map
+            },
+            SyscallBlocked(ref stage, ref profile) => {
+                let mut map = HashMap::new();
+                map.insert("error".to_string(), "Syscall blocked".to_string().to_json());
+                map.insert("stage".to_string(), stage.name().to_string().to_json());
+                map.insert("profile".to_string(), profile.name().to_string().to_json());
+                map
+            },
+            Cancelled(ref stage) => {
+                let mut map = HashMap::new();
+                map.insert("error".to_string(), "Cancelled".to_string().to_json());
+                map.insert("stage".to_string(), stage.name().to_string().to_json());
+                map
+            }
+        }
+    }
+}
+
+#[deriving(Show)]
+pub enum StageStatus {
+    Succeeded,
+    /// Along with the fraction of the score which was taken off for it
+    Failed(BuildOutcome, f64),
+    /// An earlier stage aborted the build
+    NotRun
+}
+
+/// How a single stage went.
+#[deriving(Show)]
+pub struct StageReport {
+    pub stage: Stage,
+    pub status: StageStatus
+}
+
+impl StageReport {
+    fn consume_to_json(self) -> Json {
+        let mut map = HashMap::new();
+        map.insert("stage".to_string(), self.stage.name().to_string().to_json());
+        let status =
+            match self.status {
+                Succeeded => "succeeded",
+                Failed(outcome, penalty) => {
+                    map.insert("failure".to_string(), outcome.consume_to_map().to_json());
+                    map.insert("penalty".to_string(), penalty.to_json());
+                    "failed"
+                },
+                NotRun => "skipped"
+            };
+        map.insert("status".to_string(), status.to_string().to_json());
+        map.to_json()
+    }
+}
+
+#[deriving(Show)]
+pub struct BuildResult {
+    pub outcome: BuildOutcome,
+    /// Output of every command which was run, in order
+    pub logs: Vec<CommandLog>,
+    /// Where the workspace was kept, if the build failed and it was
+    pub retained: Option<Path>,
+    /// Protected instructor files which the submission interfered with
+    pub tampering: Vec<Tampering>,
+    /// Every stage after environment setup, in the order they were
+    /// planned to run
+    pub stages: Vec<StageReport>,
+    /// Problems found by memory checking, if it ran
+    pub findings: Vec<Finding>
+}
+
+impl BuildResult {
+    // Unlike to_json, this consumes the argument.  This avoids copying.
+    pub fn consume_to_json(self) -> Json {
+        let mut map = self.outcome.consume_to_map();
+        map.insert("version".to_string(), RESULTS_VERSION.to_json());
+        map.insert("logs".to_string(), self.logs.to_json());
+        map.insert("tampering".to_string(), self.tampering.to_json());
+        map.insert("stages".to_string(),
+                   self.stages.into_iter().map(|s| s.consume_to_json()).collect::<Vec<Json>>()
+                   .to_json());
+        map.insert("findings".to_string(), self.findings.to_json());
+        match self.retained {
+            Some(ref path) => {
+                map.insert("retained".to_string(), path.display().to_string().to_json());
+            },
+            None => ()
+        }
+        map.to_json()
+    }
+} // BuildResult
+
+pub trait WholeBuildable {
+    /// Another build which this one is mostly the same as (e.g., one
+    /// which only differs in how the workspace is set up).  Everything
+    /// this one doesn't implement itself is then done by the delegate,
+    /// the default behaviour below included.
+    fn delegate<'a>(&'a self) -> Option<&'a (WholeBuildable + 'a)> {
+        None
+    }
+
+    /// The delegate, for what has to be implemented by one or the other.
+    fn delegated<'a>(&'a self) -> &'a (WholeBuildable + 'a) {
+        self.delegate().expect("WholeBuildable without a delegate should implement this")
+    }
+
+    // BEGIN FUNCTIONS TO IMPLEMENT, unless there's a delegate
+    fn env_timeout(&self) -> Option<u64> { self.delegated().env_timeout() }
+    fn env_commands(&self) -> Vec<Command> { self.delegated().env_commands() }
+
+    fn build_timeout(&self) -> Option<u64> { self.delegated().build_timeout() }
+    fn build_commands(&self) -> Vec<Command> { self.delegated().build_commands() }
+
+    fn test_timeout(&self) -> Option<u64> { self.delegated().test_timeout() }
+    fn test_command(&self) -> Command { self.delegated().test_command() }
+    // END FUNCTIONS TO IMPLEMENT
+
+    /// How the output of `test_command` should be interpreted.
+    fn output_parser<'a>(&'a self) -> &'a (TestOutputParser + 'a) {
+        match self.delegate() {
+            Some(d) => d.output_parser(),
+            None => &DEFAULT_PARSER
+        }
+    }
+
+    /// How the test results should be scored.
+    fn rubric(&self) -> Rubric {
+        match self.delegate() {
+            Some(d) => d.rubric(),
+            None => Rubric::uniform()
+        }
+    }
+
+    /// Limits which the build and test commands run under.  This is
+    /// only used to figure out why a command failed; it's up to the
+    /// implementor to actually apply them when making the commands.
+    fn limits(&self) -> ResourceLimits {
+        match self.delegate() {
+            Some(d) => d.limits(),
+            None => ResourceLimits::unlimited()
+        }
+    }
+
+    /// Seccomp profile which the test command runs under.  As with
+    /// `limits`, it's up to the implementor to actually apply it.
+    fn seccomp_profile(&self) -> Option<Profile> {
+        self.delegate().and_then(|d| d.seccomp_profile())
+    }
+
+    /// Keeps the workspace of a failed build around for inspection,
+    /// returning where it is now.  By default, nothing is kept.
+    fn retain_workspace(&self) -> Option<Path> {
+        self.delegate().and_then(|d| d.retain_workspace())
+    }
+
+    /// Protected files which the submission interfered with, as of the
+    /// last stage run.  By default, nothing is protected.
+    fn tampering(&self) -> Vec<Tampering> {
+        match self.delegate() {
+            Some(d) => d.tampering(),
+            None => Vec::new()
+        }
+    }
+
+    /// Problems found by memory checking, as of the last stage run.
+    /// By default, there's no memory checking.
+    fn findings(&self) -> Vec<Finding> {
+        match self.delegate() {
+            Some(d) => d.findings(),
+            None => Vec::new()
+        }
+    }
+
+    /// What runs after environment setup, in order.
+    fn stages(&self) -> Vec<StagePlan> {
+        match self.delegate() {
+            Some(d) => d.stages(),
+            None => StagePlan::defaults()
+        }
+    }
+
+    /// Commands for the given named stage.
+    fn stage_commands(&self, plan: &StagePlan) -> Vec<Command> {
+        match self.delegate() {
+            Some(d) => d.stage_commands(plan),
+            None => plan.commands.iter().map(|c| c.to_command()).collect()
+        }
+    }
+
+    /// Runs a named stage.
+    fn do_stage(&self, plan: &StagePlan, logs: &mut Vec<CommandLog>) -> IoResult<()> {
+        match self.delegate() {
+            Some(d) => d.do_stage(plan, logs),
+            None => run_logged_commands(&self.stage_commands(plan), plan.timeout,
+                                        plan.stage.clone(), logs)
+        }
+    }
+
+    /// Called after each stage, whether or not it succeeded.  An error
+    /// counts as a failure of that stage.
+    fn after_stage(&self, stage: &Stage) -> IoResult<()> {
+        match self.delegate() {
+            Some(d) => d.after_stage(stage),
+            None => Ok(())
+        }
+    }
+
+    /// Gets everything in order for testing to be performed.
+    /// After calling this, it is assumed that we are ready
+    /// to call make
+    fn setup_env(&self, logs: &mut Vec<CommandLog>) -> IoResult<()> {
+        match self.delegate() {
+            Some(d) => d.setup_env(logs),
+            None => run_logged_commands(&self.env_commands(), self.env_timeout(), SetupEnv, logs)
+        }
+    }
+    
+    fn do_build(&self, logs: &mut Vec<CommandLog>) -> IoResult<()> { 
+        match self.delegate() {
+            Some(d) => d.do_build(logs),
+            None => run_logged_commands(&self.build_commands(), self.build_timeout(), Build, logs)
+        }
+    }
+
+    /// The exit status of the test command is ignored, as plenty of
+    /// harnesses exit non-zero whenever any test fails.
+    fn do_testing(&self, logs: &mut Vec<CommandLog>) -> IoResult<HashMap<String, TestOutcome>> {
+        match self.delegate() {
+            Some(d) => return d.do_testing(logs),
+            None => ()
+        }
+        try!(self.output_parser().prepare());
+        let (res, log) = run_captured(&self.test_command(), self.test_timeout(), Test);
+        let parsed = res.and_then(|_| self.output_parser().parse_output(log.stdout.as_slice()));
+        logs.push(log);
+        parsed
+    }
+
+    /// If the last of the given commands hit a limit, returns the
+    /// appropriate outcome.
+    fn limit_outcome(&self, stage: Stage, logs: &[CommandLog]) -> Option<BuildOutcome> {
+        logs.last().and_then(|log| {
+            self.limits().breached_limit(log).map(|l| LimitExceeded(stage, l))
+        })
+    }
+
+    /// If the last of the given commands was killed by the seccomp
+    /// profile, returns the appropriate outcome.  Only the build runs
+    /// without the profile.
+    fn syscall_outcome(&self, stage: Stage, logs: &[CommandLog]) -> Option<BuildOutcome> {
+        match (self.seccomp_profile(), logs.last()) {
+            (Some(profile), Some(log)) if stage != Build && seccomp::blocked_syscall(log) =>
+                Some(SyscallBlocked(stage, profile)),
+            _ => None
+        }
+    }
+
+    /// Runs one stage, returning why it failed, if it did.  The
+    /// results of the test stage go into `results`.  If the build was
+    /// cancelled along the way, nothing else about the stage counts.
+    fn run_stage(&self, plan: &StagePlan, logs: &mut Vec<CommandLog>,
+                 results: &mut Option<HashMap<String, TestOutcome>>) -> Option<BuildOutcome> {
+        let stage = plan.stage.clone();
+        let logged = logs.len();
+        let res =
+            match stage {
+                Build => self.do_build(logs),
+                Test => self.do_testing(logs).map(|r| { *results = Some(r); }),
+                _ => self.do_stage(plan, logs)
+            };
+        let res = res.and(self.after_stage(&stage));
+        if is_cancelled() {
+            *results = None;
+            return Some(Cancelled(stage));
+        }
+
+        // only what this stage logged says anything about it; I/O and
+        // differential tests log nothing, and judge each run themselves
+        let own = logs.slice_from(logged);
+
+        // a test command which was killed may have printed results
+        // anyway, but they can't be trusted
+        let killed =
+            if stage == Test {
+                self.limit_outcome(Test, own).or_else(|| self.syscall_outcome(Test, own))
+            } else {
+                None
+            };
+        match (killed, res) {
+            (Some(o), _) => {
+                *results = None;
+                Some(o)
+            },
+            (None, Ok(_)) => None,
+            (None, Err(e)) => {
+                Some(self.limit_outcome(stage.clone(), own)
+                     .or_else(|| self.syscall_outcome(stage.clone(), own))
+                     .unwrap_or_else(|| BuildOutcome::from_error(stage, e)))
+            }
+        }
+    }
+
+    /// The outcome is that of the first stage which aborts the build.
+    /// Otherwise, it's the test results, less any penalties; if there
+    /// are none, the failure of the test stage is the outcome.  A
+    /// cancelled stage always aborts the build.
+    fn whole_build(&self) -> BuildResult {
+        let mut logs = Vec::new();
+        let mut reports = Vec::new();
+        let mut results = None;
+        let mut aborted =
+            match self.setup_env(&mut logs).and(self.after_stage(&SetupEnv)) {
+                _ if is_cancelled() => Some(Cancelled(SetupEnv)),
+                Ok(_) => None,
+                Err(e) => Some(BuildOutcome::from_error(SetupEnv, e))
+            };
+        let mut test_failure = None;
+        let mut penalty = 0.0;
+
+        for plan in self.stages().into_iter() {
+            if aborted.is_some() {
+                reports.push(StageReport { stage: plan.stage, status: NotRun });
+                continue;
+            }
+            let status =
+                match self.run_stage(&plan, &mut logs, &mut results) {
+                    None => Succeeded,
+                    Some(failure) => {
+                        if plan.stage == Test {
+                            test_failure = Some(failure.clone());
+                        }
+                        let on_failure =
+                            match failure {
+                                Cancelled(_) => Abort,
+                                _ => plan.on_failure.clone()
+                            };
+                        match on_failure {
+                            Abort => {
+                                aborted = Some(failure.clone());
+                                Failed(failure, 0.0)
+                            },
+                            Continue => Failed(failure, 0.0),
+                            Penalty(p) => {
+                                penalty += p;
+                                Failed(failure, p)
+                            }
+                        }
+                    }
+                };
+            reports.push(StageReport { stage: plan.stage, status: status });
+        }
+
+        let outcome =
+            match (aborted, results, test_failure) {
+                (Some(o), _, _) => o,
+                (None, Some(res), _) => {
+                    let mut score = self.rubric().score(&res);
+                    score.earned = (score.earned - penalty * score.possible).max(0.0);
+                    TestSuccess(res, score)
+                },
+                (None, None, Some(o)) => o,
+                // there was no test stage at all
+                (None, None, None) => TestSuccess(HashMap::new(), Score { earned: 0.0, possible: 0.0 })
+            };
+
+        let retained =
+            if outcome.is_failure() {
+                self.retain_workspace()
+            } else {
+                None
+            };
+
+        BuildResult {
+            outcome: outcome,
+            logs: logs,
+            retained: retained,
+            tampering: self.tampering(),
+            stages: reports,
+            findings: self.findings()
+        }
+    }
+}
+
+pub trait ToWholeBuildable<A : WholeBuildable> {
+    fn to_whole_buildable(&self) -> A;
+}
+
+pub mod workspace {
+    extern crate libc;
+
+    use self::libc::pid_t;
+    use std::cell::{Cell, RefCell};
+    use std::collections::HashSet;
+    use std::io;
+    use std::io::{fs, IoResult, IoError, FileNotFound, OtherIoError, InvalidInput,
+                  PermissionDenied, TypeDirectory};
+    use std::io::fs::PathExtensions;
+    use std::io::process::Process;
+    use std::mem;
+    use std::sync::{Mutex, Once, ONCE_INIT};
+    use std::sync::atomic::{AtomicUint, INIT_ATOMIC_UINT, SeqCst};
+
+    use util::warn;
+
+    use self::Cleanup::{RemoveAll, RemoveAdded};
+
+    static NEXT_ID: AtomicUint = INIT_ATOMIC_UINT;
+
+    /// Where retained workspaces are moved to, under the build root.
+    pub static RETAINED_DIR: &'static str = "retained";
+
+    /// Used when retention is asked for without a budget.
+    pub static DEFAULT_RETENTION_BUDGET: u64 = 1024 * 1024 * 1024;
+
+    /// Worker-wide settings for keeping the workspaces of failed builds.
+    #[deriving(Show, PartialEq, Clone)]
+    pub struct Retention {
+        /// Workspaces are only kept while all of them together take up
+        /// no more than this many bytes
+        pub budget: u64,
+        /// Keep failures of every assignment, rather than only of those
+        /// whose configuration asks for it
+        pub all: bool
+    }
+
+    /// Used when the worker isn't given an id.
+    pub static DEFAULT_WORKER_ID: &'static str = "gradr";
+
+    /// Workspaces are named `<worker id>-<pid>-<sequence number>`, so
+    /// that a worker can tell which leftover workspaces are its own.
+    struct Workspaces {
+        worker_id: String,
+        retention: Retention,
+        /// Every workspace of this process which hasn't been dropped
+        live: HashSet<Path>
+    }
+
+    static mut WORKSPACES: *const Mutex<Workspaces> = 0 as *const Mutex<Workspaces>;
+    static WORKSPACES_INIT: Once = ONCE_INIT;
+
+    fn workspaces() -> &'static Mutex<Workspaces> {
+        unsafe {
+            WORKSPACES_INIT.doit(|| {
+                WORKSPACES = mem::transmute(
+                    box Mutex::new(
+                        Workspaces {
+                            worker_id: DEFAULT_WORKER_ID.to_string(),
+                            retention: Retention {
+                                budget: DEFAULT_RETENTION_BUDGET,
+                                all: false
+                            },
+                            live: HashSet::new()
+                        }));
+            });
+            &*WORKSPACES
+        }
+    }
+
+    pub fn set_retention(retention: Retention) {
+        workspaces().lock().retention = retention;
+    }
+
+    pub fn retention() -> Retention {
+        workspaces().lock().retention.clone()
+    }
+
+    pub fn worker_id() -> String {
+        workspaces().lock().worker_id.clone()
+    }
+
+    /// Workers which share a build root need different ids.  Ids may
+    /// only contain letters, digits and underscores.
+    pub fn set_worker_id(id: &str) -> IoResult<()> {
+        if id.is_empty() || !id.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return Err(
+                IoError {
+                    kind: InvalidInput,
+                    desc: "Worker ids may only contain letters, digits and underscores",
+                    detail: Some(id.to_string())
+                });
+        }
+        workspaces().lock().worker_id = id.to_string();
+        Ok(())
+    }
+
+    /// Splits a workspace name into its worker id and pid.
+    fn parse_name(name: &str) -> Option<(String, pid_t)> {
+        let parts: Vec<&str> = name.split('-').collect();
+        if parts.len() != 3 || from_str::<uint>(parts[2]).is_none() {
+            return None;
+        }
+        from_str::<pid_t>(parts[1]).map(|pid| (parts[0].to_string(), pid))
+    }
+
+    fn is_running(pid: pid_t) -> bool {
+        match Process::kill(pid, 0) {
+            Ok(_) => true,
+            // it's there, it just isn't ours
+            Err(ref e) => e.kind == PermissionDenied
+        }
+    }
+
+    /// Removes workspaces under `build_root` which this worker made,
+    /// but which no longer belong to any build: those from a previous
+    /// run of the worker which died mid-build, and those which couldn't
+    /// be cleaned up at the time.  Workspaces of other workers are left
+    /// alone, as are retained ones.  Returns how many were removed.
+    pub fn collect_garbage(build_root: &Path) -> IoResult<uint> {
+        let me = unsafe { libc::getpid() };
+        // held throughout, so no workspace can be started in the meantime
+        let state = workspaces().lock();
+        let mut removed = 0;
+        for path in try!(fs::readdir(build_root)).iter() {
+            let stale =
+                match path.filename_str().and_then(parse_name) {
+                    Some((ref id, pid)) if *id == state.worker_id => {
+                        if pid == me {
+                            !state.live.contains(path)
+                        } else {
+                            !is_running(pid)
+                        }
+                    },
+                    _ => false
+                };
+            if stale {
+                match fs::rmdir_recursive(path) {
+                    Ok(_) => removed += 1,
+                    Err(e) => warn(format!("Could not remove stale {}: {}", path.display(), e))
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Bytes taken up by everything under `path`.  Symlinks aren't
+    /// followed, so this stays within `path`.
+    pub fn disk_usage(path: &Path) -> IoResult<u64> {
+        let stat = try!(fs::lstat(path));
+        if stat.kind != TypeDirectory {
+            return Ok(stat.size);
+        }
+        let mut total = stat.size;
+        for child in try!(fs::readdir(path)).iter() {
+            total += try!(disk_usage(child));
+        }
+        Ok(total)
+    }
+
+    /// Removes every retained workspace under `build_root`, returning
+    /// how many there were.
+    pub fn purge_retained(build_root: &Path) -> IoResult<uint> {
+        let dir = build_root.join(RETAINED_DIR);
+        if !dir.exists() {
+            return Ok(0);
+        }
+        let retained = try!(fs::readdir(&dir));
+        for path in retained.iter() {
+            try!(fs::rmdir_recursive(path));
+        }
+        Ok(retained.len())
+    }
+
+    enum Cleanup {
+        RemoveAll,
+        RemoveAdded
+    }
+
+    /// A directory which a build happens in.  Dropping it cleans up
+    /// after the build; anything which can't be cleaned up is logged.
+    pub struct Workspace {
+        path: Path,
+        // `None` for existing directories, which can't be retained
+        root: Option<Path>,
+        cleanup: Cleanup,
+        created: Cell<bool>,
+        retained: Cell<bool>,
+        added: RefCell<Vec<Path>>
+    }
+
+    impl Workspace {
+        /// A fresh directory under `root`.  It isn't actually made until
+        /// `create` is called.  When dropped, it's removed along with
+        /// everything in it.
+        pub fn new(root: &Path) -> Workspace {
+            let pid = unsafe { libc::getpid() };
+            let mut state = workspaces().lock();
+            loop {
+                let id = NEXT_ID.fetch_add(1, SeqCst);
+                let path = root.join(format!("{}-{}-{}", state.worker_id, pid, id));
+                // could be left over from a previous worker with our pid
+                if !path.exists() {
+                    state.live.insert(path.clone());
+                    return Workspace {
+                        path: path,
+                        root: Some(root.clone()),
+                        cleanup: RemoveAll,
+                        created: Cell::new(false),
+                        retained: Cell::new(false),
+                        added: RefCell::new(Vec::new())
+                    };
+                }
+            }
+        }
+
+        /// A directory which already exists.  When dropped, only files
+        /// put in with `copy_in` or marked with `remove_on_drop` are
+        /// removed.
+        pub fn existing(dir: Path) -> Workspace {
+            Workspace {
+                path: dir,
+                root: None,
+                cleanup: RemoveAdded,
+                created: Cell::new(true),
+                retained: Cell::new(false),
+                added: RefCell::new(Vec::new())
+            }
+        }
+
+        pub fn path<'a>(&'a self) -> &'a Path {
+            &self.path
+        }
+
+        /// Fails if the directory already exists, rather than sharing it.
+        pub fn create(&self) -> IoResult<()> {
+            try!(fs::mkdir(&self.path, io::USER_RWX));
+            self.created.set(true);
+            Ok(())
+        }
+
+        /// Copies `file` into the workspace as `name`, which may be in
+        /// a subdirectory.  Whatever is already there is removed first,
+        /// rather than written through, in case the build replaced it
+        /// with a symlink.  This is done as the worker, so it's only
+        /// for workspaces which nothing else can write to; otherwise,
+        /// what's checked can change before it's written to.
+        pub fn copy_in(&self, file: &Path, name: &str) -> IoResult<()> {
+            try!(self.make_parents(&Path::new(name)));
+            let dest = self.path.join(name);
+            try!(remove_file(&dest));
+            try!(fs::copy(file, &dest));
+            self.remove_on_drop(name);
+            Ok(())
+        }
+
+        /// Makes the directories leading up to `name`.  Anything in the
+        /// way which isn't a directory is removed, so that nothing can
+        /// be copied outside of the workspace through a symlink.
+        fn make_parents(&self, name: &Path) -> IoResult<()> {
+            let mut dir = self.path.clone();
+            let components: Vec<&[u8]> = name.components().collect();
+            for component in components.init().iter() {
+                dir.push(*component);
+                match fs::lstat(&dir) {
+                    Ok(ref stat) if stat.kind == TypeDirectory => continue,
+                    Ok(_) => try!(fs::unlink(&dir)),
+                    Err(ref e) if e.kind == FileNotFound => (),
+                    Err(e) => return Err(e)
+                }
+                try!(fs::mkdir(&dir, io::USER_RWX));
+                self.added.borrow_mut().push(dir.clone());
+            }
+            Ok(())
+        }
+
+        /// Moves the workspace under `RETAINED_DIR`, where it's left
+        /// alone when dropped, returning where it went.  Fails if that
+        /// would take retained workspaces over `budget` bytes.
+        pub fn retain(&self, budget: u64) -> IoResult<Path> {
+            let retained_dir =
+                match self.root {
+                    Some(ref root) if self.created.get() => root.join(RETAINED_DIR),
+                    _ => return Err(retain_error("Workspace was never created", None))
+                };
+            if !retained_dir.exists() {
+                try!(fs::mkdir(&retained_dir, io::USER_RWX));
+            }
+
+            let used = try!(disk_usage(&retained_dir)) + try!(disk_usage(&self.path));
+            if used > budget {
+                return Err(retain_error(
+                    "Retained workspaces would go over budget",
+                    Some(format!("{} bytes, budget is {}", used, budget))));
+            }
+
+            let dest = retained_dir.join(self.path.filename().unwrap());
+            try!(fs::rename(&self.path, &dest));
+            self.retained.set(true);
+            workspaces().lock().live.remove(&self.path);
+            Ok(dest)
+        }
+
+        /// Marks something which the build produces as needing to be
+        /// cleaned up.
+        pub fn remove_on_drop(&self, name: &str) {
+            let path = self.path.join(name);
+            let mut added = self.added.borrow_mut();
+            if !added.contains(&path) {
+                added.push(path);
+            }
+        }
+    }
+
+    fn retain_error(desc: &'static str, detail: Option<String>) -> IoError {
+        IoError {
+            kind: OtherIoError,
+            desc: desc,
+            detail: detail
+        }
+    }
+
+    /// Removes the given file, if there is one.
+    fn remove_file(path: &Path) -> IoResult<()> {
+        match fs::unlink(path) {
+            Err(ref e) if e.kind == FileNotFound => Ok(()),
+            res => res
+        }
+    }
+
+    impl Drop for Workspace {
+        fn drop(&mut self) {
+            if self.retained.get() {
+                return;
+            }
+            let res =
+                match self.cleanup {
+                    RemoveAll if self.created.get() => fs::rmdir_recursive(&self.path),
+                    RemoveAll => Ok(()),
+                    // in reverse, so directories are empty by the time
+                    // they're removed
+                    RemoveAdded => {
+                        self.added.borrow().iter().rev().fold(Ok(()), |res, path| {
+                            let removed =
+                                if path.is_dir() {
+                                    fs::rmdir(path)
+                                } else {
+                                    remove_file(path)
+                                };
+                            res.and(removed)
+                        })
+                    }
+                };
+            match res {
+                // removing it from the live workspaces lets it be
+                // garbage collected later
+                Ok(_) => (),
+                Err(e) => warn(format!("Could not clean up {}: {}", self.path.display(), e))
+            }
+            workspaces().lock().live.remove(&self.path);
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::io::{File, USER_RWX};
+        use std::io::fs;
+        use std::io::fs::PathExtensions;
+
+        extern crate libc;
+
+        use super::{Workspace, RETAINED_DIR, DEFAULT_WORKER_ID, purge_retained,
+                    collect_garbage, parse_name};
+
+        #[test]
+        fn unique_and_removed() {
+            let root = Path::new("test/workspaces");
+            let _ = fs::mkdir_recursive(&root, USER_RWX);
+            let path;
+            {
+                let first = Workspace::new(&root);
+                let second = Workspace::new(&root);
+                assert!(first.path() != second.path());
+                assert!(first.create().is_ok());
+                assert!(first.create().is_err());
+                assert!(File::create(&first.path().join("file")).is_ok());
+                path = first.path().clone();
+            }
+            assert!(!path.exists());
+        }
+
+        #[test]
+        fn parses_names() {
+            assert_eq!(parse_name("gradr-123-4"), Some(("gradr".to_string(), 123)));
+            assert_eq!(parse_name("123-4"), None);
+            assert_eq!(parse_name("gradr-123-x"), None);
+            assert_eq!(parse_name(RETAINED_DIR), None);
+        }
+
+        #[test]
+        fn collects_only_own_garbage() {
+            let root = Path::new("test/workspaces/gc");
+            let _ = fs::rmdir_recursive(&root);
+            assert!(fs::mkdir_recursive(&root, USER_RWX).is_ok());
+
+            let me = unsafe { libc::getpid() };
+            // above the kernel's maximum pid, so certainly not running
+            let dead = 1u << 23;
+            let stale = [format!("{}-{}-0", DEFAULT_WORKER_ID, dead),
+                         format!("{}-{}-1000000", DEFAULT_WORKER_ID, me)];
+            let kept = [format!("other-{}-0", dead),
+                        format!("{}-1-0", DEFAULT_WORKER_ID), // init never dies
+                        RETAINED_DIR.to_string()];
+            for name in stale.iter().chain(kept.iter()) {
+                assert!(fs::mkdir(&root.join(name.as_slice()), USER_RWX).is_ok());
+            }
+            let live = Workspace::new(&root);
+            assert!(live.create().is_ok());
+
+            assert_eq!(collect_garbage(&root).ok(), Some(2));
+            for name in stale.iter() {
+                assert!(!root.join(name.as_slice()).exists());
+            }
+            for name in kept.iter() {
+                assert!(root.join(name.as_slice()).exists());
+            }
+            assert!(live.path().exists());
+        }
+
+        #[test]
+        fn retained_within_budget() {
+            let root = Path::new("test/workspaces/retention");
+            let _ = fs::mkdir_recursive(&root, USER_RWX);
+            let _ = purge_retained(&root);
+
+            let kept = Workspace::new(&root);
+            assert!(kept.create().is_ok());
+            assert!(File::create(&kept.path().join("file")).write(&[0u8, ..100]).is_ok());
+            let dest = kept.retain(1024 * 1024);
+            assert!(dest.is_ok());
+            drop(kept);
+            let dest = dest.unwrap();
+            assert!(dest.join("file").exists());
+            assert_eq!(dest.dir_path(), root.join(RETAINED_DIR));
+
+            let over = Workspace::new(&root);
+            assert!(over.create().is_ok());
+            assert!(over.retain(10).is_err());
+            let path = over.path().clone();
+            drop(over);
+            assert!(!path.exists());
+
+            assert_eq!(purge_retained(&root).ok(), Some(1));
+            assert!(!dest.exists());
+        }
+
+        #[test]
+        fn existing_only_loses_added() {
+            let dir = Path::new("test/workspaces/existing");
+            let _ = fs::mkdir_recursive(&dir, USER_RWX);
+            assert!(File::create(&dir.join("main.c")).is_ok());
+            {
+                let ws = Workspace::existing(dir.clone());
+                assert!(ws.copy_in(&Path::new("test/makefile"), "makefile").is_ok());
+                assert!(dir.join("makefile").exists());
+                ws.remove_on_drop("a.out");
+            }
+            assert!(!dir.join("makefile").exists());
+            assert!(dir.join("main.c").exists());
+        }
+    }
+}
+
+pub mod github {
+    extern crate github;
+
+    use std::io;
+    use std::io::{Command, IoResult, IoError};
+
+    use self::github::notification::PushNotification;
+    use self::github::clone_url::CloneUrl;
+
+    use super::{WholeBuildable, ToWholeBuildable, CommandLog, run_logged_commands, now_millis,
+                assignment_config};
+    use super::Stage::SetupEnv;
+    use super::testing::TestingRequest;
+    use super::workspace::{Workspace, retention};
+
+    use assignment::AssignmentConfig;
+    use mirror::MirrorCache;
+    use util::MessagingUnwrapper;
+
+    /// Where workspaces for builds go, relative to the worker's
+    /// working directory.
+    pub static BUILD_ROOT: &'static str = "build_test";
+
+    /// Only something which looks like a SHA is accepted, since it
+    /// ends up on a command line.  All zeroes isn't a commit; it's
+    /// what a push which deletes a branch has.
+    pub fn valid_sha(sha: &str) -> Option<String> {
+        if sha.len() == 40 && sha.chars().all(|c| c.to_digit(16).is_some()) &&
+            sha.chars().any(|c| c != '0') {
+            Some(sha.to_string())
+        } else {
+            None
+        }
+    }
+
+    pub struct GitHubRequest {
+        branch: String,
+        /// If set, exactly this commit is built, rather than whatever
+        /// the branch points to at clone time
+        sha: Option<String>,
+        clone_url: CloneUrl,
+        /// If set, clones go through a local mirror
+        mirrors: Option<MirrorCache>,
+        retain_on_failure: bool,
+        testing_req: TestingRequest,
+    }
+
+    impl GitHubRequest {
+        /// Each build is isolated in its own directory under `build_root`.
+        pub fn new(pn: &PushNotification,
+                   build_root: Path,
+                   config: &AssignmentConfig) -> GitHubRequest {
+            GitHubRequest {
+                branch: pn.branch.clone(),
+                sha: None,
+                clone_url: pn.clone_url.clone(),
+                mirrors: MirrorCache::for_worker(&build_root),
+                retain_on_failure: config.retain_on_failure || retention().all,
+                testing_req: TestingRequest::for_assignment(
+                    Workspace::new(&build_root), config)
+            }
+        }
+
+        pub fn at_commit(mut self, sha: Option<String>) -> GitHubRequest {
+            self.sha = sha;
+            self
+        }
+
+        pub fn with_config_error(mut self, error: Option<IoError>) -> GitHubRequest {
+            self.testing_req = self.testing_req.with_config_error(error);
+            self
+        }
+
+        /// Clones from `source` into the workspace, and checks out the
+        /// commit to build.  Clones from a local mirror copy objects,
+        /// rather than hard linking them, so that handing the workspace
+        /// over to the job user doesn't hand over the mirror too.
+        fn clone_commands(&self, source: &str) -> Vec<Command> {
+            let mut clone = Command::new("git");
+            clone.arg("clone").arg("-q").arg("--no-hardlinks");
+            clone.arg("-b").arg(self.branch.as_slice());
+            clone.arg(source);
+            clone.arg(".");
+            clone.cwd(self.testing_req.dir());
+
+            match self.sha {
+                Some(ref sha) => {
+                    let mut checkout = Command::new("git");
+                    checkout.arg("checkout").arg("-q").arg(sha.as_slice());
+                    checkout.cwd(self.testing_req.dir());
+                    vec!(clone, checkout)
+                },
+                None => vec!(clone)
+            }
+        }
+    }
+    
+    /// Everything but setting up the workspace, and whether it's kept,
+    /// is as the assignment says.
+    impl WholeBuildable for GitHubRequest {
+        fn delegate<'a>(&'a self) -> Option<&'a (WholeBuildable + 'a)> {
+            Some(&self.testing_req as &WholeBuildable)
+        }
+
+        fn env_commands(&self) -> Vec<Command> {
+            self.clone_commands(self.clone_url.url.serialize().as_slice())
+        }
+
+        fn setup_env(&self, logs: &mut Vec<CommandLog>) -> IoResult<()> {
+            try!(self.testing_req.check_config());
+            try!(self.testing_req.workspace().create());
+            let url = self.clone_url.url.serialize();
+            try!(clone_via(&self.mirrors, url.as_slice(), self.env_timeout(),
+                           |source| self.clone_commands(source), logs));
+            self.testing_req.setup_env(logs)
+        }
+
+        fn retain_workspace(&self) -> Option<Path> {
+            if self.retain_on_failure {
+                self.testing_req.retain(retention().budget)
+            } else {
+                None
+            }
+        }
+    }
+
+    /// Runs the commands which `clone` makes for cloning from the given
+    /// source.  That's the mirror of `url`, brought up to date first,
+    /// if there are mirrors, or `url` itself otherwise.  Updating the
+    /// mirror and cloning from it share the timeout, rather than each
+    /// getting all of it.
+    fn clone_via(mirrors: &Option<MirrorCache>, url: &str, timeout: Option<u64>,
+                 clone: |&str| -> Vec<Command>, logs: &mut Vec<CommandLog>) -> IoResult<()> {
+        let deadline = timeout.map(|t| now_millis() + t);
+        let remaining = || deadline.map(|d| d.saturating_sub(now_millis()));
+        let commands =
+            match *mirrors {
+                Some(ref cache) => {
+                    let mirror = try!(cache.update(url, remaining(), logs));
+                    clone(mirror.as_str().unwrap_msg(line!()))
+                },
+                None => clone(url)
+            };
+        let left = remaining();
+        if left == Some(0) {
+            return Err(
+                IoError {
+                    kind: io::TimedOut,
+                    desc: "Ran out of time for cloning",
+                    detail: None
+                });
+        }
+        run_logged_commands(&commands, left, SetupEnv, logs)
+    }
+
+    impl ToWholeBuildable<GitHubRequest> for PushNotification {
+        /// Repositories are named after the assignment they're for.
+        /// A notification alone doesn't say which commit was pushed, so
+        /// this builds the branch.
+        fn to_whole_buildable(&self) -> GitHubRequest {
+            let (config, error) = assignment_config(self.clone_url.project_name());
+            GitHubRequest::new(self, Path::new(BUILD_ROOT), &config).with_config_error(error)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::io;
+        use std::io::{fs, Command, USER_RWX};
+        use std::os;
+
+        use super::{valid_sha, clone_via};
+        use super::super::now_millis;
+
+        use mirror::MirrorCache;
+        use util::MessagingUnwrapper;
+
+        #[test]
+        fn accepts_sha() {
+            let sha = "4fa849d0c9a3a1b9e3e1a5d6c1f0e2b3a4c5d6e7";
+            assert_eq!(valid_sha(sha), Some(sha.to_string()));
+        }
+
+        #[test]
+        fn rejects_non_sha() {
+            assert_eq!(valid_sha(""), None);
+            assert_eq!(valid_sha("--upload-pack=x"), None);
+            assert_eq!(valid_sha("0000000000000000000000000000000000000000"), None);
+        }
+
+        #[test]
+        fn mirror_update_and_clone_share_the_timeout() {
+            let repo = os::getcwd().join("test/workspaces/mirror_origin");
+            let _ = fs::rmdir_recursive(&repo);
+            let _ = fs::mkdir_recursive(&repo, USER_RWX);
+            let git = Command::new("sh").arg("-c")
+                .arg("git init -q && git -c user.name=gradr -c user.email=gradr@localhost \
+                          commit -q --allow-empty -m submission")
+                .cwd(&repo)
+                .status();
+            assert!(git.unwrap().success());
+
+            let dir = Path::new("test/mirrors/deadline");
+            let _ = fs::rmdir_recursive(&dir);
+            let cache = Some(MirrorCache::new(dir, 1024 * 1024 * 1024));
+            let url = repo.as_str().unwrap_msg(line!());
+            let mut logs = Vec::new();
+            let mirror =
+                match cache {
+                    Some(ref c) => c.update(url, None, &mut logs).unwrap_msg(line!()),
+                    None => unreachable!()
+                };
+            // from now on, updating the mirror takes most of the timeout
+            let slow = Command::new("git")
+                .arg("--git-dir").arg(mirror.as_str().unwrap_msg(line!()))
+                .arg("config").arg("remote.origin.uploadpack").arg("sleep 1; git-upload-pack")
+                .status();
+            assert!(slow.unwrap().success());
+
+            let start = now_millis();
+            let res = clone_via(&cache, url, Some(1500), |_| {
+                let mut clone = Command::new("sleep");
+                clone.arg("1");
+                vec!(clone)
+            }, &mut Vec::new());
+            assert_eq!(res.err().map(|e| e.kind), Some(io::TimedOut));
+            assert!(now_millis() - start < 2000);
+        }
+    }
+}
+
+pub mod local {
+    use std::default::Default;
+    use std::io::{Command, IoResult, IoError};
+    use std::io::fs::PathExtensions;
+    use std::os;
+
+    use super::{WholeBuildable, ToWholeBuildable, CommandLine, CommandLog};
+    use super::github::BUILD_ROOT;
+    use super::testing::TestingRequest;
+    use super::workspace::{Workspace, retention};
+
+    use assignment::AssignmentConfig;
+    use util::MessagingUnwrapper;
+
+    use self::LocalSource::{Directory, Archive, Bundle};
+
+    /// A submission which is already on the worker, rather than on
+    /// GitHub; e.g., from an offline exam or an LMS upload.  Paths are
+    /// absolute, since unpacking happens in the workspace.
+    #[deriving(Show, PartialEq, Clone)]
+    pub enum LocalSource {
+        Directory(Path),
+        /// A `.tar.gz`, `.tgz` or `.zip`
+        Archive(Path),
+        /// Made with `git bundle`; whatever its HEAD is gets built
+        Bundle(Path)
+    }
+
+    /// Where submissions are copied to, to be unpacked from, next to
+    /// the workspaces; each worker has its own directory under it.
+    pub static SOURCE_ROOT: &'static str = "sources";
+
+    /// Where that copy is inside of the sandbox.
+    pub static SANDBOX_SOURCE_DIR: &'static str = "/gradr_source";
+
+    static DIRECTORY_SCHEME: &'static str = "dir://";
+    static ARCHIVE_SCHEME: &'static str = "archive://";
+    static BUNDLE_SCHEME: &'static str = "bundle://";
+
+    fn is_zip(path: &Path) -> bool {
+        path.extension_str() == Some("zip")
+    }
+
+    fn strip_scheme<'a>(url: &'a str, scheme: &str) -> Option<&'a str> {
+        if url.starts_with(scheme) {
+            Some(url.slice_from(scheme.len()))
+        } else {
+            None
+        }
+    }
+
+    impl LocalSource {
+        /// Figures out what kind of submission `path` is from its
+        /// extension, or from it being a directory.
+        pub fn from_path(path: &Path) -> Option<LocalSource> {
+            let path = os::getcwd().join(path);
+            let name = path.filename_str().unwrap_or("");
+            if path.is_dir() {
+                Some(Directory(path))
+            } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") || is_zip(&path) {
+                Some(Archive(path))
+            } else if name.ends_with(".bundle") {
+                Some(Bundle(path))
+            } else {
+                None
+            }
+        }
+
+        pub fn path<'a>(&'a self) -> &'a Path {
+            match *self {
+                Directory(ref p) | Archive(ref p) | Bundle(ref p) => p
+            }
+        }
+
+        /// How this is stored where a clone URL would otherwise go.
+        pub fn to_url(&self) -> String {
+            let scheme =
+                match *self {
+                    Directory(_) => DIRECTORY_SCHEME,
+                    Archive(_) => ARCHIVE_SCHEME,
+                    Bundle(_) => BUNDLE_SCHEME
+                };
+            format!("{}{}", scheme, self.path().as_str().unwrap_msg(line!()))
+        }
+
+        /// `None` if `url` isn't one made by `to_url`.
+        pub fn from_url(url: &str) -> Option<LocalSource> {
+            strip_scheme(url, DIRECTORY_SCHEME).map(|p| Directory(Path::new(p)))
+                .or_else(|| strip_scheme(url, ARCHIVE_SCHEME).map(|p| Archive(Path::new(p))))
+                .or_else(|| strip_scheme(url, BUNDLE_SCHEME).map(|p| Bundle(Path::new(p))))
+        }
+
+        /// Commands which copy the submission into `dir`, where anyone
+        /// can read it.  `dir` only belongs to the worker.
+        pub fn copy_commands(&self, dir: &Path) -> Vec<Command> {
+            let mut copy = Command::new("cp");
+            copy.arg("-R").arg(self.path().as_str().unwrap_msg(line!()));
+            copy.arg(dir.as_str().unwrap_msg(line!()));
+            let mut chmod = Command::new("chmod");
+            chmod.arg("-R").arg("go+rX").arg(dir.as_str().unwrap_msg(line!()));
+            vec!(copy, chmod)
+        }
+
+        /// What puts the submission into the current directory, which is
+        /// empty, given `dir`, where `copy_commands` copied it to.
+        /// Ownership and permissions aren't carried over from archives.
+        pub fn unpack_line(&self, dir: &Path) -> CommandLine {
+            let copy = dir.join(self.path().filename().unwrap_msg(line!()));
+            let path = copy.as_str().unwrap_msg(line!());
+            let contents = format!("{}/.", path);
+            match *self {
+                Directory(_) => CommandLine::new("cp", &["-R", contents.as_slice(), "."]),
+                Archive(ref p) if is_zip(p) => CommandLine::new("unzip", &["-q", path, "-d", "."]),
+                Archive(_) => CommandLine::new("tar", &["-xzf", path, "--no-same-owner",
+                                                        "--no-same-permissions", "-C", "."]),
+                Bundle(_) => CommandLine::new("git", &["clone", "-q", path, "."])
+            }
+        }
+    }
+
+    pub struct LocalRequest {
+        source: LocalSource,
+        retain_on_failure: bool,
+        testing_req: TestingRequest
+    }
+
+    impl LocalRequest {
+        /// Each build is isolated in its own directory under `build_root`.
+        pub fn new(source: LocalSource,
+                   build_root: Path,
+                   config: &AssignmentConfig) -> LocalRequest {
+            LocalRequest {
+                source: source,
+                retain_on_failure: config.retain_on_failure || retention().all,
+                testing_req: TestingRequest::for_assignment(
+                    Workspace::new(&build_root), config)
+            }
+        }
+
+        pub fn with_config_error(mut self, error: Option<IoError>) -> LocalRequest {
+            self.testing_req = self.testing_req.with_config_error(error);
+            self
+        }
+    }
+
+    /// Everything but setting up the workspace, and whether it's kept,
+    /// is as the assignment says.
+    impl WholeBuildable for LocalRequest {
+        fn delegate<'a>(&'a self) -> Option<&'a (WholeBuildable + 'a)> {
+            Some(&self.testing_req as &WholeBuildable)
+        }
+
+        fn setup_env(&self, logs: &mut Vec<CommandLog>) -> IoResult<()> {
+            try!(self.testing_req.check_config());
+            try!(self.testing_req.workspace().create());
+            try!(self.testing_req.unpack(&self.source, self.env_timeout(), logs));
+            self.testing_req.setup_env(logs)
+        }
+
+        fn retain_workspace(&self) -> Option<Path> {
+            if self.retain_on_failure {
+                self.testing_req.retain(retention().budget)
+            } else {
+                None
+            }
+        }
+    }
+
+    impl ToWholeBuildable<LocalRequest> for LocalSource {
+        fn to_whole_buildable(&self) -> LocalRequest {
+            LocalRequest::new(self.clone(), Path::new(BUILD_ROOT), &Default::default())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::default::Default;
+        use std::io::{fs, Command, IoError, InvalidInput, USER_RWX};
+        use std::os;
+
+        use super::{LocalSource, LocalRequest};
+        use super::LocalSource::{Directory, Archive, Bundle};
+        use super::super::{WholeBuildable, Score};
+        use super::super::BuildOutcome::{TestSuccess, SetupEnvFailure};
+        use super::super::Overlay::Files;
+        use super::super::Stage::{SetupEnv, Build};
+
+        use assignment::AssignmentConfig;
+        use overlay::Tampering;
+
+        #[test]
+        fn kinds_from_paths() {
+            let cwd = os::getcwd();
+            assert_eq!(LocalSource::from_path(&Path::new("test/compile_success")),
+                       Some(Directory(cwd.join("test/compile_success"))));
+            assert_eq!(LocalSource::from_path(&Path::new("upload.tar.gz")),
+                       Some(Archive(cwd.join("upload.tar.gz"))));
+            assert_eq!(LocalSource::from_path(&Path::new("upload.zip")),
+                       Some(Archive(cwd.join("upload.zip"))));
+            assert_eq!(LocalSource::from_path(&Path::new("repo.bundle")),
+                       Some(Bundle(cwd.join("repo.bundle"))));
+            assert_eq!(LocalSource::from_path(&Path::new("notes.txt")), None);
+        }
+
+        #[test]
+        fn url_round_trip() {
+            let source = Archive(Path::new("/uploads/exam 1.tar.gz"));
+            assert_eq!(LocalSource::from_url(source.to_url().as_slice()), Some(source));
+            assert_eq!(LocalSource::from_url("https://github.com/a/b.git"), None);
+        }
+
+        fn expect_whole_build(source: LocalSource) {
+            let root = Path::new("test/workspaces/local");
+            let _ = fs::mkdir_recursive(&root, USER_RWX);
+            let r = LocalRequest::new(source, root, &Default::default());
+            match r.whole_build().outcome {
+                TestSuccess(_, score) => {
+                    assert_eq!(score, Score { earned: 1.0, possible: 2.0 });
+                },
+                _ => { assert!(false); }
+            };
+        }
+
+        #[test]
+        fn builds_directory() {
+            let source = LocalSource::from_path(&Path::new("test/test_whole_build"));
+            expect_whole_build(source.unwrap());
+        }
+
+        #[test]
+        fn builds_archive() {
+            let _ = fs::mkdir_recursive(&Path::new("test/workspaces"), USER_RWX);
+            let archive = Path::new("test/workspaces/test_whole_build.tar.gz");
+            let tar = Command::new("tar")
+                .arg("-czf").arg(archive.as_str().unwrap())
+                .arg("-C").arg("test/test_whole_build").arg("main.c")
+                .status();
+            assert!(tar.unwrap().success());
+            expect_whole_build(LocalSource::from_path(&archive).unwrap());
+        }
+
+        #[test]
+        fn builds_zip() {
+            let _ = fs::mkdir_recursive(&Path::new("test/workspaces"), USER_RWX);
+            let archive = Path::new("test/workspaces/test_whole_build.zip");
+            let _ = fs::unlink(&archive);
+            let zip = Command::new("zip")
+                .arg("-q").arg("-j").arg(archive.as_str().unwrap())
+                .arg("test/test_whole_build/main.c")
+                .status();
+            assert!(zip.unwrap().success());
+            expect_whole_build(LocalSource::from_path(&archive).unwrap());
+        }
+
+        #[test]
+        fn builds_bundle() {
+            let repo = Path::new("test/workspaces/bundle_repo");
+            let _ = fs::rmdir_recursive(&repo);
+            let _ = fs::mkdir_recursive(&repo, USER_RWX);
+            let bundle = Path::new("test/workspaces/test_whole_build.bundle");
+            let _ = fs::unlink(&bundle);
+            let git = Command::new("sh").arg("-c")
+                .arg("cp ../../test_whole_build/main.c . && git init -q && git add main.c && \
+                      git -c user.name=gradr -c user.email=gradr@localhost \
+                          commit -q -m submission && \
+                      git bundle create ../test_whole_build.bundle --all")
+                .cwd(&repo)
+                .status();
+            assert!(git.unwrap().success());
+            expect_whole_build(LocalSource::from_path(&bundle).unwrap());
+        }
+
+        #[test]
+        fn broken_config_fails_setup() {
+            let root = Path::new("test/workspaces/local");
+            let _ = fs::mkdir_recursive(&root, USER_RWX);
+            let error = IoError {
+                kind: InvalidInput,
+                desc: "Bad assignment configuration",
+                detail: None
+            };
+            let source = LocalSource::from_path(&Path::new("test/compile_success")).unwrap();
+            let res = LocalRequest::new(source, root, &Default::default())
+                .with_config_error(Some(error.clone()))
+                .whole_build();
+            match res.outcome {
+                SetupEnvFailure(ref e) => assert_eq!(*e, error),
+                _ => { assert!(false); }
+            };
+        }
+
+        #[test]
+        fn flags_tampering() {
+            let root = Path::new("test/workspaces/local");
+            let _ = fs::mkdir_recursive(&root, USER_RWX);
+            let mut config: AssignmentConfig = Default::default();
+            config.overlay = Files(Path::new("test/instructor_files"));
+            let source = LocalSource::from_path(&Path::new("test/tampering")).unwrap();
+
+            let res = LocalRequest::new(source, root, &config).whole_build();
+            match res.outcome {
+                // the instructor's makefile ran, rather than the student's
+                TestSuccess(u, _) => {
+                    assert_eq!(u.len(), 3);
+                    assert!(u.contains_key(&"hidden".to_string()));
+                },
+                _ => { assert!(false); }
+            };
+            assert_eq!(res.tampering,
+                       vec!(Tampering { file: "makefile".to_string(), stage: SetupEnv },
+                            Tampering { file: "include/grader.h".to_string(), stage: Build }));
+        }
+    }
+}
+
+pub mod submission {
+    use super::{WholeBuildable, ToWholeBuildable, assignment_config};
+    use super::github::{GitHubRequest, BUILD_ROOT};
+    use super::local::LocalRequest;
+
+    use database::PendingBuild;
+    use database::Source;
+
+    use self::BuildRequest::{GitHub, Local};
+
+    /// A build of a pending submission, from wherever it came from.
+    pub enum BuildRequest {
+        GitHub(GitHubRequest),
+        Local(LocalRequest)
+    }
+
+    impl BuildRequest {
+        fn inner<'a>(&'a self) -> &'a (WholeBuildable + 'a) {
+            match *self {
+                GitHub(ref r) => r as &WholeBuildable,
+                Local(ref r) => r as &WholeBuildable
+            }
+        }
+    }
+
+    impl WholeBuildable for BuildRequest {
+        fn delegate<'a>(&'a self) -> Option<&'a (WholeBuildable + 'a)> {
+            Some(self.inner())
+        }
+    }
+
+    impl ToWholeBuildable<BuildRequest> for PendingBuild {
+        fn to_whole_buildable(&self) -> BuildRequest {
+            let (config, error) = assignment_config(self.assignment.as_slice());
+            let build_root = Path::new(BUILD_ROOT);
+            match self.source {
+                Source::GitHub(ref pn, ref sha) => {
+                    GitHub(GitHubRequest::new(pn, build_root, &config)
+                           .at_commit(sha.clone())
+                           .with_config_error(error))
+                },
+                Source::Local(ref source) => {
+                    Local(LocalRequest::new(source.clone(), build_root, &config)
+                          .with_config_error(error))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod process_tests {
+    use std::default::Default;
+    use std::io;
+    use std::io::fs;
+    use std::io::process::{Command, Process, ExitStatus};
+    use super::{run_command, run_captured, run_logged_commands, OUTPUT_CAP,
+                BuildOutcome, ResourceLimits, Limit};
+    use super::Limit::{CpuTime, FileSize};
+    use super::BuildOutcome::StageTimedOut;
+    use super::Stage::{Build, Test};
+
+    #[test]
+    fn echo_ok() {
+        assert!(run_command(&*Command::new("echo").arg("foobar"), None, ()).is_ok());
+    }
+    
+    #[test]
+    fn false_ok() {
+        assert!(run_command(&Command::new("false"), None, ()).is_err());
     }
 
     fn output_from_command(cmd: &Command) -> Vec<String> {
-        let pr = ProcessReader::new(cmd, None);
-        assert!(pr.is_ok());
-        pr.unwrap_msg(line!()).output_reader().lines()
-            .map(|line| {
-                assert!(line.is_ok());
-                line.unwrap_msg(line!()).as_slice().trim().to_string()
-            }).collect()
+        let (res, log) = run_captured(cmd, None, Test);
+        assert!(res.is_ok());
+        log.stdout.as_slice().lines()
+            .map(|line| line.trim().to_string())
+            .collect()
+    }
+            
+    #[test]
+    fn output_single_line_read() {
+        let lines = output_from_command(
+            &*Command::new("sh").arg("-c").arg("echo foobar"));
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].as_slice(), "foobar");
+    }
+
+    #[test]
+    fn output_multi_line_read() {
+        let lines = output_from_command(
+            &*Command::new("sh").arg("-c").arg("echo foo; echo bar"));
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].as_slice(), "foo");
+        assert_eq!(lines[1].as_slice(), "bar");
+    }
+
+    #[test]
+    fn stderr_captured_on_failure() {
+        let (res, log) = run_captured(
+            &*Command::new("sh").arg("-c").arg("echo oops >&2; exit 3"),
+            None,
+            Test);
+        assert_eq!(res.ok(), Some(ExitStatus(3)));
+        assert_eq!(log.exit, Some(ExitStatus(3)));
+        assert_eq!(log.stderr.as_slice(), "oops\n");
+        assert!(!log.truncated);
+    }
+
+    #[test]
+    fn output_truncated_at_cap() {
+        let (res, log) = run_captured(
+            &*Command::new("sh").arg("-c").arg("yes | head -c 1000000"),
+            None,
+            Test);
+        assert!(res.is_ok());
+        assert_eq!(log.stdout.len(), OUTPUT_CAP);
+        assert!(log.truncated);
+    }
+
+    #[test]
+    fn timeout_kills_command() {
+        let (res, log) = run_captured(
+            &*Command::new("sleep").arg("10"), Some(100), Test);
+        assert!(res.is_err());
+        assert_eq!(res.err().map(|e| e.kind), Some(io::TimedOut));
+        assert_eq!(log.exit, None);
+    }
+
+    #[test]
+    fn background_processes_killed() {
+        // without the process group being killed, the `sleep` would hold
+        // on to stdout, and this would take 100 seconds
+        let (res, log) = run_captured(
+            &*Command::new("sh").arg("-c").arg("sleep 100 & echo $!"),
+            None,
+            Test);
+        assert!(res.is_ok());
+        let pid = from_str::<i32>(log.stdout.as_slice().trim());
+        assert!(pid.is_some());
+        assert!(Process::kill(pid.unwrap(), 0).is_err());
+    }
+
+    fn run_limited(limits: &ResourceLimits, script: &str) -> Option<Limit> {
+        let (_, log) = run_captured(&limits.command("sh", &["-c", script]), None, Test);
+        limits.breached_limit(&log)
+    }
+
+    #[test]
+    fn cpu_limit_breach() {
+        let limits = ResourceLimits {
+            cpu_seconds: Some(1),
+            ..ResourceLimits::unlimited()
+        };
+        assert_eq!(run_limited(&limits, "while :; do :; done"), Some(CpuTime));
+    }
+
+    #[test]
+    fn file_size_limit_breach() {
+        let limits = ResourceLimits {
+            file_size: Some(1024 * 1024),
+            ..ResourceLimits::unlimited()
+        };
+        let path = "/tmp/gradr_file_size_limit_test";
+        assert_eq!(
+            run_limited(&limits,
+                        format!("exec head -c 2000000 /dev/zero > {}", path).as_slice()),
+            Some(FileSize));
+        let _ = fs::unlink(&Path::new(path));
+    }
+
+    #[test]
+    fn no_limit_breach() {
+        assert_eq!(run_limited(&Default::default(), "echo hello"), None);
+    }
+
+    #[test]
+    fn crashes_and_messages_are_not_breaches() {
+        let limits = ResourceLimits {
+            address_space: Some(512 * 1024 * 1024),
+            processes: Some(64),
+            ..Default::default()
+        };
+        assert_eq!(run_limited(&limits, "kill -SEGV $$"), None);
+        assert_eq!(run_limited(&limits, "kill -ABRT $$"), None);
+        // e.g., the OOM killer, or a cancelled build
+        assert_eq!(run_limited(&limits, "kill -KILL $$"), None);
+        assert_eq!(run_limited(
+            &limits, "echo 'Cannot allocate memory' >&2; echo 'Resource temporarily unavailable' >&2"),
+            None);
+    }
+
+    #[test]
+    fn timeout_covers_whole_stage() {
+        let mut sleep = Command::new("sleep");
+        sleep.arg("0.3");
+        let commands = vec!(sleep.clone(), sleep.clone(), sleep);
+        let mut logs = Vec::new();
+        let res = run_logged_commands(&commands, Some(500), Build, &mut logs);
+        assert!(res.is_err());
+        assert_eq!(logs.len(), 2);
+
+        match BuildOutcome::from_error(Build, res.err().unwrap()) {
+            StageTimedOut(Build) => (),
+            _ => { assert!(false); }
+        };
+    }
+}
+
+#[cfg(test)]
+mod parse_tests {
+    use super::TestResult::{Pass, Fail, Skipped};
+    use super::{parse_test_result, parse_line, LineParser, TestOutputParser,
+                TestOutcome, Points};
+
+    use util::MessagingUnwrapper;
+
+    #[test]
+    fn parse_test_pass() {
+        let res = parse_test_result("PASS");
+        assert!(res.is_ok());
+        assert_eq!(res.unwrap_msg(line!()), Pass);
+    }
+
+    #[test]
+    fn parse_test_fail() {
+        let res = parse_test_result("FAIL");
+        assert!(res.is_ok());
+        assert_eq!(res.unwrap_msg(line!()), Fail);
+    }
+
+    #[test]
+    fn parse_test_skip() {
+        let res = parse_test_result("SKIP");
+        assert!(res.is_ok());
+        assert_eq!(res.unwrap_msg(line!()), Skipped);
+    }
+
+    #[test]
+    fn parse_test_bad_test() {
+        let res = parse_test_result("foobar");
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn parse_valid_test_line() {
+        let res = parse_line("my test:PASS");
+        assert!(res.is_ok());
+        let (key, result) = res.unwrap_msg(line!());
+        assert_eq!(key.as_slice(), "my test");
+        assert_eq!(result.result, Pass);
+    }
+
+    #[test]
+    fn parse_partial_credit_line() {
+        let res = parse_line("sorting: 7/10");
+        assert!(res.is_ok());
+        let (key, result) = res.unwrap_msg(line!());
+        assert_eq!(key.as_slice(), "sorting");
+        assert_eq!(result.result, Fail);
+        assert_eq!(result.points, Some(Points { earned: 7.0, possible: 10.0 }));
+    }
+
+    #[test]
+    fn parse_full_credit_line() {
+        let res = parse_line("sorting:10/10");
+        assert!(res.is_ok());
+        assert_eq!(res.unwrap_msg(line!()).val1().result, Pass);
+    }
+
+    #[test]
+    fn parse_bad_points() {
+        assert!(parse_line("sorting: 7/0").is_err());
+        assert!(parse_line("sorting: 7/10/2").is_err());
+    }
+
+    #[test]
+    fn parse_invalid_test_line() {
+        assert!(parse_line("this:is:PASS").is_err());
+    }
+
+    #[test]
+    fn line_parser_skips_stray_lines() {
+        let res = LineParser::new().parse_output(
+            "compiling...\ntest1:PASS\n\nsegfault?\ntest2:FAIL\n");
+        assert!(res.is_ok());
+        let u = res.unwrap_msg(line!());
+        assert_eq!(u.len(), 2);
+        assert_eq!(u.get(&"test1".to_string()), Some(&TestOutcome::new(Pass)));
+        assert_eq!(u.get(&"test2".to_string()), Some(&TestOutcome::new(Fail)));
+    }
+
+    #[test]
+    fn strict_line_parser_rejects_stray_lines() {
+        assert!(LineParser::strict().parse_output(
+            "test1:PASS\nsegfault?\n").is_err());
+    }
+}
+
+#[cfg(test)]
+mod score_tests {
+    use std::collections::HashMap;
+
+    use super::{Rubric, Score, TestOutcome};
+    use super::TestResult::{Pass, Fail, Skipped};
+
+    fn results() -> HashMap<String, TestOutcome> {
+        let mut map = HashMap::new();
+        map.insert("a".to_string(), TestOutcome::new(Pass));
+        map.insert("b".to_string(), TestOutcome::new(Fail));
+        map.insert("c".to_string(), TestOutcome::new(Skipped));
+        map.insert("sorting".to_string(), TestOutcome::with_points(7.0, 10.0));
+        map
+    }
+
+    #[test]
+    fn uniform_score() {
+        assert_eq!(Rubric::uniform().score(&results()),
+                   Score { earned: 8.0, possible: 13.0 });
+    }
+
+    #[test]
+    fn weighted_score() {
+        let mut weights = HashMap::new();
+        weights.insert("a".to_string(), 5.0);
+        weights.insert("sorting".to_string(), 20.0);
+        weights.insert("never_ran".to_string(), 3.0);
+        let rubric = Rubric {
+            weights: weights,
+            default_weight: 0.0
+        };
+        assert_eq!(rubric.score(&results()),
+                   Score { earned: 19.0, possible: 28.0 });
+    }
+}
+
+/// Ingestion of Test Anything Protocol streams, e.g.:
+///
+/// ```text
+/// 1..3
+/// ok 1 - sorts empty list
+/// not ok 2 - sorts reversed list
+///   ---
+///   expected: [1, 2, 3]
+///   got: [3, 2, 1]
+///   ...
+/// ok 3 - handles duplicates # SKIP not implemented yet
+/// ```
+pub mod tap {
+    use std::ascii::AsciiExt;
+    use std::collections::HashMap;
+    use std::io::{IoResult, IoError, OtherIoError};
+
+    use super::{TestOutputParser, TestOutcome};
+    use super::TestResult::{Pass, Fail, Skipped, Errored};
+
+    use self::Directive::{Skip, Todo};
+
+    #[deriving(Show, PartialEq, Clone)]
+    pub enum Directive {
+        Skip(String),
+        Todo(String)
+    }
+
+    /// A single `ok`/`not ok` line, along with any YAML diagnostic
+    /// block which immediately followed it.
+    #[deriving(Show, PartialEq, Clone)]
+    pub struct TapTest {
+        pub number: uint,
+        pub ok: bool,
+        pub description: String,
+        pub directive: Option<Directive>,
+        pub diagnostics: Option<String>
+    }
+
+    impl TapTest {
+        pub fn name(&self) -> String {
+            if self.description.is_empty() {
+                format!("test {}", self.number)
+            } else {
+                self.description.clone()
+            }
+        }
+
+        /// Failing TODO tests don't count as failures, as per the
+        /// TAP specification, so they are recorded as skipped.
+        /// The diagnostics block, if any, is kept as the message.
+        pub fn outcome(&self) -> TestOutcome {
+            let (result, reason) =
+                match (self.ok, &self.directive) {
+                    (_, &Some(Skip(ref r))) => (Skipped, Some(r.clone())),
+                    (false, &Some(Todo(ref r))) => (Skipped, Some(format!("TODO {}", r))),
+                    (true, _) => (Pass, None),
+                    (false, &None) => (Fail, None)
+                };
+            let message =
+                match reason {
+                    Some(ref r) if !r.is_empty() => reason.clone(),
+                    _ => self.diagnostics.clone()
+                };
+            TestOutcome::new(result).with_message(message)
+        }
+    }
+
+    /// The parsed form of an entire TAP stream.
+    #[deriving(Show)]
+    pub struct TapOutput {
+        pub planned: Option<uint>,
+        pub tests: Vec<TapTest>
+    }
+
+    impl TapOutput {
+        /// Numbers of tests which were in the plan but never reported.
+        pub fn missing(&self) -> Vec<uint> {
+            match self.planned {
+                Some(n) => {
+                    range(1, n + 1).filter(|k| {
+                        !self.tests.iter().any(|t| t.number == *k)
+                    }).collect()
+                },
+                None => Vec::new()
+            }
+        }
+    }
+
+    pub struct TapParser;
+
+    fn bail_out(line: &str) -> IoError {
+        IoError {
+            kind: OtherIoError,
+            desc: "TAP bail out",
+            detail: Some(line.to_string())
+        }
+    }
+
+    fn leading_number(s: &str) -> (Option<uint>, &str) {
+        let end = s.char_indices()
+            .find(|&(_, c)| !(c >= '0' && c <= '9'))
+            .map(|(i, _)| i)
+            .unwrap_or(s.len());
+        if end == 0 {
+            (None, s)
+        } else {
+            (from_str(s.slice_to(end)), s.slice_from(end))
+        }
+    }
+
+    fn parse_directive(s: &str) -> Option<Directive> {
+        let s = s.trim();
+        let (word, reason) =
+            match s.find(|c: char| c.is_whitespace()) {
+                Some(i) => (s.slice_to(i), s.slice_from(i).trim()),
+                None => (s, "")
+            };
+        let word = word.trim_right_chars(':');
+        if word.eq_ignore_ascii_case("skip") || word.eq_ignore_ascii_case("skipped") {
+            Some(Skip(reason.to_string()))
+        } else if word.eq_ignore_ascii_case("todo") {
+            Some(Todo(reason.to_string()))
+        } else {
+            None
+        }
+    }
+
+    /// Parses `ok 1 - description # DIRECTIVE reason`.  The test number
+    /// is optional; if it's missing, `next_number` is used instead.
+    fn parse_test_line(line: &str, next_number: uint) -> Option<TapTest> {
+        let (ok, rest) =
+            if line.starts_with("not ok") {
+                (false, line.slice_from(6))
+            } else if line.starts_with("ok") {
+                (true, line.slice_from(2))
+            } else {
+                return None;
+            };
+        if !rest.is_empty() && !rest.starts_with(" ") && !rest.starts_with("\t") {
+            return None;
+        }
+
+        let (number, rest) = leading_number(rest.trim_left());
+        let (description, directive) =
+            match rest.find('#') {
+                Some(i) => (rest.slice_to(i), parse_directive(rest.slice_from(i + 1))),
+                None => (rest, None)
+            };
+        let description = description.trim().trim_left_chars('-').trim();
+
+        Some(TapTest {
+            number: number.unwrap_or(next_number),
+            ok: ok,
+            description: description.to_string(),
+            directive: directive,
+            diagnostics: None
+        })
+    }
+
+    fn parse_plan(line: &str) -> Option<uint> {
+        if line.starts_with("1..") {
+            leading_number(line.slice_from(3)).val0()
+        } else {
+            None
+        }
+    }
+
+    impl TapParser {
+        pub fn parse_stream(&self, output: &str) -> IoResult<TapOutput> {
+            let mut planned = None;
+            let mut tests: Vec<TapTest> = Vec::new();
+            // lines of the YAML block currently being read, if any
+            let mut yaml: Option<Vec<String>> = None;
+
+            for raw in output.lines() {
+                let trimmed = raw.trim();
+                let indented = raw.starts_with(" ") || raw.starts_with("\t");
+
+                match yaml.take() {
+                    Some(mut block) => {
+                        if trimmed == "..." {
+                            match tests.last_mut() {
+                                Some(t) => t.diagnostics = Some(block.connect("\n")),
+                                None => ()
+                            }
+                        } else {
+                            block.push(raw.trim_right().to_string());
+                            yaml = Some(block);
+                        }
+                        continue;
+                    },
+                    None => ()
+                }
+
+                if indented && trimmed == "---" && !tests.is_empty() {
+                    yaml = Some(Vec::new());
+                } else if indented || trimmed.is_empty() || trimmed.starts_with("#") {
+                    // subtests and comments are not recorded
+                } else if trimmed.starts_with("Bail out!") {
+                    return Err(bail_out(trimmed));
+                } else if trimmed.starts_with("1..") {
+                    planned = parse_plan(trimmed);
+                } else {
+                    match parse_test_line(trimmed, tests.len() + 1) {
+                        Some(t) => tests.push(t),
+                        None => () // e.g., `TAP version 13`, or stray output
+                    }
+                }
+            }
+
+            Ok(TapOutput {
+                planned: planned,
+                tests: tests
+            })
+        }
+    }
+
+    impl TestOutputParser for TapParser {
+        /// Tests which were planned but never reported are recorded
+        /// as errors, as the harness likely crashed partway through.
+        fn parse_output(&self, output: &str) -> IoResult<HashMap<String, TestOutcome>> {
+            let parsed = try!(self.parse_stream(output));
+            let mut map = HashMap::new();
+            for k in parsed.missing().into_iter() {
+                map.insert(
+                    format!("test {}", k),
+                    TestOutcome::new(Errored).with_message(
+                        Some("Planned but never reported".to_string())));
+            }
+            for t in parsed.tests.iter() {
+                map.insert(t.name(), t.outcome());
+            }
+            Ok(map)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::{TapParser, parse_test_line};
+        use super::Directive::{Skip, Todo};
+        use super::super::TestOutputParser;
+        use super::super::TestResult::{Pass, Skipped, Errored};
+
+        use util::MessagingUnwrapper;
+
+        #[test]
+        fn parse_ok_line() {
+            let t = parse_test_line("ok 1 - sorts empty list", 1).unwrap_msg(line!());
+            assert!(t.ok);
+            assert_eq!(t.number, 1);
+            assert_eq!(t.description.as_slice(), "sorts empty list");
+            assert_eq!(t.directive, None);
+        }
+
+        #[test]
+        fn parse_not_ok_without_number() {
+            let t = parse_test_line("not ok reversed", 4).unwrap_msg(line!());
+            assert!(!t.ok);
+            assert_eq!(t.number, 4);
+            assert_eq!(t.description.as_slice(), "reversed");
+        }
+
+        #[test]
+        fn parse_directives() {
+            let t = parse_test_line("ok 3 - dups # SKIP not done", 3).unwrap_msg(line!());
+            assert_eq!(t.directive, Some(Skip("not done".to_string())));
+            let t = parse_test_line("not ok 4 # todo later", 4).unwrap_msg(line!());
+            assert_eq!(t.directive, Some(Todo("later".to_string())));
+            assert_eq!(t.outcome().result, Skipped);
+        }
+
+        #[test]
+        fn parse_non_test_line() {
+            assert!(parse_test_line("okay then", 1).is_none());
+            assert!(parse_test_line("TAP version 13", 1).is_none());
+        }
+
+        #[test]
+        fn parse_stream_with_diagnostics() {
+            let out = TapParser.parse_stream(
+                "TAP version 13\n1..2\nok 1 - a\nnot ok 2 - b\n  ---\n  got: 3\n  ...\n");
+            assert!(out.is_ok());
+            let out = out.unwrap_msg(line!());
+            assert_eq!(out.planned, Some(2));
+            assert_eq!(out.tests.len(), 2);
+            assert_eq!(out.tests[0].diagnostics, None);
+            assert_eq!(out.tests[1].diagnostics, Some("  got: 3".to_string()));
+            assert_eq!(out.tests[1].outcome().message, Some("  got: 3".to_string()));
+        }
+
+        #[test]
+        fn missing_planned_tests_error() {
+            let res = TapParser.parse_output("1..3\nok 1 - a\n");
+            assert!(res.is_ok());
+            let u = res.unwrap_msg(line!());
+            assert_eq!(u.len(), 3);
+            let result_of = |name: &str| u.get(&name.to_string()).map(|o| o.result.clone());
+            assert_eq!(result_of("a"), Some(Pass));
+            assert_eq!(result_of("test 2"), Some(Errored));
+            assert_eq!(result_of("test 3"), Some(Errored));
+        }
+
+        #[test]
+        fn bail_out_is_error() {
+            assert!(TapParser.parse_output("1..2\nok 1\nBail out! no db\n").is_err());
+        }
+    }
+}
+
+/// Ingestion of JUnit-style XML reports, as produced by the likes of
+/// Gradle, pytest (`--junitxml`), and gtest (`--gtest_output=xml`).
+/// Unlike the other parsers, the output of the test command is ignored;
+/// results are instead read from report files left in the build
+/// directory once the test command finishes.  Any reports there before
+/// it runs (committed ones, or leftovers) are removed first, and
+/// symlinks are never followed, so what's read was written by the test
+/// command, and is inside of the workspace.
+pub mod junit {
+    use std::collections::HashMap;
+    use std::io::{File, IoResult, IoError, OtherIoError, FileNotFound};
+    use std::io::{TypeFile, TypeDirectory, TypeSymlink};
+    use std::io::fs;
+    use std::io::fs::PathExtensions;
+
+    use super::{TestOutputParser, TestOutcome, TestResult};
+
+    use self::Outcome::{Passed, Failed, Errored, Skipped};
+    use self::Token::{Start, End, Text};
+
+    #[deriving(Show, PartialEq, Clone)]
+    pub enum Outcome {
+        Passed,
+        Failed(Option<String>),
+        Errored(Option<String>),
+        Skipped(Option<String>)
     }
-            
-    #[test]
-    fn output_single_line_read() {
-        let lines = output_from_command(
-            &*Command::new("sh").arg("-c").arg("echo foobar"));
-        assert_eq!(lines.len(), 1);
-        assert_eq!(lines[0].as_slice(), "foobar");
+
+    /// A single `<testcase>` element.
+    #[deriving(Show, PartialEq, Clone)]
+    pub struct JUnitCase {
+        pub name: String,
+        pub classname: String,
+        /// Duration in seconds, if the report included one.
+        pub time: Option<f64>,
+        pub outcome: Outcome
     }
 
-    #[test]
-    fn output_multi_line_read() {
-        let lines = output_from_command(
-            &*Command::new("sh").arg("-c").arg("echo foo; echo bar"));
-        assert_eq!(lines.len(), 2);
-        assert_eq!(lines[0].as_slice(), "foo");
-        assert_eq!(lines[1].as_slice(), "bar");
+    impl JUnitCase {
+        /// Test names are qualified by their class, as different suites
+        /// commonly reuse the same test names.
+        pub fn qualified_name(&self) -> String {
+            if self.classname.is_empty() {
+                self.name.clone()
+            } else {
+                format!("{}.{}", self.classname, self.name)
+            }
+        }
+
+        pub fn message(&self) -> Option<String> {
+            match self.outcome {
+                Passed => None,
+                Failed(ref m) | Errored(ref m) | Skipped(ref m) => m.clone()
+            }
+        }
+
+        pub fn result(&self) -> TestResult {
+            match self.outcome {
+                Passed => TestResult::Pass,
+                Failed(_) => TestResult::Fail,
+                Errored(_) => TestResult::Errored,
+                Skipped(_) => TestResult::Skipped
+            }
+        }
+
+        pub fn to_outcome(&self) -> TestOutcome {
+            TestOutcome::new(self.result())
+                .with_message(self.message())
+                .with_duration(self.time)
+        }
+    }
+
+    fn malformed(what: &str) -> IoError {
+        IoError {
+            kind: OtherIoError,
+            desc: "Malformed JUnit XML",
+            detail: Some(what.to_string())
+        }
+    }
+
+    struct Element {
+        name: String,
+        attrs: HashMap<String, String>,
+        self_closing: bool
+    }
+
+    impl Element {
+        fn attr(&self, name: &str) -> Option<String> {
+            self.attrs.get(&name.to_string()).map(|s| s.clone())
+        }
+    }
+
+    enum Token {
+        Start(Element),
+        End(String),
+        Text(String)
+    }
+
+    fn unescape(s: &str) -> String {
+        s.replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&")
+    }
+
+    /// Finds the `>` which ends the tag at the start of `s`, skipping
+    /// over any which appear in quoted attribute values.
+    fn tag_end(s: &str) -> Option<uint> {
+        let mut quote = None;
+        for (i, c) in s.char_indices() {
+            match (quote, c) {
+                (None, '"') | (None, '\'') => quote = Some(c),
+                (Some(q), _) if q == c => quote = None,
+                (None, '>') => return Some(i),
+                _ => ()
+            }
+        }
+        None
+    }
+
+    fn parse_attrs(mut s: &str) -> IoResult<HashMap<String, String>> {
+        let mut attrs = HashMap::new();
+        loop {
+            s = s.trim_left();
+            if s.is_empty() {
+                return Ok(attrs);
+            }
+            let eq = try!(s.find('=').ok_or(malformed(s)));
+            let key = s.slice_to(eq).trim().to_string();
+            s = s.slice_from(eq + 1).trim_left();
+            let quote = try!(s.chars().next().ok_or(malformed(s)));
+            if quote != '"' && quote != '\'' {
+                return Err(malformed(s));
+            }
+            s = s.slice_from(1);
+            let close = try!(s.find(quote).ok_or(malformed(s)));
+            attrs.insert(key, unescape(s.slice_to(close)));
+            s = s.slice_from(close + 1);
+        }
+    }
+
+    fn parse_tag(tag: &str) -> IoResult<Token> {
+        if tag.starts_with("/") {
+            return Ok(End(tag.slice_from(1).trim().to_string()));
+        }
+        let self_closing = tag.ends_with("/");
+        let body = if self_closing { tag.slice_to(tag.len() - 1) } else { tag };
+        let body = body.trim();
+        let (name, rest) =
+            match body.find(|c: char| c.is_whitespace()) {
+                Some(i) => (body.slice_to(i), body.slice_from(i)),
+                None => (body, "")
+            };
+        Ok(Start(Element {
+            name: name.to_string(),
+            attrs: try!(parse_attrs(rest)),
+            self_closing: self_closing
+        }))
+    }
+
+    /// Just enough of XML to read test reports: elements, attributes,
+    /// text, CDATA, comments, and the prolog.  Namespaces and DTDs are
+    /// not interpreted.
+    fn tokenize(xml: &str) -> IoResult<Vec<Token>> {
+        let mut tokens = Vec::new();
+        let mut rest = xml;
+        loop {
+            let lt = match rest.find('<') {
+                Some(i) => i,
+                None => {
+                    if !rest.trim().is_empty() {
+                        tokens.push(Text(unescape(rest)));
+                    }
+                    return Ok(tokens);
+                }
+            };
+            if !rest.slice_to(lt).trim().is_empty() {
+                tokens.push(Text(unescape(rest.slice_to(lt))));
+            }
+            rest = rest.slice_from(lt);
+
+            if rest.starts_with("<!--") {
+                let end = try!(rest.find_str("-->").ok_or(malformed("unterminated comment")));
+                rest = rest.slice_from(end + 3);
+            } else if rest.starts_with("<![CDATA[") {
+                let end = try!(rest.find_str("]]>").ok_or(malformed("unterminated CDATA")));
+                tokens.push(Text(rest.slice(9, end).to_string()));
+                rest = rest.slice_from(end + 3);
+            } else if rest.starts_with("<?") || rest.starts_with("<!") {
+                let end = try!(rest.find('>').ok_or(malformed("unterminated declaration")));
+                rest = rest.slice_from(end + 1);
+            } else {
+                let end = try!(tag_end(rest).ok_or(malformed("unterminated tag")));
+                tokens.push(try!(parse_tag(rest.slice(1, end))));
+                rest = rest.slice_from(end + 1);
+            }
+        }
+    }
+
+    fn new_case(e: &Element) -> JUnitCase {
+        JUnitCase {
+            name: e.attr("name").unwrap_or(String::new()),
+            classname: e.attr("classname").unwrap_or(String::new()),
+            time: e.attr("time").and_then(|t| from_str(t.as_slice())),
+            outcome: Passed
+        }
+    }
+
+    /// Gets all the test cases out of a single report.  Both a bare
+    /// `<testsuite>` and `<testsuites>` wrapping several are accepted,
+    /// as only `<testcase>` elements are looked at.
+    pub fn parse_report(xml: &str) -> IoResult<Vec<JUnitCase>> {
+        let mut cases = Vec::new();
+        let mut current: Option<JUnitCase> = None;
+        // set while inside a <failure> etc. without a `message` attribute
+        let mut message: Option<String> = None;
+
+        for token in try!(tokenize(xml)).into_iter() {
+            match token {
+                Start(ref e) if e.name.as_slice() == "testcase" => {
+                    let case = new_case(e);
+                    if e.self_closing {
+                        cases.push(case);
+                    } else {
+                        current = Some(case);
+                    }
+                },
+                Start(ref e) if current.is_some() => {
+                    let msg = e.attr("message");
+                    let outcome =
+                        match e.name.as_slice() {
+                            "failure" => Failed(msg.clone()),
+                            "error" => Errored(msg.clone()),
+                            "skipped" => Skipped(msg.clone()),
+                            _ => continue
+                        };
+                    current.as_mut().map(|c| c.outcome = outcome);
+                    if msg.is_none() && !e.self_closing {
+                        message = Some(String::new());
+                    }
+                },
+                Text(t) => {
+                    match message {
+                        Some(ref mut m) => m.push_str(t.as_slice()),
+                        None => ()
+                    }
+                },
+                End(ref name) if name.as_slice() == "testcase" => {
+                    match current.take() {
+                        Some(c) => cases.push(c),
+                        None => return Err(malformed("unexpected </testcase>"))
+                    }
+                },
+                End(_) => {
+                    match (message.take(), current.as_mut()) {
+                        (Some(m), Some(c)) => {
+                            let m = Some(m.as_slice().trim().to_string());
+                            c.outcome = match c.outcome {
+                                Failed(_) => Failed(m),
+                                Errored(_) => Errored(m),
+                                Skipped(_) => Skipped(m),
+                                Passed => Passed
+                            };
+                        },
+                        _ => ()
+                    }
+                },
+                _ => ()
+            }
+        }
+
+        if current.is_some() {
+            Err(malformed("unterminated <testcase>"))
+        } else {
+            Ok(cases)
+        }
+    }
+
+    pub struct JUnitParser {
+        /// Reports are only read from under this, and nothing on the
+        /// way from it to `report_dir` may be a symlink
+        pub build_dir: Path,
+        /// Directory containing the reports
+        pub report_dir: Path,
+        /// Only files ending with this are read, usually `.xml`
+        pub suffix: String
+    }
+
+    impl JUnitParser {
+        pub fn new(report_dir: Path) -> JUnitParser {
+            JUnitParser {
+                build_dir: report_dir.clone(),
+                report_dir: report_dir,
+                suffix: ".xml".to_string()
+            }
+        }
+
+        /// Takes a pattern like `reports/*.xml`, relative to `build_dir`.
+        /// Only a `*` in the last component is understood.
+        pub fn from_pattern(build_dir: &Path, pattern: &str) -> JUnitParser {
+            let (dir, file) =
+                match pattern.rfind('/') {
+                    Some(i) => (pattern.slice_to(i), pattern.slice_from(i + 1)),
+                    None => (".", pattern)
+                };
+            JUnitParser {
+                build_dir: build_dir.clone(),
+                report_dir: build_dir.join(dir),
+                suffix: file.trim_left_chars('*').to_string()
+            }
+        }
+
+        fn is_report(&self, path: &Path) -> bool {
+            path.filename_str().map(|f| f.ends_with(self.suffix.as_slice())).unwrap_or(false)
+        }
+
+        /// Whether the report directory, or anything between it and
+        /// the build directory, is a symlink.
+        fn behind_symlink(&self) -> IoResult<bool> {
+            let rel = match self.report_dir.path_relative_from(&self.build_dir) {
+                Some(rel) => rel,
+                None => return Ok(true)
+            };
+            let mut path = self.build_dir.clone();
+            for component in rel.components() {
+                path.push(component);
+                match fs::lstat(&path) {
+                    Ok(ref stat) if stat.kind == TypeSymlink => return Ok(true),
+                    Ok(_) => (),
+                    Err(ref e) if e.kind == FileNotFound => return Ok(false),
+                    Err(e) => return Err(e)
+                }
+            }
+            Ok(false)
+        }
+
+        /// Removes every report which is already there.  A report
+        /// directory behind a symlink is left alone, as its reports
+        /// are never read anyway.
+        pub fn remove_reports(&self) -> IoResult<()> {
+            if try!(self.behind_symlink()) || !self.report_dir.exists() {
+                return Ok(());
+            }
+            for path in try!(fs::readdir(&self.report_dir)).iter() {
+                if self.is_report(path) && try!(fs::lstat(path)).kind != TypeDirectory {
+                    try!(fs::unlink(path));
+                }
+            }
+            Ok(())
+        }
+
+        /// Reports which are regular files; symlinks are skipped.
+        fn report_files(&self) -> IoResult<Vec<Path>> {
+            if try!(self.behind_symlink()) {
+                return Err(
+                    IoError {
+                        kind: OtherIoError,
+                        desc: "JUnit reports are behind a symlink",
+                        detail: Some(self.report_dir.display().to_string())
+                    });
+            }
+            let mut files: Vec<Path> = try!(fs::readdir(&self.report_dir))
+                .into_iter()
+                .filter(|p| {
+                    self.is_report(p) &&
+                        fs::lstat(p).map(|s| s.kind == TypeFile).unwrap_or(false)
+                }).collect();
+            files.sort();
+            Ok(files)
+        }
+
+        pub fn read_cases(&self) -> IoResult<Vec<JUnitCase>> {
+            let files = try!(self.report_files());
+            if files.is_empty() {
+                return Err(
+                    IoError {
+                        kind: FileNotFound,
+                        desc: "No JUnit reports found",
+                        detail: Some(self.report_dir.display().to_string())
+                    });
+            }
+
+            let mut cases = Vec::new();
+            for file in files.iter() {
+                let xml = try!(File::open(file).read_to_string());
+                cases.push_all(try!(parse_report(xml.as_slice())).as_slice());
+            }
+            Ok(cases)
+        }
+    }
+
+    impl TestOutputParser for JUnitParser {
+        fn parse_output(&self, _: &str) -> IoResult<HashMap<String, TestOutcome>> {
+            let mut map = HashMap::new();
+            for case in try!(self.read_cases()).into_iter() {
+                map.insert(case.qualified_name(), case.to_outcome());
+            }
+            Ok(map)
+        }
+
+        fn prepare(&self) -> IoResult<()> {
+            self.remove_reports()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::io::{fs, File, USER_RWX};
+        use std::io::fs::PathExtensions;
+
+        use super::{parse_report, JUnitParser};
+        use super::Outcome::{Passed, Failed, Errored, Skipped};
+
+        use util::MessagingUnwrapper;
+
+        static REPORT: &'static str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites>
+  <testsuite name="sorting" tests="4">
+    <testcase classname="Sort" name="empty" time="0.003"/>
+    <testcase classname="Sort" name="reversed" time="0.1">
+      <failure message="expected [1, 2] &amp; got [2, 1]" type="AssertionError">trace</failure>
+    </testcase>
+    <testcase classname="Sort" name="huge">
+      <error><![CDATA[NullPointerException at <init>]]></error>
+    </testcase>
+    <!-- not yet -->
+    <testcase name="dups"><skipped/></testcase>
+  </testsuite>
+</testsuites>
+"#;
+
+        #[test]
+        fn parse_sample_report() {
+            let res = parse_report(REPORT);
+            assert!(res.is_ok());
+            let cases = res.unwrap_msg(line!());
+            assert_eq!(cases.len(), 4);
+
+            assert_eq!(cases[0].qualified_name().as_slice(), "Sort.empty");
+            assert_eq!(cases[0].time, Some(0.003));
+            assert_eq!(cases[0].outcome, Passed);
+
+            assert_eq!(cases[1].outcome,
+                       Failed(Some("expected [1, 2] & got [2, 1]".to_string())));
+            assert_eq!(cases[2].outcome,
+                       Errored(Some("NullPointerException at <init>".to_string())));
+            assert_eq!(cases[3].qualified_name().as_slice(), "dups");
+            assert_eq!(cases[3].outcome, Skipped(None));
+        }
+
+        #[test]
+        fn unterminated_testcase() {
+            assert!(parse_report("<testsuite><testcase name=\"a\">").is_err());
+        }
+
+        #[test]
+        fn pattern_splitting() {
+            let p = JUnitParser::from_pattern(&Path::new("build"), "reports/*.xml");
+            assert_eq!(p.report_dir, Path::new("build/reports"));
+            assert_eq!(p.suffix.as_slice(), ".xml");
+        }
+
+        #[test]
+        fn missing_report_dir() {
+            let p = JUnitParser::new(Path::new("test/no_such_dir"));
+            assert!(p.read_cases().is_err());
+        }
+
+        #[test]
+        fn stale_reports_and_symlinks() {
+            let build = Path::new("test/junit_stale");
+            let reports = build.join("reports");
+            let _ = fs::rmdir_recursive(&reports);
+            fs::mkdir_recursive(&reports, USER_RWX).unwrap_msg(line!());
+            File::create(&reports.join("committed.xml"))
+                .write_str(REPORT).unwrap_msg(line!());
+
+            let p = JUnitParser::from_pattern(&build, "reports/*.xml");
+            p.remove_reports().unwrap_msg(line!());
+            assert!(p.read_cases().is_err());
+
+            File::create(&reports.join("fresh.xml")).write_str(REPORT).unwrap_msg(line!());
+            fs::symlink(&Path::new("fresh.xml"), &reports.join("link.xml"))
+                .unwrap_msg(line!());
+            assert_eq!(p.read_cases().unwrap_msg(line!()).len(), 4);
+
+            let linked = build.join("reports/linked");
+            fs::symlink(&Path::new("."), &linked).unwrap_msg(line!());
+            let through = JUnitParser::from_pattern(&build, "reports/linked/*.xml");
+            assert!(through.read_cases().is_err());
+            through.remove_reports().unwrap_msg(line!());
+            assert!(reports.join("fresh.xml").exists());
+        }
     }
 }
 
-#[cfg(test)]
-mod parse_tests {
-    use super::TestResult::{Pass, Fail};
-    use super::{parse_test_result, parse_line};
+/// Parses the output of Rust's built-in test harness, as run by
+/// `cargo test`.  Each test is reported on its own line, like so:
+///
+/// test some::test_name ... ok
+///
+/// Anything a failed test printed shows up further down, in a section
+/// headed `---- some::test_name stdout ----`, which becomes the
+/// message of that test.
+pub mod libtest {
+    use std::collections::HashMap;
+    use std::io::IoResult;
 
-    use util::MessagingUnwrapper;
+    use super::{TestOutputParser, TestOutcome};
+    use super::TestResult::{Pass, Fail, Skipped};
 
-    #[test]
-    fn parse_test_pass() {
-        let res = parse_test_result("PASS");
-        assert!(res.is_ok());
-        assert_eq!(res.unwrap_msg(line!()), Pass);
+    pub struct LibTestParser;
+
+    /// `None` for anything which isn't a test result, such as benchmarks
+    /// or the `test result:` summary.
+    fn parse_test_line(line: &str) -> Option<(String, TestOutcome)> {
+        if !line.starts_with("test ") {
+            return None;
+        }
+        let line = line.slice_from(5);
+        let (name, status) =
+            match line.find_str(" ... ") {
+                Some(i) => (line.slice_to(i), line.slice_from(i + 5).trim()),
+                None => return None
+            };
+        let result =
+            match status {
+                "ok" => Pass,
+                "FAILED" => Fail,
+                "ignored" => Skipped,
+                _ => return None
+            };
+        Some((name.to_string(), TestOutcome::new(result)))
     }
 
-    #[test]
-    fn parse_test_fail() {
-        let res = parse_test_result("FAIL");
-        assert!(res.is_ok());
-        assert_eq!(res.unwrap_msg(line!()), Fail);
+    /// `---- name stdout ----` headers, giving the name.
+    fn section_name(line: &str) -> Option<&str> {
+        if line.starts_with("---- ") && line.ends_with(" stdout ----") {
+            Some(line.slice(5, line.len() - 12))
+        } else {
+            None
+        }
     }
 
-    #[test]
-    fn parse_test_bad_test() {
-        let res = parse_test_result("foobar");
-        assert!(res.is_err());
+    /// What a failed test printed becomes its message.
+    fn end_section(section: Option<(String, Vec<&str>)>,
+                   map: &mut HashMap<String, TestOutcome>) {
+        match section {
+            Some((name, lines)) => {
+                let message = lines.connect("\n").as_slice().trim().to_string();
+                match map.get_mut(&name) {
+                    Some(outcome) if !message.is_empty() => {
+                        outcome.message = Some(message);
+                    },
+                    _ => ()
+                }
+            },
+            None => ()
+        }
     }
 
-    #[test]
-    fn parse_valid_test_line() {
-        let res = parse_line("my test:PASS");
-        assert!(res.is_ok());
-        let (key, result) = res.unwrap_msg(line!());
-        assert_eq!(key.as_slice(), "my test");
-        assert_eq!(result, Pass);
+    impl TestOutputParser for LibTestParser {
+        fn parse_output(&self, output: &str) -> IoResult<HashMap<String, TestOutcome>> {
+            let mut map = HashMap::new();
+            let mut section: Option<(String, Vec<&str>)> = None;
+            for line in output.lines() {
+                match (parse_test_line(line), section_name(line)) {
+                    (Some((name, outcome)), _) => { map.insert(name, outcome); },
+                    (None, Some(name)) => {
+                        end_section(section.take(), &mut map);
+                        section = Some((name.to_string(), Vec::new()));
+                    },
+                    // the list of failures comes after the last section
+                    (None, None) if line == "failures:" => {
+                        end_section(section.take(), &mut map);
+                    },
+                    (None, None) => {
+                        match section {
+                            Some((_, ref mut lines)) => lines.push(line),
+                            None => ()
+                        }
+                    }
+                }
+            }
+            end_section(section, &mut map);
+            Ok(map)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::{LibTestParser, parse_test_line};
+        use super::super::TestOutputParser;
+        use super::super::TestResult::{Pass, Fail, Skipped};
+
+        use util::MessagingUnwrapper;
+
+        static OUTPUT: &'static str = r#"
+running 3 tests
+test tests::adds ... ok
+test tests::divides ... FAILED
+test tests::slow ... ignored
+
+failures:
+
+---- tests::divides stdout ----
+thread 'tests::divides' panicked at 'attempt to divide by zero'
+
+failures:
+    tests::divides
+
+test result: FAILED. 1 passed; 1 failed; 1 ignored; 0 measured
+"#;
+
+        #[test]
+        fn parse_lines() {
+            let (name, outcome) = parse_test_line("test a::b ... ok").unwrap_msg(line!());
+            assert_eq!(name.as_slice(), "a::b");
+            assert_eq!(outcome.result, Pass);
+            assert!(parse_test_line("test result: ok. 1 passed; 0 failed").is_none());
+            assert!(parse_test_line("test bench ... bench: 10 ns/iter (+/- 1)").is_none());
+        }
+
+        #[test]
+        fn parse_whole_output() {
+            let res = LibTestParser.parse_output(OUTPUT).unwrap_msg(line!());
+            assert_eq!(res.len(), 3);
+            assert_eq!(res.get(&"tests::adds".to_string()).unwrap_msg(line!()).result, Pass);
+            assert_eq!(res.get(&"tests::slow".to_string()).unwrap_msg(line!()).result, Skipped);
+
+            let failed = res.get(&"tests::divides".to_string()).unwrap_msg(line!());
+            assert_eq!(failed.result, Fail);
+            assert_eq!(failed.message,
+                       Some("thread 'tests::divides' panicked at 'attempt to divide by zero'"
+                            .to_string()));
+        }
+    }
+}
+
+/// Tests given as pairs of files: `name.in` is fed to the student's
+/// program, and whatever it prints has to match `name.out`.  Each pair
+/// is a test named `name`.  Only the output matters; the exit status is
+/// ignored, unless the program was killed.
+pub mod iotest {
+    extern crate regex;
+
+    use self::regex::Regex;
+    use std::collections::HashMap;
+    use std::io;
+    use std::io::{fs, File, IoResult, IoError};
+    use std::io::fs::PathExtensions;
+    use std::io::process::{Command, ExitSignal};
+
+    use super::{run_with_input, now_millis, ResourceLimits, CommandLine, CommandLog,
+                TestOutcome};
+    use super::TestResult::{Pass, Fail, Errored, TimedOut, Crashed};
+    use super::Stage::Test;
+    use seccomp;
+
+    use self::Comparison::{Exact, Whitespace, Float, Pattern};
+
+    /// Most differing lines reported for a single test.
+    pub static MAX_DIFF_LINES: uint = 10;
+
+    /// Longer lines are cut off in the diff.
+    pub static MAX_LINE_CHARS: uint = 120;
+
+    /// Per-case timeout for assignments which don't give one, in
+    /// milliseconds.
+    pub static DEFAULT_CASE_TIMEOUT: u64 = 10 * 1000;
+
+    /// How output is compared against what's expected.  In every mode
+    /// other than `Exact`, lines are trimmed and blank lines are ignored.
+    #[deriving(Show, PartialEq, Clone)]
+    pub enum Comparison {
+        /// Byte for byte
+        Exact,
+        /// Runs of whitespace are all the same
+        Whitespace,
+        /// As with `Whitespace`, but numbers only need to be within this
+        /// relative tolerance (absolute, for numbers under 1)
+        Float(f64),
+        /// Each expected line is a regular expression, which has to
+        /// match the whole of the corresponding line
+        Pattern
+    }
+
+    #[deriving(Show, PartialEq, Clone)]
+    pub struct IoCase {
+        pub name: String,
+        pub input: Path,
+        pub expected: Path
+    }
+
+    #[deriving(Show, PartialEq, Clone)]
+    pub struct IoTests {
+        /// Directory holding the `.in` and `.out` files
+        pub cases: Path,
+        /// Run in the workspace once per case
+        pub program: CommandLine,
+        pub comparison: Comparison,
+        /// For each case, in milliseconds
+        pub timeout: Option<u64>
+    }
+
+    fn within(tolerance: f64, expected: f64, actual: f64) -> bool {
+        (expected - actual).abs() <= tolerance * expected.abs().max(actual.abs()).max(1.0)
+    }
+
+    fn float_tokens_match(tolerance: f64, expected: &str, actual: &str) -> bool {
+        let e: Vec<&str> = expected.words().collect();
+        let a: Vec<&str> = actual.words().collect();
+        e.len() == a.len() && e.iter().zip(a.iter()).all(|(e, a)| {
+            match (from_str::<f64>(*e), from_str::<f64>(*a)) {
+                (Some(x), Some(y)) => within(tolerance, x, y),
+                _ => e == a
+            }
+        })
+    }
+
+    fn cut(line: &str) -> String {
+        if line.char_len() > MAX_LINE_CHARS {
+            format!("{}...", line.slice_chars(0, MAX_LINE_CHARS))
+        } else {
+            line.to_string()
+        }
+    }
+
+    /// Lines to compare, along with their line numbers.
+    fn compared_lines<'a>(comparison: &Comparison, s: &'a str) -> Vec<(uint, &'a str)> {
+        match *comparison {
+            Exact => s.split('\n').enumerate().map(|(i, l)| (i + 1, l)).collect(),
+            _ => s.lines().enumerate()
+                .map(|(i, l)| (i + 1, l.trim()))
+                .filter(|&(_, l)| !l.is_empty())
+                .collect()
+        }
+    }
+
+    /// With `Pattern`, each of the expected lines, compiled, so that's
+    /// only done once however many lines are compared.  Otherwise,
+    /// there's nothing to compile.
+    fn compile_patterns(comparison: &Comparison,
+                        expected: &[(uint, &str)]) -> IoResult<Vec<Regex>> {
+        let mut patterns = Vec::new();
+        if *comparison != Pattern {
+            return Ok(patterns);
+        }
+        for &(_, line) in expected.iter() {
+            let re = try!(Regex::new(format!("^(?:{})$", line).as_slice()).map_err(|e| {
+                IoError {
+                    kind: io::InvalidInput,
+                    desc: "Bad expected output pattern",
+                    detail: Some(e.to_string())
+                }
+            }));
+            patterns.push(re);
+        }
+        Ok(patterns)
+    }
+
+    /// `pattern` is the expected line compiled, for `Pattern`.
+    fn lines_match(comparison: &Comparison, pattern: Option<&Regex>,
+                   expected: &str, actual: &str) -> bool {
+        match *comparison {
+            Exact => expected == actual,
+            Whitespace => expected.words().collect::<Vec<&str>>() ==
+                          actual.words().collect::<Vec<&str>>(),
+            Float(tolerance) => float_tokens_match(tolerance, expected, actual),
+            Pattern => pattern.map(|re| re.is_match(actual)).unwrap_or(false)
+        }
+    }
+
+    /// `None` if `actual` matches `expected`.  Otherwise, a diff of at
+    /// most `MAX_DIFF_LINES` of the lines which differ.
+    pub fn compare(comparison: &Comparison, expected: &str,
+                   actual: &str) -> IoResult<Option<String>> {
+        if *comparison == Exact && expected == actual {
+            return Ok(None);
+        }
+        let expected = compared_lines(comparison, expected);
+        let actual = compared_lines(comparison, actual);
+        let patterns = try!(compile_patterns(comparison, expected.as_slice()));
+
+        let mut diffs = Vec::new();
+        let mut differing = 0u;
+        for i in range(0, expected.len().max(actual.len())) {
+            let (line, e, a) =
+                match (expected.get(i), actual.get(i)) {
+                    (Some(&(n, e)), Some(&(_, a))) => {
+                        if lines_match(comparison, patterns.get(i), e, a) {
+                            continue;
+                        }
+                        (n, cut(e), cut(a))
+                    },
+                    (Some(&(n, e)), None) => (n, cut(e), "<missing>".to_string()),
+                    (None, Some(&(n, a))) => (n, "<missing>".to_string(), cut(a)),
+                    (None, None) => break
+                };
+            differing += 1;
+            if diffs.len() < MAX_DIFF_LINES {
+                diffs.push(format!("line {}:\n- {}\n+ {}", line, e, a));
+            }
+        }
+        if differing > diffs.len() {
+            diffs.push(format!("...and {} more differing lines", differing - diffs.len()));
+        }
+        Ok(Some(diffs.connect("\n")))
+    }
+
+    /// What became of a single run of a student's program, given what it
+    /// should have printed.  Runs which hit a limit, or made a blocked
+    /// syscall, fail on their own rather than taking the whole stage
+    /// down with them.
+    pub fn judge(comparison: &Comparison, exit: IoResult<()>, log: &CommandLog,
+                 limits: &ResourceLimits, expected: &str) -> IoResult<TestOutcome> {
+        match exit {
+            Err(ref e) if e.kind == io::TimedOut => return Ok(TestOutcome::new(TimedOut)),
+            Err(e) => return Err(e),
+            Ok(_) => ()
+        }
+        match (limits.breached_limit(log), seccomp::blocked_syscall(log), log.exit) {
+            (Some(limit), _, _) => return Ok(
+                TestOutcome::new(Fail).with_message(Some(limit.description().to_string()))),
+            (None, true, _) => return Ok(
+                TestOutcome::new(Fail).with_message(Some("Blocked syscall".to_string()))),
+            (None, false, Some(ExitSignal(sig))) => return Ok(TestOutcome::new(Crashed(sig))),
+            _ => ()
+        }
+
+        match compare(comparison, expected, log.stdout.as_slice()) {
+            Ok(None) => Ok(TestOutcome::new(Pass)),
+            Ok(Some(diff)) => {
+                let diff =
+                    if log.truncated {
+                        format!("{}\n(output was cut off)", diff)
+                    } else {
+                        diff
+                    };
+                Ok(TestOutcome::new(Fail).with_message(Some(diff)))
+            },
+            Err(e) => Ok(TestOutcome::new(Errored).with_message(Some(e.to_string())))
+        }
+    }
+
+    impl IoTests {
+        /// Every `.in` file with a matching `.out` file, by name.
+        pub fn find_cases(&self) -> IoResult<Vec<IoCase>> {
+            let mut cases = Vec::new();
+            for input in try!(fs::readdir(&self.cases)).into_iter() {
+                if input.extension_str() != Some("in") {
+                    continue;
+                }
+                let expected = input.with_extension("out");
+                match input.filestem_str() {
+                    Some(name) if expected.is_file() => {
+                        cases.push(IoCase {
+                            name: name.to_string(),
+                            input: input.clone(),
+                            expected: expected
+                        });
+                    },
+                    _ => ()
+                }
+            }
+            cases.sort_by(|a, b| a.name.cmp(&b.name));
+            Ok(cases)
+        }
+
+        /// Runs `command` once per case.  Cases aren't logged, as there
+        /// may be hundreds of them; anything worth knowing about a
+        /// failed case is in its message.  `timeout` is for all of
+        /// them together: no case may run past it, and cases there's
+        /// no time left for time out without being run.
+        pub fn run(&self, command: &Command, limits: &ResourceLimits,
+                   timeout: Option<u64>) -> IoResult<HashMap<String, TestOutcome>> {
+            let deadline = timeout.map(|t| now_millis() + t);
+            let mut map = HashMap::new();
+            for case in try!(self.find_cases()).iter() {
+                let remaining = deadline.map(|d| d.saturating_sub(now_millis()));
+                if remaining == Some(0) {
+                    map.insert(case.name.clone(), TestOutcome::new(TimedOut).with_message(
+                        Some("Not run; ran out of time for I/O tests".to_string())));
+                    continue;
+                }
+                let case_timeout =
+                    match (self.timeout, remaining) {
+                        (Some(t), Some(r)) => Some(t.min(r)),
+                        (t, r) => t.or(r)
+                    };
+                let input = try!(File::open(&case.input).read_to_end());
+                let start = now_millis();
+                let (res, log) = run_with_input(command, input, case_timeout, Test);
+                let duration = (now_millis() - start) as f64 / 1000.0;
+                let expected = try!(File::open(&case.expected).read_to_string());
+                let outcome = try!(judge(&self.comparison, res.map(|_| ()), &log, limits,
+                                         expected.as_slice()));
+                map.insert(case.name.clone(), outcome.with_duration(Some(duration)));
+            }
+            Ok(map)
+        }
     }
 
-    #[test]
-    fn parse_invalid_test_line() {
-        assert!(parse_line("this:is:PASS").is_err());
+    #[cfg(test)]
+    mod tests {
+        use std::default::Default;
+        use std::io::process::Command;
+
+        use super::{IoTests, compare, MAX_DIFF_LINES};
+        use super::Comparison::{Exact, Whitespace, Float, Pattern};
+        use super::super::{CommandLine, now_millis};
+        use super::super::TestResult::TimedOut;
+
+        use util::MessagingUnwrapper;
+
+        #[test]
+        fn exact() {
+            assert_eq!(compare(&Exact, "a b\n", "a b\n").unwrap_msg(line!()), None);
+            assert_eq!(compare(&Exact, "a b\n", "a  b\n").unwrap_msg(line!()),
+                       Some("line 1:\n- a b\n+ a  b".to_string()));
+            assert!(compare(&Exact, "a\n", "a").unwrap_msg(line!()).is_some());
+        }
+
+        #[test]
+        fn whitespace() {
+            assert_eq!(compare(&Whitespace, "a b\nc\n", "  a\tb \n\n c").unwrap_msg(line!()),
+                       None);
+            assert_eq!(compare(&Whitespace, "a\nb\n", "a\n").unwrap_msg(line!()),
+                       Some("line 2:\n- b\n+ <missing>".to_string()));
+        }
+
+        #[test]
+        fn float() {
+            let tolerance = Float(0.001);
+            assert_eq!(compare(&tolerance, "mean: 2.5\n", "mean:  2.5001").unwrap_msg(line!()),
+                       None);
+            assert_eq!(compare(&tolerance, "1000000\n", "1000400\n").unwrap_msg(line!()), None);
+            assert!(compare(&tolerance, "mean: 2.5\n", "mean: 2.51").unwrap_msg(line!())
+                    .is_some());
+            assert!(compare(&tolerance, "mean: 2.5\n", "avg: 2.5").unwrap_msg(line!())
+                    .is_some());
+        }
+
+        #[test]
+        fn pattern() {
+            assert_eq!(compare(&Pattern, "took \\d+ ms\n", "took 15 ms\n").unwrap_msg(line!()),
+                       None);
+            assert!(compare(&Pattern, "took \\d+ ms\n", "it took 15 ms\n").unwrap_msg(line!())
+                    .is_some());
+            assert!(compare(&Pattern, "(\n", "(\n").is_err());
+        }
+
+        #[test]
+        fn diff_is_bounded() {
+            let expected = range(0, 50u).map(|i| i.to_string()).collect::<Vec<String>>()
+                .connect("\n");
+            let diff = compare(&Exact, expected.as_slice(), "").unwrap_msg(line!())
+                .unwrap_msg(line!());
+            assert_eq!(diff.as_slice().lines().filter(|l| l.starts_with("line ")).count(),
+                       MAX_DIFF_LINES);
+            assert!(diff.as_slice().ends_with(
+                format!("...and {} more differing lines", 50 - MAX_DIFF_LINES).as_slice()));
+        }
+
+        #[test]
+        fn finds_paired_cases() {
+            let tests = IoTests {
+                cases: Path::new("test/io_tests"),
+                program: CommandLine::new("./a.out", &[]),
+                comparison: Whitespace,
+                timeout: None
+            };
+            let names: Vec<String> =
+                tests.find_cases().unwrap_msg(line!()).into_iter().map(|c| c.name).collect();
+            assert_eq!(names, vec!("hang".to_string(), "spacing".to_string(),
+                                   "sum".to_string(), "wrong".to_string()));
+        }
+
+        #[test]
+        fn cases_share_the_timeout() {
+            let tests = IoTests {
+                cases: Path::new("test/io_tests"),
+                program: CommandLine::new("sleep", &["5"]),
+                comparison: Whitespace,
+                timeout: None
+            };
+            let mut sleep = Command::new("sleep");
+            sleep.arg("5");
+            let start = now_millis();
+            let results = tests.run(&sleep, &Default::default(), Some(300))
+                .unwrap_msg(line!());
+            assert!(now_millis() - start < 5000);
+            assert_eq!(results.len(), 4);
+            assert!(results.values().all(|o| o.result == TimedOut));
+        }
     }
 }
 
 pub mod testing {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::default::Default;
+    use std::io;
+    use std::io::{fs, File, IoResult, IoError, OtherIoError};
+    use std::io::fs::PathExtensions;
     use std::io::process::Command;
+    use std::os;
 
-    use super::{run_command, WholeBuildable};
+    use super::{run_command, run_captured, run_with_input, run_logged_commands, WholeBuildable,
+                ErrorSimplifier,
+                TestOutputParser, LineParser, Rubric, Timeouts, ResourceLimits, StageCommands,
+                StagePlan, Overlay, CommandLine, CommandLog, Stage, TestOutcome};
+    use super::Overlay::{Makefile, Files, Nothing};
+    use super::Stage::{SetupEnv, Build, Test};
+    use super::iotest::IoTests;
+    use super::local::{LocalSource, SOURCE_ROOT, SANDBOX_SOURCE_DIR};
+    use super::workspace::{Workspace, worker_id};
 
-    use util::MessagingUnwrapper;
+    use assignment::AssignmentConfig;
+    use differential::{Differential, ReferenceCache};
+    use memcheck::{MemCheck, Finding, REPORT_ROOT, SANDBOX_REPORT_DIR};
+    use memcheck::Tool::{Valgrind, Sanitizers};
+    use overlay::{InstructorFiles, Tampering};
+    use sandbox::{Sandbox, JobUser};
+    use seccomp;
+    use seccomp::Profile;
+    use util::warn;
 
     pub struct TestingRequest {
-        pub dir: Path, // directory where the build is to be performed
-        pub makefile_loc: Path // where the makefile is located
+        pub overlay: Overlay,
+        pub commands: StageCommands,
+        pub stages: Vec<StagePlan>,
+        pub rubric: Rubric,
+        pub timeouts: Timeouts,
+        /// Applied to the build and test commands
+        pub limits: ResourceLimits,
+        /// If set, the build and test commands run in this sandbox
+        pub sandbox: Option<Sandbox>,
+        /// If set, the test command runs under this seccomp profile
+        pub seccomp: Option<Profile>,
+        /// If set, the build and test commands run as this user,
+        /// rather than as the worker
+        pub job_user: Option<JobUser>,
+        /// If set, these are run instead of the test command
+        pub io_tests: Option<IoTests>,
+        /// If set, this is run instead of the test command, along
+        /// with any I/O tests
+        pub differential: Option<Differential>,
+        /// Where the reference for `differential` is built
+        pub references: ReferenceCache,
+        /// If set, this is what the memory checking stage does
+        pub memcheck: Option<MemCheck>,
+        /// If set, the assignment's configuration was broken, and this
+        /// fails environment setup
+        pub config_error: Option<IoError>,
+        // where the build is to be performed; dropped after `job_user`,
+        // so nothing is left running in it by then
+        workspace: Workspace,
+        parser: Box<TestOutputParser + 'static>,
+        // what the overlay put into the workspace, once it has
+        instructor_files: RefCell<Option<InstructorFiles>>,
+        tampering: RefCell<Vec<Tampering>>,
+        // the reference for `differential`, once it's built
+        reference_build: RefCell<Option<Path>>,
+        findings: RefCell<Vec<Finding>>
     }
 
     impl TestingRequest {
         pub fn new(dir: Path, makefile_loc: Path) -> TestingRequest {
+            TestingRequest::with_parser(dir, makefile_loc, box LineParser::new())
+        }
+
+        pub fn with_parser(dir: Path,
+                           makefile_loc: Path,
+                           parser: Box<TestOutputParser + 'static>) -> TestingRequest {
+            TestingRequest::in_workspace(Workspace::existing(dir), Makefile(makefile_loc), parser)
+        }
+
+        /// The copied-over files are cleaned up along with the
+        /// workspace, as is `a.out`.  References are built next to the
+        /// workspace.
+        pub fn in_workspace(workspace: Workspace,
+                            overlay: Overlay,
+                            parser: Box<TestOutputParser + 'static>) -> TestingRequest {
+            workspace.remove_on_drop("a.out");
+            let references = ReferenceCache::for_worker(&workspace.path().dir_path());
             TestingRequest {
-                dir: dir,
-                makefile_loc: makefile_loc
+                overlay: overlay,
+                commands: Default::default(),
+                stages: StagePlan::defaults(),
+                rubric: Rubric::uniform(),
+                timeouts: Default::default(),
+                limits: Default::default(),
+                sandbox: None,
+                seccomp: None,
+                job_user: None,
+                io_tests: None,
+                differential: None,
+                references: references,
+                memcheck: None,
+                config_error: None,
+                workspace: workspace,
+                parser: parser,
+                instructor_files: RefCell::new(None),
+                tampering: RefCell::new(Vec::new()),
+                reference_build: RefCell::new(None),
+                findings: RefCell::new(Vec::new())
+            }
+        }
+
+        /// Directory where the build is to be performed
+        pub fn dir<'a>(&'a self) -> &'a Path {
+            self.workspace.path()
+        }
+
+        pub fn workspace<'a>(&'a self) -> &'a Workspace {
+            &self.workspace
+        }
+
+        /// Runs the build and test commands the way `config` says to,
+        /// as a job user if there is one to spare.
+        pub fn for_assignment(workspace: Workspace,
+                              config: &AssignmentConfig) -> TestingRequest {
+            let parser = config.parser.to_parser(workspace.path());
+            TestingRequest::in_workspace(workspace, config.overlay.clone(), parser)
+                .with_commands(config.commands.clone())
+                .with_stages(config.stages.clone())
+                .with_rubric(config.rubric.clone())
+                .with_timeouts(config.timeouts.clone())
+                .with_limits(config.limits.clone())
+                .with_sandbox(config.sandbox.clone())
+                .with_seccomp(config.seccomp.clone())
+                .with_io_tests(config.io_tests.clone())
+                .with_differential(config.differential.clone())
+                .with_memcheck(config.memcheck.clone())
+                .with_job_user(JobUser::allocate())
+        }
+
+        pub fn with_commands(mut self, commands: StageCommands) -> TestingRequest {
+            self.commands = commands;
+            self
+        }
+
+        pub fn with_stages(mut self, stages: Vec<StagePlan>) -> TestingRequest {
+            self.stages = stages;
+            self
+        }
+
+        pub fn with_rubric(mut self, rubric: Rubric) -> TestingRequest {
+            self.rubric = rubric;
+            self
+        }
+
+        pub fn with_timeouts(mut self, timeouts: Timeouts) -> TestingRequest {
+            self.timeouts = timeouts;
+            self
+        }
+
+        pub fn with_limits(mut self, limits: ResourceLimits) -> TestingRequest {
+            self.limits = limits;
+            self
+        }
+
+        pub fn with_sandbox(mut self, sandbox: Option<Sandbox>) -> TestingRequest {
+            self.sandbox = sandbox;
+            self
+        }
+
+        pub fn with_seccomp(mut self, seccomp: Option<Profile>) -> TestingRequest {
+            self.seccomp = seccomp;
+            self
+        }
+
+        pub fn with_job_user(mut self, job_user: Option<JobUser>) -> TestingRequest {
+            self.job_user = job_user;
+            self
+        }
+
+        pub fn with_io_tests(mut self, io_tests: Option<IoTests>) -> TestingRequest {
+            self.io_tests = io_tests;
+            self
+        }
+
+        pub fn with_differential(mut self, differential: Option<Differential>) -> TestingRequest {
+            self.differential = differential;
+            self
+        }
+
+        pub fn with_memcheck(mut self, memcheck: Option<MemCheck>) -> TestingRequest {
+            self.memcheck = memcheck;
+            self
+        }
+
+        pub fn with_config_error(mut self, error: Option<IoError>) -> TestingRequest {
+            self.config_error = error;
+            self
+        }
+
+        /// Fails if the assignment's configuration was broken.  That's
+        /// checked before anything else, so nothing is done the wrong
+        /// way.
+        pub fn check_config(&self) -> IoResult<()> {
+            match self.config_error {
+                Some(ref e) => Err(e.clone()),
+                None => Ok(())
+            }
+        }
+
+        /// Gives the workspace back to the worker, so it can be cleaned
+        /// up.  Anything still running as the job user is killed first,
+        /// so nothing of it can change the workspace from then on.
+        #[allow(unused_must_use)]
+        pub fn reclaim(&self) {
+            match self.job_user {
+                Some(ref user) => {
+                    run_command(&user.kill_command(), None, ());
+                    run_command(&user.reclaim_command(self.dir()), None, ());
+                },
+                None => ()
+            }
+        }
+
+        /// Keeps the workspace around, if it's a fresh one, as long as
+        /// that fits into the budget.
+        pub fn retain(&self, budget: u64) -> Option<Path> {
+            self.reclaim();
+            match self.workspace.retain(budget) {
+                Ok(path) => Some(path),
+                Err(e) => {
+                    warn(format!("Not keeping {}: {}", self.dir().display(), e));
+                    None
+                }
+            }
+        }
+
+        fn record_tampering(&self, files: Vec<String>, stage: Stage) {
+            let mut tampering = self.tampering.borrow_mut();
+            for file in files.into_iter() {
+                tampering.push(Tampering { file: file, stage: stage.clone() });
+            }
+        }
+
+        /// Instructor files are checksummed as they're copied over,
+        /// and any the submission had its own versions of are noted.
+        fn copy_overlay(&self) -> IoResult<()> {
+            let files =
+                try!(match self.overlay {
+                    Makefile(ref makefile) => InstructorFiles::single(makefile, "makefile"),
+                    Files(ref dir) => InstructorFiles::scan(dir),
+                    Nothing => Ok(InstructorFiles::none())
+                });
+            self.record_tampering(files.shadowed(self.dir()), SetupEnv);
+            try!(self.copy_in(&files, &files.names()));
+            *self.instructor_files.borrow_mut() = Some(files);
+            Ok(())
+        }
+
+        /// Puts back any instructor files which `stage` changed.
+        fn verify_overlay(&self, stage: &Stage) -> IoResult<()> {
+            match *self.instructor_files.borrow() {
+                Some(ref files) => {
+                    let modified = files.modified(self.dir());
+                    try!(self.copy_in(files, &modified));
+                    self.record_tampering(modified, stage.clone());
+                },
+                None => ()
+            }
+            Ok(())
+        }
+
+        /// Copies the instructor files in `names` over whatever is in the
+        /// workspace.  With a job user, which owns the workspace by then,
+        /// that's done as the job user, so nothing the submission put in
+        /// the way (e.g., a symlink) gets the worker to write anywhere.
+        fn copy_in(&self, files: &InstructorFiles, names: &Vec<String>) -> IoResult<()> {
+            let user =
+                match self.job_user {
+                    Some(ref user) => user,
+                    None => return files.copy_into(&self.workspace, names)
+                };
+            for &(ref name, ref source) in files.sources(names).iter() {
+                let contents = try!(File::open(source).read_to_end());
+                let mode = try!(fs::stat(source)).perm.bits();
+                let mut write = user.write_command(name.as_slice(), mode);
+                write.cwd(self.dir());
+                let (res, _) = run_with_input(&write, contents, None, SetupEnv);
+                try!(try!(res).if_ok(()));
+                self.workspace.remove_on_drop(name.as_slice());
+            }
+            Ok(())
+        }
+
+        /// Builds the reference for differential testing, if need be, the
+        /// same way submissions are built.  If that fails, it's not the
+        /// submission's fault, so it counts as environment setup.
+        fn build_reference(&self, logs: &mut Vec<CommandLog>) -> IoResult<()> {
+            match self.differential {
+                Some(ref diff) => {
+                    let built = try!(self.references.build(
+                        diff, &self.commands.build, self.timeouts.build, logs));
+                    *self.reference_build.borrow_mut() = Some(built);
+                },
+                None => ()
+            }
+            Ok(())
+        }
+
+        /// Makes the job user, if any, the owner of the workspace.
+        fn hand_over(&self, timeout: Option<u64>, stage: Stage,
+                     logs: &mut Vec<CommandLog>) -> IoResult<()> {
+            match self.job_user {
+                Some(ref user) => {
+                    let chown = vec!(user.chown_command(self.dir()));
+                    run_logged_commands(&chown, timeout, stage, logs)
+                },
+                None => Ok(())
+            }
+        }
+
+        /// A directory of this build's under `root`, which is next to
+        /// the workspace.
+        fn side_dir(&self, root: &str) -> Path {
+            self.dir().dir_path().join(root).join(worker_id())
+                .join(self.dir().filename_display().to_string())
+        }
+
+        /// Makes an empty `dir`, as made by `side_dir`.  The job user can
+        /// get to it, but can't list any of the others.
+        fn make_side_dir(&self, dir: &Path, mode: io::FilePermission) -> IoResult<()> {
+            let parent = dir.dir_path();
+            if !parent.exists() {
+                try!(fs::mkdir_recursive(&parent, io::USER_RWX));
+            }
+            for ancestor in [parent.dir_path(), parent].iter() {
+                try!(fs::chmod(ancestor, io::USER_RWX | io::GROUP_EXECUTE | io::OTHER_EXECUTE));
+            }
+            if dir.exists() {
+                try!(fs::rmdir_recursive(dir));
+            }
+            fs::mkdir(dir, mode)
+        }
+
+        /// Puts `source` into the empty workspace.  It's unpacked as
+        /// the job user, and in the sandbox, like a build command would
+        /// be, so that nothing in an archive can end up outside of the
+        /// workspace.  What's unpacked is a copy which only the worker
+        /// can change, which is removed afterwards.
+        pub fn unpack(&self, source: &LocalSource, timeout: Option<u64>,
+                      logs: &mut Vec<CommandLog>) -> IoResult<()> {
+            let copy = self.side_dir(SOURCE_ROOT);
+            try!(self.make_side_dir(
+                &copy, io::USER_RWX | io::GROUP_EXECUTE | io::OTHER_EXECUTE));
+            let res = self.unpack_copy(source, &copy, timeout, logs);
+            match fs::rmdir_recursive(&copy) {
+                Ok(_) => res,
+                Err(e) => res.and(Err(e))
+            }
+        }
+
+        fn unpack_copy(&self, source: &LocalSource, copy: &Path, timeout: Option<u64>,
+                       logs: &mut Vec<CommandLog>) -> IoResult<()> {
+            try!(run_logged_commands(&source.copy_commands(copy), timeout, SetupEnv, logs));
+            try!(self.hand_over(timeout, SetupEnv, logs));
+            // where the unpacking command sees the copy
+            let (seen, binds) =
+                match self.sandbox {
+                    Some(_) => (Path::new(SANDBOX_SOURCE_DIR),
+                                vec!((copy.clone(), SANDBOX_SOURCE_DIR))),
+                    None => (os::getcwd().join(copy), Vec::new())
+                };
+            let unpack = vec!(self.wrap_command_binding(
+                &source.unpack_line(&seen), &None, binds.as_slice()));
+            run_logged_commands(&unpack, timeout, SetupEnv, logs)
+        }
+
+        /// Where valgrind's logs go for this build.
+        fn report_dir(&self) -> Path {
+            self.side_dir(REPORT_ROOT)
+        }
+
+        /// Makes an empty `dir` which only the job user, if any, can
+        /// write to.
+        fn make_report_dir(&self, dir: &Path) -> IoResult<()> {
+            try!(self.make_side_dir(dir, io::USER_RWX));
+            match self.job_user {
+                Some(ref user) => run_command(&user.chown_command(dir), None, ()),
+                None => Ok(())
+            }
+        }
+
+        /// Kills anything of the job user's which is still running, and
+        /// gives `dir` back to the worker, for only it to use.
+        fn take_back(&self, dir: &Path) -> IoResult<()> {
+            match self.job_user {
+                Some(ref user) => {
+                    try!(run_command(&user.kill_command(), None, ()));
+                    try!(run_command(&user.reclaim_command(dir), None, ()));
+                },
+                None => ()
+            }
+            fs::chmod(dir, io::USER_RWX)
+        }
+
+        /// Makes a fresh build for `check`, and runs its command under
+        /// the tool.  Valgrind can't run under the seccomp profile, as it
+        /// needs syscalls which no profile allows; sanitizers can, but
+        /// then can't look for leaks.  The report directory is removed
+        /// once its findings are in.
+        fn run_memcheck(&self, check: &MemCheck, plan: &StagePlan,
+                        logs: &mut Vec<CommandLog>) -> IoResult<()> {
+            let reports = self.report_dir();
+            try!(self.make_report_dir(&reports));
+            let res = self.check_memory(check, plan, &reports, logs);
+            match fs::rmdir_recursive(&reports) {
+                Ok(_) => (),
+                Err(e) => return res.and(Err(e))
+            }
+            res
+        }
+
+        fn check_memory(&self, check: &MemCheck, plan: &StagePlan, reports: &Path,
+                        logs: &mut Vec<CommandLog>) -> IoResult<()> {
+            try!(self.hand_over(plan.timeout, plan.stage.clone(), logs));
+            let build: Vec<Command> =
+                check.build.iter().map(|c| self.wrap_command(c, &None)).collect();
+            try!(run_logged_commands(&build, plan.timeout, plan.stage.clone(), logs));
+
+            let profile =
+                match check.tool {
+                    Valgrind => None,
+                    Sanitizers => self.seccomp.clone()
+                };
+            // where the command sees the reports going
+            let (seen, binds) =
+                match self.sandbox {
+                    Some(_) => (Path::new(SANDBOX_REPORT_DIR),
+                                vec!((reports.clone(), SANDBOX_REPORT_DIR))),
+                    None => (os::getcwd().join(reports), Vec::new())
+                };
+            let command = self.wrap_command_binding(
+                &check.wrap(&seen, profile.is_none()), &profile, binds.as_slice());
+            let (res, log) = run_captured(&command, plan.timeout, plan.stage.clone());
+            // nothing of the submission's gets to touch the reports from here on
+            let taken_back = self.take_back(reports);
+            let breached = self.limits.breached_limit(&log);
+            let blocked = profile.is_some() && seccomp::blocked_syscall(&log);
+            let findings = check.findings(reports, &log);
+            logs.push(log);
+            try!(res);
+            try!(taken_back);
+            match breached {
+                Some(limit) => return Err(
+                    IoError {
+                        kind: OtherIoError,
+                        desc: "Memory checking was cut short",
+                        detail: Some(format!("{}", limit))
+                    }),
+                None => ()
+            }
+            if blocked {
+                return Err(
+                    IoError {
+                        kind: OtherIoError,
+                        desc: "Memory checking was cut short",
+                        detail: Some("blocked syscall".to_string())
+                    });
+            }
+
+            let findings = try!(findings);
+            let found = findings.len();
+            *self.findings.borrow_mut() = findings;
+            if found == 0 {
+                Ok(())
+            } else {
+                Err(IoError {
+                    kind: OtherIoError,
+                    desc: "Memory errors found",
+                    detail: Some(format!("{} finding(s)", found))
+                })
             }
         }
 
-        fn make_with_arg<A : ToCStr>(&self, arg: A) -> Command {
-            let mut c = Command::new("make");
-            c.arg("-s").arg(arg).cwd(&self.dir);
+        /// Limits are applied outside of the sandbox, so `prlimit`
+        /// doesn't need to be visible inside of it.  `gradr_exec`, and
+        /// with it the seccomp profile, goes innermost, since `bwrap`
+        /// itself needs syscalls which no profile allows.
+        fn wrap_command(&self, line: &CommandLine, profile: &Option<Profile>) -> Command {
+            self.wrap_command_binding(line, profile, &[])
+        }
+
+        /// Like `wrap_command`, but with more directories made
+        /// writable inside of the sandbox, if there is one.
+        fn wrap_command_binding(&self, line: &CommandLine, profile: &Option<Profile>,
+                                binds: &[(Path, &'static str)]) -> Command {
+            let mut line = seccomp::supervised(profile, line.clone());
+            match self.sandbox {
+                Some(ref sandbox) => line = sandbox.wrap_binding(self.dir(), binds, line),
+                None => ()
+            }
+            let mut c = self.limits.wrap(line).to_command();
+            c.cwd(self.dir());
+            match self.job_user {
+                Some(ref user) => {
+                    c.uid(user.uid);
+                    c.gid(user.gid);
+                },
+                None => ()
+            }
             c
         }
     }
 
     impl Drop for TestingRequest {
-        /// Automatically deletes the copied-over makefile on test end,
-        /// along with any applicable executables (namely `a.out`)
-        #[allow(unused_must_use)]
+        /// The workspace is cleaned up once this is done, which the
+        /// worker may not be able to do if the job user still owns it.
         fn drop(&mut self) {
-            let dir = self.dir.as_str().unwrap_msg(line!());
-            run_command(
-                &*Command::new("rm")
-                    .arg(format!("{}/makefile", dir))
-                    .arg(format!("{}/a.out", dir)),
-                None,
-                ());
+            self.reclaim();
         }
     }
 
     impl WholeBuildable for TestingRequest {
-        fn env_timeout(&self) -> Option<u64> { None }
+        fn env_timeout(&self) -> Option<u64> { self.timeouts.env }
 
+        /// Environment commands are the instructor's, so they run as
+        /// the worker, outside of the sandbox and without limits.
         fn env_commands(&self) -> Vec<Command> {
-            let mut c = Command::new("cp");
-            c.arg(self.makefile_loc.as_str().unwrap_msg(line!()));
-            c.arg(self.dir.as_str().unwrap_msg(line!()));
-            vec!(c)
+            self.commands.env.iter().map(|c| {
+                let mut command = c.to_command();
+                command.cwd(self.dir());
+                command
+            }).collect()
+        }
+
+        /// The overlay goes in first, so that environment commands can
+        /// use instructor files.  From then on, the workspace belongs to
+        /// the job user, so the overlay goes in as the job user, too.
+        fn setup_env(&self, logs: &mut Vec<CommandLog>) -> IoResult<()> {
+            try!(self.check_config());
+            try!(self.hand_over(self.env_timeout(), SetupEnv, logs));
+            try!(self.copy_overlay());
+            try!(run_logged_commands(&self.env_commands(), self.env_timeout(), SetupEnv, logs));
+            self.build_reference(logs)
         }
 
-        fn build_timeout(&self) -> Option<u64> { None }
+        fn build_timeout(&self) -> Option<u64> { self.timeouts.build }
 
         fn build_commands(&self) -> Vec<Command> {
-            vec!(self.make_with_arg("build"))
+            self.commands.build.iter().map(|c| self.wrap_command(c, &None)).collect()
         }
 
-        fn test_timeout(&self) -> Option<u64> { None }
+        /// With a job user, the workspace is handed over to it again,
+        /// in case the environment commands made anything of their own.
+        fn do_build(&self, logs: &mut Vec<CommandLog>) -> IoResult<()> {
+            try!(self.hand_over(self.build_timeout(), Build, logs));
+            run_logged_commands(&self.build_commands(), self.build_timeout(), Build, logs)
+        }
+
+        fn test_timeout(&self) -> Option<u64> { self.timeouts.test }
 
         fn test_command(&self) -> Command {
-            self.make_with_arg("test")
+            self.wrap_command(&self.commands.test, &self.seccomp)
+        }
+
+        fn output_parser<'a>(&'a self) -> &'a (TestOutputParser + 'a) {
+            &*self.parser
+        }
+
+        /// I/O and differential tests run the program under the same
+        /// limits, sandbox, profile and user as the test command would.
+        /// Each of them gets the whole of the test timeout.
+        fn do_testing(&self, logs: &mut Vec<CommandLog>) -> IoResult<HashMap<String, TestOutcome>> {
+            if self.io_tests.is_none() && self.differential.is_none() {
+                try!(self.parser.prepare());
+                let (res, log) = run_captured(&self.test_command(), self.test_timeout(), Test);
+                let parsed = res.and_then(|_| self.parser.parse_output(log.stdout.as_slice()));
+                logs.push(log);
+                return parsed;
+            }
+
+            let mut results = HashMap::new();
+            match self.io_tests {
+                Some(ref tests) => {
+                    let program = self.wrap_command(&tests.program, &self.seccomp);
+                    results.extend(
+                        try!(tests.run(&program, &self.limits, self.test_timeout())).into_iter());
+                },
+                None => ()
+            }
+            match (&self.differential, &*self.reference_build.borrow()) {
+                (&Some(ref diff), &Some(ref built)) => {
+                    let program = self.wrap_command(&diff.program, &self.seccomp);
+                    results.extend(
+                        try!(diff.run(built, &program, &self.limits, self.test_timeout()))
+                        .into_iter());
+                },
+                _ => ()
+            }
+            Ok(results)
+        }
+
+        fn rubric(&self) -> Rubric {
+            self.rubric.clone()
+        }
+
+        fn limits(&self) -> ResourceLimits {
+            self.limits.clone()
+        }
+
+        fn seccomp_profile(&self) -> Option<Profile> {
+            self.seccomp.clone()
+        }
+
+        fn tampering(&self) -> Vec<Tampering> {
+            self.tampering.borrow().clone()
+        }
+
+        fn findings(&self) -> Vec<Finding> {
+            self.findings.borrow().clone()
+        }
+
+        fn stages(&self) -> Vec<StagePlan> {
+            self.stages.clone()
+        }
+
+        /// Named stages commonly run the student's code, too, so they
+        /// get the test command's profile.
+        fn stage_commands(&self, plan: &StagePlan) -> Vec<Command> {
+            plan.commands.iter().map(|c| self.wrap_command(c, &self.seccomp)).collect()
+        }
+
+        /// The memory checking stage fails if anything was found, so
+        /// that its failure policy decides what that costs.
+        fn do_stage(&self, plan: &StagePlan, logs: &mut Vec<CommandLog>) -> IoResult<()> {
+            match self.memcheck {
+                Some(ref check) if plan.stage == MemCheck::stage() =>
+                    self.run_memcheck(check, plan, logs),
+                _ => run_logged_commands(&self.stage_commands(plan), plan.timeout,
+                                         plan.stage.clone(), logs)
+            }
+        }
+
+        /// Anything left running as the job user (e.g., something which
+        /// left its process group) is killed after every stage, so it
+        /// can't carry on into the next one.  Instructor files are
+        /// checked after that, so they're intact by the time they're
+        /// used, and it's known which stage changed them.
+        fn after_stage(&self, stage: &Stage) -> IoResult<()> {
+            match self.job_user {
+                Some(ref user) => try!(run_command(&user.kill_command(), None, ())),
+                None => ()
+            }
+            self.verify_overlay(stage)
+        }
+    }
+}
+
+/// Built-in ways of building and testing projects for common
+/// toolchains, so that instructors don't need to wrap each of them in
+/// a makefile.  A recipe only supplies defaults; an assignment can
+/// still override any of its commands, or its parser.
+pub mod recipe {
+    use std::default::Default;
+
+    use super::{StageCommands, Overlay, CommandLine};
+    use super::Overlay::{Makefile, Nothing};
+
+    use assignment::{ParserSpec, DEFAULT_MAKEFILE};
+    use assignment::ParserSpec::{Line, LibTest, JUnit};
+
+    use self::Recipe::{Make, Cargo, Gradle, Pytest, CMake};
+
+    #[deriving(Show, PartialEq, Clone)]
+    pub enum Recipe {
+        /// `make -s build` and `make -s test`, with the instructor's makefile
+        Make,
+        /// Rust, with Cargo's built-in test harness
+        Cargo,
+        /// Java, with Gradle's JUnit reports
+        Gradle,
+        /// Python, with pytest's JUnit report
+        Pytest,
+        /// C or C++, built with CMake and tested with CTest
+        CMake
+    }
+
+    impl Recipe {
+        pub fn name(&self) -> &'static str {
+            match *self {
+                Make => "make",
+                Cargo => "cargo",
+                Gradle => "gradle",
+                Pytest => "pytest",
+                CMake => "cmake"
+            }
+        }
+
+        pub fn from_name(name: &str) -> Option<Recipe> {
+            [Make, Cargo, Gradle, Pytest, CMake].iter()
+                .find(|r| r.name() == name)
+                .map(|r| r.clone())
+        }
+
+        /// Builds don't get network access in the sandbox, so nothing
+        /// tries to download dependencies.
+        pub fn commands(&self) -> StageCommands {
+            let (build, test) =
+                match *self {
+                    Make => return Default::default(),
+                    Cargo => (
+                        vec!(CommandLine::new("cargo", &["test", "--offline", "--no-run"])),
+                        CommandLine::new("cargo", &["test", "--offline"])),
+                    Gradle => (
+                        vec!(CommandLine::new("gradle", &["--offline", "-q", "testClasses"])),
+                        CommandLine::new("gradle", &["--offline", "-q", "test"])),
+                    // byte compiling catches syntax errors at build time
+                    Pytest => (
+                        vec!(CommandLine::new("python3", &["-m", "compileall", "-q", "."])),
+                        CommandLine::new(
+                            "python3", &["-m", "pytest", "-q", "--junitxml=reports/pytest.xml"])),
+                    CMake => (
+                        vec!(CommandLine::new("cmake", &["-S", ".", "-B", "build"]),
+                             CommandLine::new("cmake", &["--build", "build"])),
+                        CommandLine::new(
+                            "ctest", &["--test-dir", "build", "--output-junit", "ctest.xml"]))
+                };
+            StageCommands {
+                env: Vec::new(),
+                build: build,
+                test: test
+            }
+        }
+
+        pub fn parser(&self) -> ParserSpec {
+            match *self {
+                Make => Line,
+                Cargo => LibTest,
+                Gradle => JUnit("build/test-results/test/*.xml".to_string()),
+                Pytest => JUnit("reports/*.xml".to_string()),
+                // relative to the build directory, which is where CTest runs
+                CMake => JUnit("build/ctest.xml".to_string())
+            }
+        }
+
+        /// Only `make` needs anything from the instructor by default.
+        pub fn overlay(&self) -> Overlay {
+            match *self {
+                Make => Makefile(Path::new(DEFAULT_MAKEFILE)),
+                _ => Nothing
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::Recipe;
+        use super::Recipe::{Make, Cargo, Gradle, Pytest, CMake};
+        use super::super::CommandLine;
+
+        #[test]
+        fn names_round_trip() {
+            for r in [Make, Cargo, Gradle, Pytest, CMake].iter() {
+                assert_eq!(Recipe::from_name(r.name()), Some(r.clone()));
+            }
+            assert_eq!(Recipe::from_name("ant"), None);
+        }
+
+        #[test]
+        fn cmake_builds_out_of_tree() {
+            let c = CMake.commands();
+            assert_eq!(c.build.len(), 2);
+            assert_eq!(c.build[1], CommandLine::new("cmake", &["--build", "build"]));
         }
     }
 }
 
 #[cfg(test)]
 mod build_tests {
-    use super::BuildResult::TestSuccess;
-    use super::TestResult::{Pass, Fail};
+    use std::io::File;
+    use std::io::fs;
+    use std::io::process::Process;
+    use std::io::timer;
+    use std::time::Duration;
+
+    use super::BuildOutcome::{BuildFailure, StageFailure, TestSuccess, StageTimedOut,
+                              LimitExceeded, SyscallBlocked};
+    use super::{Score, CommandLog, Timeouts, ResourceLimits, StageCommands, CommandLine,
+                Rubric, StagePlan, FailurePolicy};
+    use super::FailurePolicy::{Abort, Continue, Penalty};
+    use super::Limit::CpuTime;
+    use super::Stage::{Build, Test, Named};
+    use super::StageStatus::{Succeeded, Failed, NotRun};
+    use super::TestResult::{Pass, Fail, TimedOut};
 
     use super::WholeBuildable;
     use super::testing::TestingRequest;
+    use super::iotest::IoTests;
+    use super::iotest::Comparison::{Exact, Whitespace};
+
+    use differential::Differential;
+    use memcheck::MemCheck;
+    use memcheck::Tool::Sanitizers;
+    use seccomp::Profile::FileIo;
+    use super::junit::JUnitParser;
 
     use util::MessagingUnwrapper;
 
@@ -495,29 +4904,45 @@ mod build_tests {
 
     #[test]
     fn makefile_copy_ok() {
-        assert!(req("compile_error").setup_env().is_ok());
+        assert!(req("compile_error").setup_env(&mut Vec::new()).is_ok());
     }
 
     #[test]
     fn expected_compile_failure() {
         let r = req("compile_error");
-        assert!(r.setup_env().is_ok());
-        assert!(r.do_build().is_err());
+        let mut logs = Vec::new();
+        assert!(r.setup_env(&mut logs).is_ok());
+        assert!(r.do_build(&mut logs).is_err());
+    }
+
+    #[test]
+    fn compile_errors_logged() {
+        let res = req("compile_error").whole_build();
+        match res.outcome {
+            BuildFailure(_) => (),
+            _ => { assert!(false); }
+        };
+        let build_logs: Vec<&CommandLog> =
+            res.logs.iter().filter(|l| l.stage == Build).collect();
+        assert_eq!(build_logs.len(), 1);
+        assert!(build_logs[0].stderr.as_slice().contains("error"));
     }
 
     #[test]
     fn expected_compile_success() {
         let r = req("compile_success");
-        assert!(r.setup_env().is_ok());
-        assert!(r.do_build().is_ok());
+        let mut logs = Vec::new();
+        assert!(r.setup_env(&mut logs).is_ok());
+        assert!(r.do_build(&mut logs).is_ok());
     }
 
     #[test]
     fn testing_parsing_empty_success() {
         let r = req("testing_parsing_empty_success");
-        assert!(r.setup_env().is_ok());
-        assert!(r.do_build().is_ok());
-        let res = r.do_testing();
+        let mut logs = Vec::new();
+        assert!(r.setup_env(&mut logs).is_ok());
+        assert!(r.do_build(&mut logs).is_ok());
+        let res = r.do_testing(&mut logs);
         assert!(res.is_ok());
         assert_eq!(res.unwrap_msg(line!()).len(), 0);
     }
@@ -525,9 +4950,10 @@ mod build_tests {
     #[test]
     fn testing_parsing_nonempty_success() {
         let r = req("testing_parsing_nonempty_success");
-        assert!(r.setup_env().is_ok());
-        assert!(r.do_build().is_ok());
-        let res = r.do_testing();
+        let mut logs = Vec::new();
+        assert!(r.setup_env(&mut logs).is_ok());
+        assert!(r.do_build(&mut logs).is_ok());
+        let res = r.do_testing(&mut logs);
 
         assert!(res.is_ok());
         let u = res.unwrap_msg(line!());
@@ -535,24 +4961,299 @@ mod build_tests {
 
         let t1 = u.get(&"test1".to_string());
         assert!(t1.is_some());
-        assert_eq!(t1.unwrap_msg(line!()), &Pass);
+        assert_eq!(t1.unwrap_msg(line!()).result, Pass);
 
         let t2 = u.get(&"test2".to_string());
         assert!(t2.is_some());
-        assert_eq!(t2.unwrap_msg(line!()), &Fail);
+        assert_eq!(t2.unwrap_msg(line!()).result, Fail);
     }
 
     #[test]
     fn test_whole_build() {
-        match req("test_whole_build").whole_build() {
-            TestSuccess(u) => {
+        match req("test_whole_build").whole_build().outcome {
+            TestSuccess(u, score) => {
+                assert_eq!(score, Score { earned: 1.0, possible: 2.0 });
+
                 let t1 = u.get(&"test1".to_string());
                 assert!(t1.is_some());
-                assert_eq!(t1.unwrap_msg(line!()), &Pass);
+                assert_eq!(t1.unwrap_msg(line!()).result, Pass);
                 
                 let t2 = u.get(&"test2".to_string());
                 assert!(t2.is_some());
-                assert_eq!(t2.unwrap_msg(line!()), &Fail);
+                assert_eq!(t2.unwrap_msg(line!()).result, Fail);
+            },
+            _ => { assert!(false); }
+        };
+    }
+
+    #[test]
+    fn declared_commands_and_rubric() {
+        let mut rubric = Rubric::uniform();
+        rubric.weights.insert("test3".to_string(), 3.0);
+        let r = req("declared_commands")
+            .with_commands(
+                StageCommands {
+                    env: Vec::new(),
+                    build: vec!(CommandLine::new("gcc", &["main.c"])),
+                    test: CommandLine::new("./a.out", &[])
+                })
+            .with_rubric(rubric);
+        match r.whole_build().outcome {
+            TestSuccess(u, score) => {
+                assert_eq!(u.len(), 3);
+                assert_eq!(score, Score { earned: 4.0, possible: 5.0 });
+            },
+            _ => { assert!(false); }
+        };
+    }
+
+    #[test]
+    fn io_tests() {
+        let r = req("io_program").with_io_tests(
+            Some(IoTests {
+                cases: Path::new("test/io_tests"),
+                program: CommandLine::new("./a.out", &[]),
+                comparison: Whitespace,
+                timeout: Some(500)
+            }));
+        match r.whole_build().outcome {
+            TestSuccess(u, score) => {
+                assert_eq!(score, Score { earned: 2.0, possible: 4.0 });
+                assert_eq!(u.get(&"sum".to_string()).unwrap_msg(line!()).result, Pass);
+                assert_eq!(u.get(&"spacing".to_string()).unwrap_msg(line!()).result, Pass);
+                assert_eq!(u.get(&"hang".to_string()).unwrap_msg(line!()).result, TimedOut);
+
+                let wrong = u.get(&"wrong".to_string()).unwrap_msg(line!());
+                assert_eq!(wrong.result, Fail);
+                assert_eq!(wrong.message, Some(
+                    "line 1:\n- sum: 3\n+ sum: 2\nline 2:\n- mean: 1.500000\n+ mean: 1.000000"
+                    .to_string()));
+            },
+            _ => { assert!(false); }
+        };
+    }
+
+    #[test]
+    fn differential_tests() {
+        let r = req("differential").with_differential(
+            Some(Differential {
+                reference: Path::new("test/differential_reference"),
+                generator: CommandLine::new("sh", &["gen.sh"]),
+                program: CommandLine::new("./a.out", &[]),
+                runs: 5,
+                seed: Some(0),
+                comparison: Exact,
+                timeout: Some(5000)
+            }));
+        match r.whole_build().outcome {
+            TestSuccess(u, score) => {
+                // only inputs of more than three numbers trip it up
+                assert_eq!(score, Score { earned: 3.0, possible: 5.0 });
+                let passed = u.get(&"random_3".to_string()).unwrap_msg(line!());
+                assert_eq!(passed.result, Pass);
+                assert_eq!(passed.message,
+                           Some("seed 2 (input from `sh gen.sh 2`)".to_string()));
+
+                let failed = u.get(&"random_4".to_string()).unwrap_msg(line!());
+                assert_eq!(failed.result, Fail);
+                assert_eq!(failed.message,
+                           Some("seed 3 (input from `sh gen.sh 3`)\nline 1:\n- sum: 18\n+ sum: 12"
+                                .to_string()));
+            },
+            _ => { assert!(false); }
+        };
+    }
+
+    fn named_stage(name: &str, program: &str, on_failure: FailurePolicy) -> StagePlan {
+        StagePlan {
+            stage: Named(name.to_string()),
+            commands: vec!(CommandLine::new(program, &[])),
+            timeout: None,
+            on_failure: on_failure
+        }
+    }
+
+    #[test]
+    fn failed_stages_continue_with_penalty() {
+        let r = req("declared_commands").with_stages(
+            vec!(named_stage("lint", "false", Penalty(0.5)),
+                 StagePlan::built_in(Build),
+                 StagePlan::built_in(Test),
+                 named_stage("benchmark", "false", Continue)));
+        let res = r.whole_build();
+        match res.outcome {
+            TestSuccess(_, score) => {
+                // 2 of 3, less half of 3
+                assert_eq!(score, Score { earned: 0.5, possible: 3.0 });
+            },
+            _ => { assert!(false); }
+        };
+
+        let statuses: Vec<(&str, bool)> = res.stages.iter().map(|r| {
+            let ok = match r.status { Succeeded => true, _ => false };
+            (r.stage.name(), ok)
+        }).collect();
+        assert_eq!(statuses, vec!(("lint", false), ("build", true),
+                                  ("test", true), ("benchmark", false)));
+    }
+
+    #[test]
+    fn failed_stage_aborts() {
+        let r = req("declared_commands").with_stages(
+            vec!(named_stage("lint", "false", Abort),
+                 StagePlan::built_in(Build),
+                 StagePlan::built_in(Test)));
+        let res = r.whole_build();
+        match res.outcome {
+            StageFailure(ref stage, _) => assert_eq!(stage.name(), "lint"),
+            _ => { assert!(false); }
+        };
+        match res.stages[0].status {
+            Failed(_, penalty) => assert_eq!(penalty, 0.0),
+            _ => { assert!(false); }
+        };
+        for report in res.stages.slice_from(1).iter() {
+            match report.status {
+                NotRun => (),
+                _ => { assert!(false); }
+            };
+        }
+    }
+
+    #[test]
+    fn memcheck_finds_leak() {
+        let r = req("memcheck")
+            .with_stages(
+                vec!(StagePlan::built_in(Build),
+                     StagePlan::built_in(Test),
+                     StagePlan {
+                         stage: MemCheck::stage(),
+                         commands: Vec::new(),
+                         timeout: None,
+                         on_failure: Penalty(0.5)
+                     }))
+            .with_memcheck(
+                Some(MemCheck {
+                    tool: Sanitizers,
+                    build: vec!(CommandLine::new("gcc", &["-g", "-fsanitize=address", "main.c"])),
+                    command: CommandLine::new("./a.out", &[])
+                }));
+        let res = r.whole_build();
+        match res.outcome {
+            TestSuccess(_, score) => {
+                assert_eq!(score, Score { earned: 0.5, possible: 1.0 });
+            },
+            _ => { assert!(false); }
+        };
+        match res.stages[2].status {
+            Failed(_, penalty) => assert_eq!(penalty, 0.5),
+            _ => { assert!(false); }
+        };
+        assert_eq!(res.findings.len(), 1);
+        assert_eq!(res.findings[0].kind.as_slice(), "leak_direct");
+        assert_eq!(res.findings[0].bytes_lost, Some(40));
+    }
+
+    /// Whether or not everything listed in the file of pids has died.
+    fn all_dead(pids_file: &Path) -> bool {
+        let pids = File::open(pids_file).read_to_string().unwrap_msg(line!());
+        let pids: Vec<i32> = pids.as_slice().lines()
+            .filter_map(|p| from_str(p.trim()))
+            .collect();
+        assert_eq!(pids.len(), 8);
+        pids.iter().all(|pid| Process::kill(*pid, 0).is_err())
+    }
+
+    #[test]
+    fn timeout_kills_fork_bomb() {
+        let pids_file = Path::new("test/fork_bomb/pids.txt");
+        let _ = fs::unlink(&pids_file);
+
+        let r = req("fork_bomb").with_timeouts(
+            Timeouts {
+                env: None,
+                build: None,
+                test: Some(1000)
+            });
+        match r.whole_build().outcome {
+            StageTimedOut(Test) => (),
+            _ => { assert!(false); }
+        };
+
+        // everything was killed, but the orphans may not have been
+        // reaped by init just yet
+        let mut dead = false;
+        for _ in range(0, 100u) {
+            if all_dead(&pids_file) {
+                dead = true;
+                break;
+            }
+            timer::sleep(Duration::milliseconds(10));
+        }
+        let _ = fs::unlink(&pids_file);
+        assert!(dead);
+    }
+
+    #[test]
+    fn cpu_limit_reported_through_make() {
+        // `make` itself just fails, when the `a.out` it runs is killed
+        let r = req("cpu_hog").with_limits(
+            ResourceLimits {
+                cpu_seconds: Some(1),
+                ..ResourceLimits::unlimited()
+            });
+        match r.whole_build().outcome {
+            LimitExceeded(Test, CpuTime) => (),
+            _ => { assert!(false); }
+        };
+    }
+
+    #[test]
+    fn cpu_limit_reported() {
+        let r = req("cpu_hog")
+            .with_commands(
+                StageCommands {
+                    env: Vec::new(),
+                    build: vec!(CommandLine::new("gcc", &["main.c"])),
+                    test: CommandLine::new("./a.out", &[])
+                })
+            .with_limits(
+                ResourceLimits {
+                    cpu_seconds: Some(1),
+                    ..ResourceLimits::unlimited()
+                });
+        match r.whole_build().outcome {
+            LimitExceeded(Test, CpuTime) => (),
+            _ => { assert!(false); }
+        };
+    }
+
+    #[test]
+    fn syscall_blocked_through_make() {
+        // `make` itself just fails, when the `a.out` it runs is killed
+        let r = req("blocked_syscall").with_seccomp(Some(FileIo));
+        match r.whole_build().outcome {
+            SyscallBlocked(Test, FileIo) => (),
+            _ => { assert!(false); }
+        };
+    }
+
+    #[test]
+    fn test_junit_report() {
+        let dir = Path::new("test/junit_report");
+        let r = TestingRequest::with_parser(
+            dir.clone(),
+            Path::new("test/makefile"),
+            box JUnitParser::from_pattern(&dir, "reports/*.xml"));
+        match r.whole_build().outcome {
+            TestSuccess(u, _) => {
+                assert_eq!(u.len(), 2);
+                let t1 = u.get(&"main.test1".to_string()).unwrap_msg(line!());
+                assert_eq!(t1.result, Pass);
+                assert_eq!(t1.duration, Some(0.01));
+                let t2 = u.get(&"main.test2".to_string()).unwrap_msg(line!());
+                assert_eq!(t2.result, Fail);
+                assert_eq!(t2.message, Some("expected 1".to_string()));
             },
             _ => { assert!(false); }
         };
@@ -14,26 +14,50 @@ extern crate pg_typeprovider;
 extern crate github;
 
 use self::github::notification::PushNotification;
-use self::github::clone_url::CloneUrl;
+use std::io::IoResult;
 use builder::BuildResult;
+use builder::local::LocalSource;
 
 use self::EntryStatus::{Pending, InProgress, Done};
 
+/// Where the code of a pending build comes from.
+pub enum Source {
+    /// Along with the commit to build; `None` for commits recorded
+    /// before SHAs were, or whose push didn't come with one, which
+    /// build the branch
+    GitHub(PushNotification, Option<String>),
+    Local(LocalSource)
+}
+
 pub struct PendingBuild {
-    pub clone_url: CloneUrl,
-    pub branch: String,
+    pub source: Source,
+    /// `git_project_name` of the assignment this is a submission for
+    pub assignment: String,
     build_id: i32
 }
 
 /// Type A is some key
 pub trait Database : Send {
-    fn add_pending(&self, entry: PushNotification);
+    /// `sha` is the commit which was pushed, if known.  It's only
+    /// recorded if it looks like a SHA; otherwise the branch is built.
+    fn add_pending(&self, entry: PushNotification, sha: Option<String>);
+
+    /// Like `add_pending`, but for a submission which didn't come from
+    /// GitHub.  `username` is the submitter's GitHub username, which
+    /// is how users are looked up either way.  Fails if there is no
+    /// such user or assignment.
+    fn add_pending_local(&self, source: LocalSource, username: &str,
+                         assignment: &str) -> IoResult<()>;
 
     /// Optionally gets a pending build from the database.
     /// If `Some` is returned, it will not be returned again.
     /// If `None` is returned, it is expected that the caller will sleep.
     fn get_pending(&self) -> Option<PendingBuild>;
 
+    /// Puts a build gotten from `get_pending` back, so that it
+    /// will be returned again.  For builds which were interrupted.
+    fn return_pending(&self, entry: &PendingBuild);
+
     fn add_test_results(&self, entry: &PendingBuild, results: BuildResult);
 }
 
@@ -59,7 +83,7 @@ pub mod postgres_db {
     extern crate github;
     extern crate openssl;
 
-    use super::PendingBuild;
+    use super::{PendingBuild, Source};
 
     use self::github::notification::PushNotification;
     use self::github::clone_url::CloneUrl;
@@ -69,9 +93,13 @@ pub mod postgres_db {
 
     use self::openssl::ssl::{SslContext, SslMethod};
 
+    use std::io::{IoResult, IoError, InvalidInput};
+
     use super::postgres::{Connection, GenericConnection, SslMode, ToSql};
 
     use builder::BuildResult;
+    use builder::github::valid_sha;
+    use builder::local::LocalSource;
 
     use super::EntryStatus::{Pending, InProgress, Done};
     use super::Database;
@@ -159,10 +187,26 @@ pub mod postgres_db {
                 .search(conn, Some(1))
                 .pop()
                 .unwrap(); // should be in there
+            let assignment = AssignmentSearch::new()
+                .where_id(self.assignment_id)
+                .search(conn, Some(1))
+                .pop()
+                .unwrap(); // likewise
+            let source =
+                match LocalSource::from_url(commit.clone_url.as_slice()) {
+                    Some(local) => Source::Local(local),
+                    None => {
+                        let pn = PushNotification {
+                            clone_url: CloneUrl::new_from_str(
+                                commit.clone_url.as_slice()).unwrap(),
+                            branch: commit.branch_name
+                        };
+                        Source::GitHub(pn, commit.sha)
+                    }
+                };
             PendingBuild {
-                clone_url: CloneUrl::new_from_str(
-                    commit.clone_url.as_slice()).unwrap(),
-                branch: commit.branch_name,
+                source: source,
+                assignment: assignment.git_project_name,
                 build_id: self.id
             }
         }
@@ -201,11 +245,15 @@ pub mod postgres_db {
             .unwrap()
     }
 
+    /// Local submissions are recorded with the URL of their source
+    /// as the clone URL, and no branch.
     fn insert_commit(conn: &GenericConnection,
                      user: &User,
                      assignment: &Assignment,
                      submission: &Submission,
-                     pn: &PushNotification) -> Commit {
+                     clone_url: String,
+                     branch: String,
+                     sha: Option<String>) -> Commit {
         let current_time = now().to_timespec();
         CommitInsert {
             assignment_id: assignment.id,
@@ -213,8 +261,9 @@ pub mod postgres_db {
             created_at: current_time.clone(),
             updated_at: current_time.clone(),
             submission_id: submission.id,
-            branch_name: pn.branch.clone(),
-            clone_url: pn.clone_url.url.to_string()
+            branch_name: branch,
+            clone_url: clone_url,
+            sha: sha
         }.insert(conn);
         CommitSearch::new()
             .where_created_at(current_time)
@@ -241,7 +290,8 @@ pub mod postgres_db {
     }
                     
     impl Database for PostgresDatabase {
-        fn add_pending(&self, entry: PushNotification) {
+        fn add_pending(&self, entry: PushNotification, sha: Option<String>) {
+            let sha = sha.and_then(|s| valid_sha(s.as_slice()));
             self.with_connection(|conn| {
                 let trans = conn.transaction().unwrap();
                 let op_user = get_user_by_github_username(
@@ -257,7 +307,9 @@ pub mod postgres_db {
                                                    &user,
                                                    &assignment,
                                                    &submission,
-                                                   &entry);
+                                                   entry.clone_url.url.to_string(),
+                                                   entry.branch.clone(),
+                                                   sha.clone());
                         insert_build(&trans, &user, &assignment, &commit);
                         trans.commit().unwrap();
                     },
@@ -269,6 +321,46 @@ pub mod postgres_db {
             });
         }
 
+        fn add_pending_local(&self, source: LocalSource, username: &str,
+                             assignment: &str) -> IoResult<()> {
+            self.with_connection(|conn| {
+                let trans = conn.transaction().unwrap();
+                let op_user = get_user_by_github_username(&trans, username);
+                let op_assignment = get_assignment_by_git_project_name(&trans, assignment);
+                match (op_user, op_assignment) {
+                    (Some(user), Some(assignment)) => {
+                        let submission = insert_submission(&trans,
+                                                           &user,
+                                                           &assignment);
+                        let commit = insert_commit(&trans,
+                                                   &user,
+                                                   &assignment,
+                                                   &submission,
+                                                   source.to_url(),
+                                                   String::new(),
+                                                   None);
+                        insert_build(&trans, &user, &assignment, &commit);
+                        trans.commit().unwrap();
+                        Ok(())
+                    },
+                    (op_user, _) => {
+                        trans.set_rollback();
+                        trans.finish().unwrap();
+                        let (desc, detail) =
+                            match op_user {
+                                None => ("No such user", username),
+                                Some(_) => ("No such assignment", assignment)
+                            };
+                        Err(IoError {
+                            kind: InvalidInput,
+                            desc: desc,
+                            detail: Some(detail.to_string())
+                        })
+                    }
+                }
+            })
+        }
+
         fn get_pending(&self) -> Option<PendingBuild> {
             self.with_connection(|conn| {
                 loop {
@@ -305,6 +397,17 @@ pub mod postgres_db {
             })
         }
 
+        fn return_pending(&self, entry: &PendingBuild) {
+            let num_updated =
+                BuildUpdate::new()
+                .status_to((&Pending).to_int())
+                .updated_at_to(now().to_timespec())
+                .where_id(entry.build_id)
+                .where_status((&InProgress).to_int())
+                .update(&self.db);
+            assert_eq!(num_updated, 1);
+        }
+
         fn add_test_results(&self, entry: &PendingBuild, results: BuildResult) {
             let num_updated =
                 BuildUpdate::new()
@@ -0,0 +1,346 @@
+// Differential testing: the instructor's reference solution and the
+// submission are run on the same inputs, which come from a generator,
+// and whatever the reference prints is what the submission has to
+// print.  Each run is a test of its own, named `random_<n>`, whose
+// message has the seed its input was generated from, so that any
+// mismatch can be reproduced by running the generator with that seed.
+//
+// The reference is built once per worker and version, under the build
+// root.  A version is a digest of the reference's files along with the
+// commands which build it, so changing either gets a fresh build, and
+// the builds of older versions are removed.
+
+extern crate openssl;
+
+use self::openssl::crypto::hash::{hash, HashType};
+use serialize::hex::ToHex;
+use std::collections::HashMap;
+use std::io;
+use std::io::{fs, Command, IoResult, IoError, OtherIoError};
+use std::io::fs::PathExtensions;
+use std::os;
+use std::rand;
+
+use builder::{CommandLine, CommandLog, ResourceLimits, TestOutcome, run_with_input,
+              run_logged_commands, now_millis};
+use builder::Stage::{SetupEnv, Test};
+use builder::iotest::{Comparison, judge};
+use builder::workspace::worker_id;
+use builder::TestResult::TimedOut;
+use overlay::InstructorFiles;
+use util::{MessagingUnwrapper, warn};
+
+/// Where reference builds go, under the build root.
+pub static REFERENCE_DIR: &'static str = "references";
+
+/// How many runs there are for assignments which don't say.
+pub static DEFAULT_RUNS: uint = 10;
+
+// Where a reference is built before it's complete
+static PARTIAL_SUFFIX: &'static str = ".partial";
+
+// Hex digits of the digest which go into version names
+static VERSION_DIGITS: uint = 16;
+
+#[deriving(Show, PartialEq, Clone)]
+pub struct Differential {
+    /// The instructor's solution, along with the generator
+    pub reference: Path,
+    /// Run in the reference build with the seed as its last argument;
+    /// whatever it prints is the input
+    pub generator: CommandLine,
+    /// Run in both the reference build and the workspace
+    pub program: CommandLine,
+    pub runs: uint,
+    /// Seed of the first run, with those of later runs counting up from
+    /// it.  If not set, each build gets a random one.
+    pub seed: Option<u32>,
+    pub comparison: Comparison,
+    /// For each run of each program, in milliseconds
+    pub timeout: Option<u64>
+}
+
+fn failure(desc: &'static str, detail: String) -> IoError {
+    IoError {
+        kind: OtherIoError,
+        desc: desc,
+        detail: Some(detail)
+    }
+}
+
+impl Differential {
+    fn name<'a>(&'a self) -> &'a str {
+        self.reference.filename_str().unwrap_or("reference")
+    }
+
+    /// Names the build of the reference made by `build`.
+    pub fn version(&self, build: &Vec<CommandLine>) -> IoResult<String> {
+        let mut all = try!(InstructorFiles::scan(&self.reference)).fingerprint();
+        for line in build.iter() {
+            all.push_all(line.program.as_bytes());
+            for arg in line.args.iter() {
+                all.push(0);
+                all.push_all(arg.as_bytes());
+            }
+            all.push(b'\n');
+        }
+        let digest = hash(HashType::SHA256, all.as_slice()).as_slice().to_hex();
+        Ok(format!("{}-{}", self.name(), digest.as_slice().slice_to(VERSION_DIGITS)))
+    }
+
+    /// Whether `name` is a build (or partial build) of some version
+    /// of this reference.
+    fn is_version(&self, name: &str) -> bool {
+        let prefix = format!("{}-", self.name());
+        if !name.starts_with(prefix.as_slice()) {
+            return false;
+        }
+        let rest = name.slice_from(prefix.len());
+        let digest =
+            if rest.ends_with(PARTIAL_SUFFIX) {
+                rest.slice_to(rest.len() - PARTIAL_SUFFIX.len())
+            } else {
+                rest
+            };
+        digest.len() == VERSION_DIGITS && digest.chars().all(|c| c.to_digit(16).is_some())
+    }
+
+    /// Counts up from the seed, wrapping around past `u32::MAX`, as
+    /// the first one is often random.
+    pub fn seeds(&self) -> Vec<u32> {
+        let first = self.seed.unwrap_or_else(|| rand::random()) as u64;
+        range(0, self.runs as u64).map(|i| (first + i) as u32).collect()
+    }
+
+    /// How to get the input of the run with the given seed.
+    fn reproduce(&self, seed: u32) -> String {
+        let mut argv = vec!(self.generator.program.clone());
+        argv.push_all(self.generator.args.as_slice());
+        format!("seed {} (input from `{} {}`)", seed, argv.connect(" "), seed)
+    }
+
+    /// Output of a trusted command in the reference build, which has to
+    /// succeed and fit within the output cap.
+    fn run_reference(&self, reference: &Path, line: &CommandLine, input: String,
+                     seed: u32, what: &'static str) -> IoResult<String> {
+        let mut c = line.to_command();
+        c.cwd(reference);
+        let (res, log) = run_with_input(&c, input.into_bytes(), self.timeout, Test);
+        let exit = try!(res);
+        if !exit.success() {
+            return Err(failure(what, format!("seed {}, {}: {}", seed, exit, log.stderr)));
+        }
+        if log.truncated {
+            return Err(failure("Reference output is too long", format!("seed {}", seed)));
+        }
+        Ok(log.stdout)
+    }
+
+    /// Runs `command`, the submission's program, once per seed.  As with
+    /// I/O tests, runs aren't logged, and `timeout` is for all of them
+    /// together: no run may go past it, and those there's no time left
+    /// for time out without being run.  The generator or the reference
+    /// failing is an error, rather than the fault of the submission.
+    pub fn run(&self, reference: &Path, command: &Command, limits: &ResourceLimits,
+               timeout: Option<u64>) -> IoResult<HashMap<String, TestOutcome>> {
+        let deadline = timeout.map(|t| now_millis() + t);
+        let mut map = HashMap::new();
+        for (i, seed) in self.seeds().into_iter().enumerate() {
+            let name = format!("random_{}", i + 1);
+            let remaining = deadline.map(|d| d.saturating_sub(now_millis()));
+            if remaining == Some(0) {
+                map.insert(name, TestOutcome::new(TimedOut).with_message(Some(
+                    format!("{}\nNot run; ran out of time for differential tests",
+                            self.reproduce(seed)))));
+                continue;
+            }
+            let run_timeout =
+                match (self.timeout, remaining) {
+                    (Some(t), Some(r)) => Some(t.min(r)),
+                    (t, r) => t.or(r)
+                };
+
+            let mut generator = self.generator.clone();
+            generator.args.push(seed.to_string());
+            let input = try!(self.run_reference(reference, &generator, String::new(), seed,
+                                                "Input generator failed"));
+            let expected = try!(self.run_reference(reference, &self.program, input.clone(),
+                                                   seed, "Reference solution failed"));
+
+            let start = now_millis();
+            let (res, log) = run_with_input(command, input.into_bytes(), run_timeout, Test);
+            let duration = (now_millis() - start) as f64 / 1000.0;
+            let outcome = try!(judge(&self.comparison, res.map(|_| ()), &log, limits,
+                                     expected.as_slice()));
+            let message =
+                match outcome.message {
+                    Some(ref m) => format!("{}\n{}", self.reproduce(seed), m),
+                    None => self.reproduce(seed)
+                };
+            map.insert(name, outcome.with_message(Some(message)).with_duration(Some(duration)));
+        }
+        Ok(map)
+    }
+}
+
+/// Builds of references, which only the worker can get at.
+pub struct ReferenceCache {
+    dir: Path
+}
+
+impl ReferenceCache {
+    pub fn new(dir: Path) -> ReferenceCache {
+        ReferenceCache {
+            dir: dir
+        }
+    }
+
+    /// The cache of this worker under `build_root`.
+    pub fn for_worker(build_root: &Path) -> ReferenceCache {
+        ReferenceCache::new(build_root.join(REFERENCE_DIR).join(worker_id()))
+    }
+
+    /// Where the reference of `diff` is built by `build`, building it
+    /// first if this worker hasn't yet.  The build runs as the worker,
+    /// outside of any sandbox, as it's the instructor's code.
+    pub fn build(&self, diff: &Differential, build: &Vec<CommandLine>, timeout: Option<u64>,
+                 logs: &mut Vec<CommandLog>) -> IoResult<Path> {
+        let built = os::getcwd().join(self.dir.join(try!(diff.version(build))));
+        if built.exists() {
+            return Ok(built);
+        }
+        if !self.dir.exists() {
+            try!(fs::mkdir_recursive(&self.dir, io::USER_RWX));
+        }
+        let partial = Path::new(
+            format!("{}{}", built.as_str().unwrap_msg(line!()), PARTIAL_SUFFIX));
+        if partial.exists() {
+            try!(fs::rmdir_recursive(&partial));
+        }
+        try!(fs::mkdir(&partial, io::USER_RWX));
+
+        let mut copy = Command::new("cp");
+        copy.arg("-R");
+        copy.arg(format!("{}/.", diff.reference.as_str().unwrap_msg(line!())));
+        copy.arg(partial.as_str().unwrap_msg(line!()));
+        let mut commands = vec!(copy);
+        for line in build.iter() {
+            let mut c = line.to_command();
+            c.cwd(&partial);
+            commands.push(c);
+        }
+        try!(run_logged_commands(&commands, timeout, SetupEnv, logs));
+        try!(fs::rename(&partial, &built));
+
+        match self.remove_old(diff, &built) {
+            Ok(_) => (),
+            Err(e) => warn(format!("Could not remove old references from {}: {}",
+                                   self.dir.display(), e))
+        }
+        Ok(built)
+    }
+
+    /// Removes builds of every version of `diff`'s reference but `keep`.
+    fn remove_old(&self, diff: &Differential, keep: &Path) -> IoResult<()> {
+        for path in try!(fs::readdir(&self.dir)).iter() {
+            match path.filename_str() {
+                Some(name) if diff.is_version(name) && path.filename() != keep.filename() => {
+                    try!(fs::rmdir_recursive(path));
+                },
+                _ => ()
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::default::Default;
+    use std::io::{fs, Command, USER_RWX};
+    use std::io::fs::PathExtensions;
+
+    use builder::{CommandLine, now_millis};
+    use builder::TestResult::TimedOut;
+    use builder::iotest::Comparison::Exact;
+    use super::{Differential, ReferenceCache};
+
+    use util::MessagingUnwrapper;
+
+    fn differential() -> Differential {
+        Differential {
+            reference: Path::new("test/differential_reference"),
+            generator: CommandLine::new("sh", &["gen.sh"]),
+            program: CommandLine::new("./a.out", &[]),
+            runs: 3,
+            seed: Some(7),
+            comparison: Exact,
+            timeout: Some(5000)
+        }
+    }
+
+    #[test]
+    fn versions_follow_build_commands() {
+        let diff = differential();
+        let make = vec!(CommandLine::new("make", &["-s", "build"]));
+        let gcc = vec!(CommandLine::new("gcc", &["main.c"]));
+        let version = diff.version(&make).unwrap_msg(line!());
+        assert!(version.as_slice().starts_with("differential_reference-"));
+        assert_eq!(version, diff.version(&make).unwrap_msg(line!()));
+        assert!(version != diff.version(&gcc).unwrap_msg(line!()));
+
+        assert!(diff.is_version(version.as_slice()));
+        assert!(diff.is_version(format!("{}.partial", version).as_slice()));
+        assert!(!diff.is_version("differential_reference-old-0123456789abcdef"));
+    }
+
+    #[test]
+    fn seeds_count_up() {
+        assert_eq!(differential().seeds(), vec!(7, 8, 9));
+        let mut top = differential();
+        top.seed = Some(::std::u32::MAX);
+        assert_eq!(top.seeds(), vec!(::std::u32::MAX, 0, 1));
+    }
+
+    #[test]
+    fn builds_once_per_version() {
+        let dir = Path::new("test/references/cache");
+        let _ = fs::rmdir_recursive(&dir);
+        let cache = ReferenceCache::new(dir.clone());
+        let diff = differential();
+
+        // a stale version, which goes once the current one is built
+        let stale = dir.join("differential_reference-0123456789abcdef");
+        assert!(fs::mkdir_recursive(&stale, USER_RWX).is_ok());
+
+        let build = vec!(CommandLine::new("gcc", &["main.c"]));
+        let mut logs = Vec::new();
+        let built = cache.build(&diff, &build, None, &mut logs).unwrap_msg(line!());
+        assert!(built.join("a.out").exists());
+        assert_eq!(logs.len(), 2);
+        assert!(!stale.exists());
+
+        let mut logs = Vec::new();
+        assert_eq!(cache.build(&diff, &build, None, &mut logs).unwrap_msg(line!()), built);
+        assert!(logs.is_empty());
+    }
+
+    #[test]
+    fn runs_share_the_timeout() {
+        let dir = Path::new("test/references/deadline");
+        let _ = fs::rmdir_recursive(&dir);
+        let cache = ReferenceCache::new(dir);
+        let diff = differential();
+        let build = vec!(CommandLine::new("gcc", &["main.c"]));
+        let built = cache.build(&diff, &build, None, &mut Vec::new()).unwrap_msg(line!());
+
+        let mut sleep = Command::new("sleep");
+        sleep.arg("5");
+        let start = now_millis();
+        let results = diff.run(&built, &sleep, &Default::default(), Some(300))
+            .unwrap_msg(line!());
+        assert!(now_millis() - start < 5000);
+        assert_eq!(results.len(), 3);
+        assert!(results.values().all(|o| o.result == TimedOut));
+    }
+}
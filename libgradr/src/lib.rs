@@ -1,7 +1,16 @@
 #![feature(phase, macro_rules)]
 
+extern crate serialize;
+
+pub mod assignment;
 pub mod builder;
 pub mod database;
+pub mod differential;
+pub mod memcheck;
+pub mod mirror;
 pub mod worker;
 pub mod notification_listener;
+pub mod overlay;
+pub mod sandbox;
+pub mod seccomp;
 pub mod util;
@@ -0,0 +1,536 @@
+// Memory checking, as a stage of its own.  The stage runs a command
+// (by default, the test command) under valgrind, or with sanitizers,
+// which means the command has to run a build made with
+// `-fsanitize=address,undefined`; the stage can make that build first.
+// Reports are parsed into findings once the command is done.  Only the
+// findings matter; the stage fails if there are any, and its failure
+// policy decides what that does to the score.  The exit status of the
+// command is ignored, as it's usually that of the tests.
+//
+// Valgrind writes a log per process into a directory under the build
+// root, outside of the workspace, which is only read once nothing of
+// the submission's is left running and the worker has taken it back.
+// Every log has to be complete, so deleting them doesn't get rid of
+// findings.  Sanitizers report on the command's stderr instead, which
+// the worker reads as it's written; that way, the command can still
+// run under the assignment's seccomp profile, which wouldn't let them
+// write files.  LeakSanitizer needs `ptrace`, which no profile allows,
+// so under a profile, leaks are only found by valgrind.
+//
+// AddressSanitizer reserves terabytes of address space, so a memory
+// limit has to be left off for it to work.
+
+extern crate serialize;
+
+use self::serialize::json::{ToJson, Json};
+use std::collections::HashMap;
+use std::io;
+use std::io::{fs, File, IoResult, IoError, OtherIoError};
+use std::io::util::LimitReader;
+
+use builder::{Stage, CommandLine, CommandLog};
+use builder::Stage::Named;
+
+use self::Tool::{Valgrind, Sanitizers};
+
+/// Name of the stage which does the checking.
+pub static MEMCHECK_STAGE: &'static str = "memcheck";
+
+/// Where valgrind's logs go, under the build root.
+pub static REPORT_ROOT: &'static str = "memcheck_reports";
+
+/// Where the report directory shows up inside of the sandbox.
+pub static SANDBOX_REPORT_DIR: &'static str = "/gradr_memcheck";
+
+/// Most bytes read from any one report.  A log cut off by this isn't
+/// complete.
+pub static MAX_REPORT_BYTES: uint = 1024 * 1024;
+
+/// Most reports read, at one per process.
+pub static MAX_REPORTS: uint = 64;
+
+// What valgrind ends every complete log with
+static VALGRIND_SUMMARY: &'static str = "ERROR SUMMARY:";
+
+/// For memory checking stages which don't give their own, in
+/// milliseconds.  Programs are a lot slower under valgrind.
+pub static DEFAULT_MEMCHECK_TIMEOUT: u64 = 10 * 60 * 1000;
+
+#[deriving(Show, PartialEq, Clone)]
+pub enum Tool {
+    Valgrind,
+    /// AddressSanitizer, LeakSanitizer and UndefinedBehaviorSanitizer
+    Sanitizers
+}
+
+impl Tool {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Valgrind => "valgrind",
+            Sanitizers => "sanitizers"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Tool> {
+        [Valgrind, Sanitizers].iter()
+            .find(|t| t.name() == name)
+            .map(|t| t.clone())
+    }
+}
+
+/// A single problem which a tool reported.
+#[deriving(Show, PartialEq, Clone)]
+pub struct Finding {
+    /// E.g., `invalid_read`, `leak_definite` or `heap_buffer_overflow`
+    pub kind: String,
+    /// The headline of the report, as the tool put it
+    pub message: String,
+    /// Innermost frame first
+    pub stack: Vec<String>,
+    /// Only for leaks
+    pub bytes_lost: Option<u64>
+}
+
+impl ToJson for Finding {
+    fn to_json(&self) -> Json {
+        let mut map = HashMap::new();
+        map.insert("kind".to_string(), self.kind.to_json());
+        map.insert("message".to_string(), self.message.to_json());
+        map.insert("stack".to_string(), self.stack.to_json());
+        map.insert("bytes_lost".to_string(), self.bytes_lost.to_json());
+        map.to_json()
+    }
+}
+
+fn finding(kind: &str, message: &str, bytes_lost: Option<u64>) -> Finding {
+    Finding {
+        kind: kind.to_string(),
+        message: message.to_string(),
+        stack: Vec::new(),
+        bytes_lost: bytes_lost
+    }
+}
+
+/// Reads `1,024` as well as `1024`.
+fn leading_bytes(s: &str) -> Option<u64> {
+    s.words().next().and_then(|n| {
+        from_str::<u64>(n.chars().filter(|c| *c != ',').collect::<String>().as_slice())
+    })
+}
+
+/// `None` for anything which isn't a problem, such as summaries.
+fn valgrind_kind(headline: &str) -> Option<&'static str> {
+    static LEAKS: &'static [(&'static str, &'static str)] = &[
+        ("are definitely lost", "leak_definite"),
+        ("are indirectly lost", "leak_indirect"),
+        ("are possibly lost", "leak_possible"),
+        ("are still reachable", "leak_reachable")];
+    static ERRORS: &'static [(&'static str, &'static str)] = &[
+        ("Invalid read", "invalid_read"),
+        ("Invalid write", "invalid_write"),
+        ("Invalid free", "invalid_free"),
+        ("Mismatched free", "mismatched_free"),
+        ("Conditional jump or move depends on uninitialised", "uninitialised_value"),
+        ("Use of uninitialised", "uninitialised_value"),
+        ("Syscall param", "uninitialised_value"),
+        ("Source and destination overlap", "overlap")];
+
+    for &(text, kind) in LEAKS.iter() {
+        if headline.contains(text) {
+            return Some(kind);
+        }
+    }
+    for &(text, kind) in ERRORS.iter() {
+        if headline.starts_with(text) {
+            return Some(kind);
+        }
+    }
+    None
+}
+
+/// Drops the `==pid==` which valgrind puts in front of every line.
+fn strip_pid(line: &str) -> &str {
+    if line.starts_with("==") {
+        match line.slice_from(2).find_str("==") {
+            Some(i) => return line.slice_from(i + 4),
+            None => ()
+        }
+    }
+    line
+}
+
+/// Parses what valgrind's memcheck writes to its log.  Each problem is
+/// a paragraph, which starts with the headline, followed by the stack.
+/// Anything after the first stack, like where a block was allocated,
+/// is left out.
+pub fn parse_valgrind(log: &str) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut current: Option<Finding> = None;
+    let mut in_stack = false;
+    let mut paragraph_start = true;
+
+    for line in log.lines() {
+        let line = strip_pid(line).trim();
+        if line.is_empty() {
+            findings.extend(current.take().into_iter().filter(|f| !f.stack.is_empty()));
+            in_stack = false;
+            paragraph_start = true;
+            continue;
+        }
+        if paragraph_start {
+            paragraph_start = false;
+            current = valgrind_kind(line).map(|kind| {
+                let bytes = if kind.starts_with("leak_") { leading_bytes(line) } else { None };
+                finding(kind, line, bytes)
+            });
+            in_stack = true;
+            continue;
+        }
+        if !in_stack || !(line.starts_with("at 0x") || line.starts_with("by 0x")) {
+            in_stack = false;
+            continue;
+        }
+        match current {
+            Some(ref mut f) => {
+                let frame = line.find_str(": ").map(|i| line.slice_from(i + 2)).unwrap_or(line);
+                f.stack.push(frame.to_string());
+            },
+            None => ()
+        }
+    }
+    findings.extend(current.take().into_iter().filter(|f| !f.stack.is_empty()));
+    findings
+}
+
+/// `#0 0x4011d6 in main /build/main.c:6` is `main /build/main.c:6`.
+/// Frames without a function are left as whatever follows the address.
+fn sanitizer_frame(line: &str) -> Option<String> {
+    if !line.starts_with("#") ||
+        !line.chars().nth(1).map(|c| c.to_digit(10).is_some()).unwrap_or(false) {
+        return None;
+    }
+    match line.find_str(" in ") {
+        Some(i) => Some(line.slice_from(i + 4).to_string()),
+        None => Some(line.words().skip(2).collect::<Vec<&str>>().connect(" "))
+    }
+}
+
+/// Starts a finding for the headline of a sanitizer report.
+fn sanitizer_headline(line: &str) -> Option<Finding> {
+    static ASAN: &'static str = "ERROR: AddressSanitizer: ";
+    static UBSAN: &'static str = "runtime error: ";
+
+    match line.find_str(ASAN) {
+        Some(i) => {
+            let message = line.slice_from(i + ASAN.len());
+            let kind = message.words().next().unwrap_or("unknown").replace("-", "_");
+            return Some(finding(kind.as_slice(), message, None));
+        },
+        None => ()
+    }
+    for &(prefix, kind) in [("Direct leak of ", "leak_direct"),
+                            ("Indirect leak of ", "leak_indirect")].iter() {
+        if line.starts_with(prefix) {
+            return Some(finding(kind, line, leading_bytes(line.slice_from(prefix.len()))));
+        }
+    }
+    // the location comes first, so it's kept in the message
+    if line.contains(UBSAN) {
+        Some(finding("undefined_behavior", line, None))
+    } else {
+        None
+    }
+}
+
+/// Parses what AddressSanitizer, LeakSanitizer and UBSan write to their
+/// logs.  As with valgrind, only the first stack of each report is kept.
+pub fn parse_sanitizers(log: &str) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut current: Option<Finding> = None;
+    let mut stack_done = false;
+
+    for line in log.lines() {
+        let line = line.trim();
+        match sanitizer_headline(line) {
+            Some(f) => {
+                findings.extend(current.take().into_iter());
+                current = Some(f);
+                stack_done = false;
+                continue;
+            },
+            None => ()
+        }
+        if stack_done {
+            continue;
+        }
+        match current {
+            Some(ref mut f) => {
+                match sanitizer_frame(line) {
+                    Some(frame) => f.stack.push(frame),
+                    // the stack ends at the first line which isn't part of it
+                    None => stack_done = !f.stack.is_empty()
+                }
+            },
+            None => ()
+        }
+    }
+    findings.extend(current.take().into_iter());
+    findings
+}
+
+#[deriving(Show, PartialEq, Clone)]
+pub struct MemCheck {
+    pub tool: Tool,
+    /// Run first, to make a build for the tool
+    pub build: Vec<CommandLine>,
+    pub command: CommandLine
+}
+
+fn incomplete(detail: String) -> IoError {
+    IoError {
+        kind: OtherIoError,
+        desc: "Memory checking reports are missing or incomplete",
+        detail: Some(detail)
+    }
+}
+
+/// The contents of every regular file in `dir`, up to
+/// `MAX_REPORT_BYTES` of each.  Anything else, like a symlink or a
+/// FIFO, is skipped, so nothing is read from outside of `dir`.  No one
+/// else may be able to change `dir` by now.
+pub fn read_reports(dir: &Path) -> IoResult<Vec<String>> {
+    let mut paths = try!(fs::readdir(dir));
+    if paths.len() > MAX_REPORTS {
+        return Err(incomplete(format!("{} reports, but only {} are read",
+                                      paths.len(), MAX_REPORTS)));
+    }
+    paths.sort();
+    let mut reports = Vec::new();
+    for path in paths.iter() {
+        if try!(fs::lstat(path)).kind != io::TypeFile {
+            continue;
+        }
+        let file = try!(File::open(path));
+        let bytes = try!(LimitReader::new(file, MAX_REPORT_BYTES).read_to_end());
+        reports.push(String::from_utf8_lossy(bytes.as_slice()).into_string());
+    }
+    Ok(reports)
+}
+
+impl MemCheck {
+    pub fn stage() -> Stage {
+        Named(MEMCHECK_STAGE.to_string())
+    }
+
+    /// Makes `command` run under the tool.  Valgrind's logs go to
+    /// `report_dir`, as the command sees it.  Leak checking can be left
+    /// off for sanitizers, for when it can't work.
+    pub fn wrap(&self, report_dir: &Path, detect_leaks: bool) -> CommandLine {
+        let (program, mut args) =
+            match self.tool {
+                Valgrind => ("valgrind", vec!(
+                    "--trace-children=yes".to_string(),
+                    "--trace-children-skip=*/make,*/sh,*/bash,*/env".to_string(),
+                    "--leak-check=full".to_string(),
+                    format!("--log-file={}/valgrind.%p.log", report_dir.display()))),
+                // sanitizers are configured through the environment
+                Sanitizers => ("env", vec!(
+                    format!("ASAN_OPTIONS=detect_leaks={}", if detect_leaks { 1u } else { 0 }),
+                    "UBSAN_OPTIONS=print_stacktrace=1".to_string()))
+            };
+        args.push(self.command.program.clone());
+        args.push_all(self.command.args.as_slice());
+        CommandLine {
+            program: program.to_string(),
+            args: args
+        }
+    }
+
+    /// Findings of the tool, from the reports in `report_dir` or the
+    /// log of the command.  Identical findings (e.g., from a test which
+    /// ran the same code twice) are only kept once.  Reports which may
+    /// have been cut short are an error, as they may be missing findings.
+    pub fn findings(&self, report_dir: &Path, log: &CommandLog) -> IoResult<Vec<Finding>> {
+        let parsed =
+            match self.tool {
+                Valgrind => {
+                    let reports = try!(read_reports(report_dir));
+                    if reports.is_empty() {
+                        return Err(incomplete("valgrind left no logs".to_string()));
+                    }
+                    if reports.iter().any(|r| !r.as_slice().contains(VALGRIND_SUMMARY)) {
+                        return Err(incomplete("a valgrind log was cut short".to_string()));
+                    }
+                    reports.iter().flat_map(|r| parse_valgrind(r.as_slice()).into_iter())
+                        .collect::<Vec<Finding>>()
+                },
+                Sanitizers => {
+                    if log.truncated {
+                        return Err(incomplete("the command's output was cut off".to_string()));
+                    }
+                    parse_sanitizers(log.stderr.as_slice())
+                }
+            };
+        let mut findings = Vec::new();
+        for f in parsed.into_iter() {
+            if !findings.contains(&f) {
+                findings.push(f);
+            }
+        }
+        Ok(findings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{fs, File, USER_RWX};
+    use std::io::fs::PathExtensions;
+
+    use builder::{CommandLine, CommandLog};
+    use builder::Stage::Test;
+    use super::{MemCheck, Tool, parse_valgrind, parse_sanitizers, read_reports};
+    use super::Tool::{Valgrind, Sanitizers};
+
+    use util::MessagingUnwrapper;
+
+    static VALGRIND: &'static str = "==4242== Invalid read of size 4
+==4242==    at 0x1091A4: main (main.c:6)
+==4242==  Address 0x4a8f044 is 0 bytes after a block of size 4 alloc'd
+==4242==    at 0x483B7F3: malloc (vg_replace_malloc.c:309)
+==4242==    by 0x109196: main (main.c:5)
+==4242==
+==4242== 1,024 bytes in 1 blocks are definitely lost in loss record 1 of 1
+==4242==    at 0x483B7F3: malloc (vg_replace_malloc.c:309)
+==4242==    by 0x109183: leak (main.c:2)
+==4242==    by 0x1091B0: main (main.c:7)
+==4242==
+==4242== LEAK SUMMARY:
+==4242==    definitely lost: 1,024 bytes in 1 blocks
+";
+
+    static SANITIZERS: &'static str = "main.c:9:5: runtime error: signed integer overflow: 2147483647 + 1 cannot be represented in type 'int'
+    #0 0x401200 in main /build/main.c:9
+    #1 0x7f3a2c in __libc_start_main (/lib/x86_64-linux-gnu/libc.so.6+0x2d)
+
+=================================================================
+==77==ERROR: AddressSanitizer: heap-use-after-free on address 0x602000000010 at pc 0x4011d6
+READ of size 4 at 0x602000000010 thread T0
+    #0 0x4011d6 in main /build/main.c:12
+
+0x602000000010 is located 0 bytes inside of 4-byte region
+freed by thread T0 here:
+    #0 0x7f3a2d in free (/lib/x86_64-linux-gnu/libasan.so.6+0xb0)
+
+=================================================================
+==78==ERROR: LeakSanitizer: detected memory leaks
+
+Direct leak of 40 byte(s) in 1 object(s) allocated from:
+    #0 0x7f3a2e in malloc (/lib/x86_64-linux-gnu/libasan.so.6+0xb1)
+    #1 0x401196 in main /build/main.c:4
+
+SUMMARY: AddressSanitizer: 40 byte(s) leaked in 1 allocation(s).
+";
+
+    #[test]
+    fn names_round_trip() {
+        for t in [Valgrind, Sanitizers].iter() {
+            assert_eq!(Tool::from_name(t.name()), Some(t.clone()));
+        }
+        assert_eq!(Tool::from_name("purify"), None);
+    }
+
+    #[test]
+    fn parses_valgrind() {
+        let findings = parse_valgrind(VALGRIND);
+        assert_eq!(findings.len(), 2);
+
+        assert_eq!(findings[0].kind.as_slice(), "invalid_read");
+        assert_eq!(findings[0].message.as_slice(), "Invalid read of size 4");
+        assert_eq!(findings[0].stack, vec!("main (main.c:6)".to_string()));
+        assert_eq!(findings[0].bytes_lost, None);
+
+        assert_eq!(findings[1].kind.as_slice(), "leak_definite");
+        assert_eq!(findings[1].bytes_lost, Some(1024));
+        assert_eq!(findings[1].stack,
+                   vec!("malloc (vg_replace_malloc.c:309)".to_string(),
+                        "leak (main.c:2)".to_string(),
+                        "main (main.c:7)".to_string()));
+    }
+
+    #[test]
+    fn parses_sanitizers() {
+        let findings = parse_sanitizers(SANITIZERS);
+        assert_eq!(findings.len(), 3);
+
+        assert_eq!(findings[0].kind.as_slice(), "undefined_behavior");
+        assert!(findings[0].message.as_slice().starts_with("main.c:9:5: runtime error"));
+        assert_eq!(findings[0].stack,
+                   vec!("main /build/main.c:9".to_string(),
+                        "__libc_start_main (/lib/x86_64-linux-gnu/libc.so.6+0x2d)".to_string()));
+
+        assert_eq!(findings[1].kind.as_slice(), "heap_use_after_free");
+        assert_eq!(findings[1].stack, vec!("main /build/main.c:12".to_string()));
+
+        assert_eq!(findings[2].kind.as_slice(), "leak_direct");
+        assert_eq!(findings[2].bytes_lost, Some(40));
+        assert_eq!(findings[2].stack.len(), 2);
+    }
+
+    #[test]
+    fn wraps_command() {
+        let check = MemCheck {
+            tool: Sanitizers,
+            build: Vec::new(),
+            command: CommandLine::new("./a.out", &[])
+        };
+        assert_eq!(check.wrap(&Path::new("/gradr_memcheck"), false), CommandLine::new(
+            "env", &["ASAN_OPTIONS=detect_leaks=0", "UBSAN_OPTIONS=print_stacktrace=1",
+                     "./a.out"]));
+    }
+
+    #[test]
+    fn reads_only_regular_files() {
+        let dir = Path::new("test/memcheck_reports/regular");
+        let _ = fs::rmdir_recursive(&dir);
+        assert!(fs::mkdir_recursive(&dir, USER_RWX).is_ok());
+        assert!(File::create(&dir.join("valgrind.1.log")).write_str("==1== hello").is_ok());
+        assert!(fs::symlink(&Path::new("/dev/zero"), &dir.join("valgrind.2.log")).is_ok());
+        assert!(fs::mkdir(&dir.join("valgrind.3.log"), USER_RWX).is_ok());
+
+        let reports = read_reports(&dir).unwrap_msg(line!());
+        assert_eq!(reports, vec!("==1== hello".to_string()));
+        assert!(fs::rmdir_recursive(&dir).is_ok());
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn valgrind_logs_have_to_be_complete() {
+        let dir = Path::new("test/memcheck_reports/complete");
+        let _ = fs::rmdir_recursive(&dir);
+        assert!(fs::mkdir_recursive(&dir, USER_RWX).is_ok());
+        let check = MemCheck {
+            tool: Valgrind,
+            build: Vec::new(),
+            command: CommandLine::new("./a.out", &[])
+        };
+        let log = CommandLog {
+            stage: Test,
+            command: "valgrind ./a.out".to_string(),
+            exit: None,
+            stdout: String::new(),
+            stderr: String::new(),
+            truncated: false
+        };
+        // no logs at all
+        assert!(check.findings(&dir, &log).is_err());
+
+        let path = dir.join("valgrind.4242.log");
+        assert!(File::create(&path).write_str(VALGRIND).is_ok());
+        assert!(check.findings(&dir, &log).is_err());
+
+        let complete = format!("{}==4242== ERROR SUMMARY: 1 errors from 1 contexts\n", VALGRIND);
+        assert!(File::create(&path).write_str(complete.as_slice()).is_ok());
+        assert_eq!(check.findings(&dir, &log).unwrap_msg(line!()).len(), 2);
+        assert!(fs::rmdir_recursive(&dir).is_ok());
+    }
+}
@@ -0,0 +1,191 @@
+// A per-worker cache of bare mirrors of submission repositories.
+// Rather than cloning from the remote for every build, the mirror of
+// the clone URL is fetched into (which only transfers what's new), and
+// the build clones from the mirror.
+//
+// Mirrors are evicted least recently used first, once all of them
+// together go over the disk budget.  The mirror a build is using is
+// never evicted out from under it.
+
+extern crate openssl;
+
+use self::openssl::crypto::hash::{hash, HashType};
+use serialize::hex::ToHex;
+use std::io;
+use std::io::{fs, File, IoResult};
+use std::io::fs::PathExtensions;
+use std::mem;
+use std::os;
+use std::sync::{Mutex, Once, ONCE_INIT};
+
+use builder::{CommandLog, run_logged_commands};
+use builder::Stage::SetupEnv;
+use builder::workspace::{disk_usage, worker_id};
+use util::{MessagingUnwrapper, warn};
+
+/// Where mirrors go, under the build root.
+pub static MIRROR_DIR: &'static str = "mirrors";
+
+// Touched whenever a mirror is used, for LRU eviction
+static LAST_USED: &'static str = "gradr-last-used";
+
+// Where a mirror is cloned to before it's complete
+static PARTIAL_SUFFIX: &'static str = ".partial";
+
+static mut BUDGET: *const Mutex<Option<u64>> = 0 as *const Mutex<Option<u64>>;
+static BUDGET_INIT: Once = ONCE_INIT;
+
+fn budget_setting() -> &'static Mutex<Option<u64>> {
+    unsafe {
+        BUDGET_INIT.doit(|| {
+            BUDGET = mem::transmute(box Mutex::new(None::<u64>));
+        });
+        &*BUDGET
+    }
+}
+
+/// Makes builds go through mirrors, which may take up to `budget`
+/// bytes.  Until this is called, every build clones from the remote.
+pub fn set_mirror_budget(budget: u64) {
+    *budget_setting().lock() = Some(budget);
+}
+
+pub struct MirrorCache {
+    dir: Path,
+    budget: u64
+}
+
+impl MirrorCache {
+    pub fn new(dir: Path, budget: u64) -> MirrorCache {
+        MirrorCache {
+            dir: dir,
+            budget: budget
+        }
+    }
+
+    /// The cache of this worker under `build_root`, if mirrors are
+    /// enabled at all.
+    pub fn for_worker(build_root: &Path) -> Option<MirrorCache> {
+        budget_setting().lock().map(|budget| {
+            MirrorCache::new(build_root.join(MIRROR_DIR).join(worker_id()), budget)
+        })
+    }
+
+    /// Mirrors are named after a hash of their URL, which keeps the
+    /// names safe to use as paths.
+    fn mirror_path(&self, url: &str) -> Path {
+        self.dir.join(hash(HashType::SHA1, url.as_bytes()).as_slice().to_hex())
+    }
+
+    /// Brings the mirror of `url` up to date, making it if need be,
+    /// and returns its absolute path.  Other mirrors may be evicted
+    /// to make room.
+    pub fn update(&self, url: &str, timeout: Option<u64>,
+                  logs: &mut Vec<CommandLog>) -> IoResult<Path> {
+        let mirror = os::getcwd().join(self.mirror_path(url));
+        if mirror.exists() {
+            let mut fetch = io::Command::new("git");
+            fetch.arg("--git-dir").arg(mirror.as_str().unwrap_msg(line!()));
+            fetch.arg("fetch").arg("-q").arg("--prune").arg("origin");
+            try!(run_logged_commands(&vec!(fetch), timeout, SetupEnv, logs));
+        } else {
+            if !self.dir.exists() {
+                try!(fs::mkdir_recursive(&self.dir, io::USER_RWX));
+            }
+            let partial = Path::new(
+                format!("{}{}", mirror.as_str().unwrap_msg(line!()), PARTIAL_SUFFIX));
+            if partial.exists() {
+                try!(fs::rmdir_recursive(&partial));
+            }
+            let mut clone = io::Command::new("git");
+            clone.arg("clone").arg("-q").arg("--mirror").arg(url);
+            clone.arg(partial.as_str().unwrap_msg(line!()));
+            try!(run_logged_commands(&vec!(clone), timeout, SetupEnv, logs));
+            try!(fs::rename(&partial, &mirror));
+        }
+        try!(File::create(&mirror.join(LAST_USED)));
+
+        match self.evict(&mirror) {
+            Ok(_) => (),
+            Err(e) => warn(format!("Could not evict mirrors from {}: {}", self.dir.display(), e))
+        }
+        Ok(mirror)
+    }
+
+    /// Removes least recently used mirrors, other than `keep`, until
+    /// they all fit into the budget again.
+    pub fn evict(&self, keep: &Path) -> IoResult<()> {
+        let mut mirrors = Vec::new();
+        let mut total = 0;
+        for path in try!(fs::readdir(&self.dir)).iter() {
+            let size = try!(disk_usage(path));
+            // partial clones, or ones which were never used, go first
+            let last_used = fs::stat(&path.join(LAST_USED)).map(|s| s.modified).unwrap_or(0);
+            total += size;
+            mirrors.push((last_used, size, path.clone()));
+        }
+
+        mirrors.sort_by(|&(a, _, _), &(b, _, _)| a.cmp(&b));
+        for &(_, size, ref path) in mirrors.iter() {
+            if total <= self.budget {
+                break;
+            }
+            if path.filename() == keep.filename() {
+                continue;
+            }
+            try!(fs::rmdir_recursive(path));
+            total -= size;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{fs, File, USER_RWX};
+    use std::io::fs::PathExtensions;
+
+    use builder::workspace::disk_usage;
+    use super::{MirrorCache, LAST_USED};
+
+    fn fake_mirror(dir: &Path, name: &str, bytes: uint, last_used: u64) -> Path {
+        let path = dir.join(name);
+        assert!(fs::mkdir(&path, USER_RWX).is_ok());
+        assert!(File::create(&path.join("objects")).write(Vec::from_elem(bytes, 0u8).as_slice()).is_ok());
+        let stamp = path.join(LAST_USED);
+        assert!(File::create(&stamp).is_ok());
+        assert!(fs::change_file_times(&stamp, last_used, last_used).is_ok());
+        path
+    }
+
+    #[test]
+    fn mirror_names_are_stable() {
+        let cache = MirrorCache::new(Path::new("test/mirrors"), 0);
+        let url = "https://github.com/scalableinternetservices/GradrBackend.git";
+        assert_eq!(cache.mirror_path(url), cache.mirror_path(url));
+        assert!(cache.mirror_path(url) != cache.mirror_path("https://github.com/other.git"));
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let dir = Path::new("test/mirrors/eviction");
+        let _ = fs::rmdir_recursive(&dir);
+        assert!(fs::mkdir_recursive(&dir, USER_RWX).is_ok());
+
+        let oldest = fake_mirror(&dir, "oldest", 4096, 1000);
+        let older = fake_mirror(&dir, "older", 4096, 2000);
+        let newer = fake_mirror(&dir, "newer", 4096, 3000);
+        // in use, so it stays even though it's the oldest of all
+        let in_use = fake_mirror(&dir, "in_use", 4096, 500);
+
+        // only room for two
+        let budget = disk_usage(&in_use).unwrap() + disk_usage(&newer).unwrap();
+        let cache = MirrorCache::new(dir.clone(), budget);
+        assert!(cache.evict(&in_use).is_ok());
+
+        assert!(in_use.exists());
+        assert!(!oldest.exists());
+        assert!(!older.exists());
+        assert!(newer.exists());
+    }
+}
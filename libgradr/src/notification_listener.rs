@@ -17,14 +17,20 @@ use self::hyper::{IpAddr, Port};
 // Upon receiving a notification, information gets put into
 // a database which is polled upon later.
 
+/// A push, along with the commit it moved the branch to, if known.
+pub struct Push {
+    pub notification: PushNotification,
+    pub sha: Option<String>
+}
+
 pub trait NotificationSource : Send {
-    fn get_notification(&self) -> Option<PushNotification>;
+    fn get_notification(&self) -> Option<Push>;
 
     /// Returns true if processing should continue, else false
     fn notification_event_loop_step<D : Database>(&self, db: &D) -> bool {
         match self.get_notification() {
-            Some(not) => {
-                db.add_pending(not);
+            Some(push) => {
+                db.add_pending(push.notification, push.sha);
                 true
             },
             None => false
@@ -49,8 +55,10 @@ pub struct GitHubServer<'a> {
 }
 
 impl NotificationSource for RunningServer {
-    fn get_notification(&self) -> Option<PushNotification> {
-        self.recv.recv()
+    /// `PushNotification` doesn't carry the pushed SHA, so these
+    /// pushes build whatever their branch points to.
+    fn get_notification(&self) -> Option<Push> {
+        self.recv.recv().map(|not| Push { notification: not, sha: None })
     }
 }
 
@@ -0,0 +1,221 @@
+// Instructor files which are put over a submission: makefiles, headers,
+// hidden tests and fixtures.  Neither what students commit nor what
+// their builds do can be trusted to leave these alone, so a checksum of
+// every file is kept.  Before testing, the copies in the workspace are
+// checked against these; any which differ are put back, and reported.
+
+extern crate openssl;
+
+use self::openssl::crypto::hash::{hash, HashType};
+use serialize::json::{ToJson, Json};
+use std::collections::HashMap;
+use std::io::{fs, File, IoResult, TypeFile, TypeDirectory};
+use std::io::fs::PathExtensions;
+
+use builder::Stage;
+use builder::workspace::Workspace;
+use util::MessagingUnwrapper;
+
+/// A protected instructor file which a submission got in the way of.
+#[deriving(Show, PartialEq, Clone)]
+pub struct Tampering {
+    /// Relative to the workspace
+    pub file: String,
+    /// The stage which changed or removed the file; submissions which
+    /// came with their own version of it count as environment setup
+    pub stage: Stage
+}
+
+impl ToJson for Tampering {
+    fn to_json(&self) -> Json {
+        let mut map = HashMap::new();
+        map.insert("file".to_string(), self.file.to_json());
+        map.insert("stage".to_string(), self.stage.name().to_string().to_json());
+        map.to_json()
+    }
+}
+
+struct ProtectedFile {
+    /// Relative to the workspace
+    name: String,
+    source: Path,
+    checksum: Vec<u8>
+}
+
+fn checksum(path: &Path) -> IoResult<Vec<u8>> {
+    let contents = try!(File::open(path).read_to_end());
+    Ok(hash(HashType::SHA256, contents.as_slice()))
+}
+
+pub struct InstructorFiles {
+    files: Vec<ProtectedFile>
+}
+
+impl InstructorFiles {
+    /// Every regular file under `dir`, including hidden ones, goes to
+    /// the same place relative to the workspace.
+    pub fn scan(dir: &Path) -> IoResult<InstructorFiles> {
+        let mut files = Vec::new();
+        for path in try!(fs::walk_dir(dir)).filter(|p| p.is_file()) {
+            let name = path.path_relative_from(dir).unwrap_msg(line!());
+            files.push(
+                ProtectedFile {
+                    name: name.as_str().unwrap_msg(line!()).to_string(),
+                    checksum: try!(checksum(&path)),
+                    source: path
+                });
+        }
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(InstructorFiles { files: files })
+    }
+
+    pub fn none() -> InstructorFiles {
+        InstructorFiles { files: Vec::new() }
+    }
+
+    /// Just `file`, which goes into the workspace as `name`.
+    pub fn single(file: &Path, name: &str) -> IoResult<InstructorFiles> {
+        Ok(InstructorFiles {
+            files: vec!(
+                ProtectedFile {
+                    name: name.to_string(),
+                    checksum: try!(checksum(file)),
+                    source: file.clone()
+                })
+        })
+    }
+
+    /// A digest of every file's name and contents, which changes
+    /// whenever any of them do.
+    pub fn fingerprint(&self) -> Vec<u8> {
+        let mut all = Vec::new();
+        for file in self.files.iter() {
+            all.push_all(file.name.as_bytes());
+            all.push(0);
+            all.push_all(file.checksum.as_slice());
+        }
+        hash(HashType::SHA256, all.as_slice())
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.files.iter().map(|f| f.name.clone()).collect()
+    }
+
+    /// `None` if there's nothing at all where `file` goes in `dir`.
+    /// Anything other than a regular file (e.g., a symlink to the real
+    /// thing) doesn't count as intact, and neither does a file in a
+    /// directory which is really a symlink; those are never read.
+    fn intact(&self, file: &ProtectedFile, dir: &Path) -> Option<bool> {
+        let name = Path::new(file.name.as_slice());
+        let mut path = dir.clone();
+        for component in name.dir_path().components() {
+            path.push(component);
+            match fs::lstat(&path) {
+                Ok(ref stat) if stat.kind == TypeDirectory => (),
+                Ok(_) => return Some(false),
+                Err(_) => return None
+            }
+        }
+        path.push(name.filename().unwrap_msg(line!()));
+        match fs::lstat(&path) {
+            Ok(ref stat) if stat.kind == TypeFile => {
+                Some(checksum(&path).map(|c| c == file.checksum).unwrap_or(false))
+            },
+            Ok(_) => Some(false),
+            Err(_) => None
+        }
+    }
+
+    /// Files which a submission in `dir` has its own versions of.
+    pub fn shadowed(&self, dir: &Path) -> Vec<String> {
+        self.files.iter()
+            .filter(|f| self.intact(*f, dir) == Some(false))
+            .map(|f| f.name.clone())
+            .collect()
+    }
+
+    /// Files which are missing from `dir`, or differ from the originals.
+    pub fn modified(&self, dir: &Path) -> Vec<String> {
+        self.files.iter()
+            .filter(|f| self.intact(*f, dir) != Some(true))
+            .map(|f| f.name.clone())
+            .collect()
+    }
+
+    /// Where each of the files in `names` comes from.
+    pub fn sources(&self, names: &Vec<String>) -> Vec<(String, Path)> {
+        self.files.iter()
+            .filter(|f| names.contains(&f.name))
+            .map(|f| (f.name.clone(), f.source.clone()))
+            .collect()
+    }
+
+    /// Copies the files in `names` over whatever is in the workspace,
+    /// as the worker.  Only for workspaces which nothing else can
+    /// write to.
+    pub fn copy_into(&self, workspace: &Workspace, names: &Vec<String>) -> IoResult<()> {
+        for file in self.files.iter().filter(|f| names.contains(&f.name)) {
+            try!(workspace.copy_in(&file.source, file.name.as_slice()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{fs, File, USER_RWX};
+    use std::os;
+
+    use builder::workspace::Workspace;
+    use super::InstructorFiles;
+
+    fn instructor_files() -> InstructorFiles {
+        InstructorFiles::scan(&Path::new("test/instructor_files")).unwrap()
+    }
+
+    #[test]
+    fn scans_nested_and_hidden() {
+        assert_eq!(instructor_files().names(),
+                   vec!(".hidden".to_string(),
+                        "include/grader.h".to_string(),
+                        "makefile".to_string()));
+    }
+
+    #[test]
+    fn symlinked_dirs_are_not_intact() {
+        let root = Path::new("test/workspaces/overlay");
+        let _ = fs::mkdir_recursive(&root, USER_RWX);
+        let workspace = Workspace::new(&root);
+        assert!(workspace.create().is_ok());
+        let dir = workspace.path().clone();
+        let files = instructor_files();
+
+        // the real thing, but only by way of a symlink
+        assert!(fs::symlink(&os::getcwd().join("test/instructor_files/include"),
+                            &dir.join("include")).is_ok());
+        assert_eq!(files.shadowed(&dir), vec!("include/grader.h".to_string()));
+    }
+
+    #[test]
+    fn detects_changes() {
+        let root = Path::new("test/workspaces/overlay");
+        let _ = fs::mkdir_recursive(&root, USER_RWX);
+        let workspace = Workspace::new(&root);
+        assert!(workspace.create().is_ok());
+        let dir = workspace.path().clone();
+        let files = instructor_files();
+
+        assert!(File::create(&dir.join("makefile")).write_str("all:\n").is_ok());
+        assert_eq!(files.shadowed(&dir), vec!("makefile".to_string()));
+
+        assert!(files.copy_into(&workspace, &files.names()).is_ok());
+        assert!(files.modified(&dir).is_empty());
+
+        assert!(File::create(&dir.join("include/grader.h")).write_str("").is_ok());
+        assert!(fs::unlink(&dir.join(".hidden")).is_ok());
+        assert_eq!(files.modified(&dir),
+                   vec!(".hidden".to_string(), "include/grader.h".to_string()));
+        // missing isn't shadowed, since the submission didn't have one
+        assert_eq!(files.shadowed(&dir), vec!("include/grader.h".to_string()));
+    }
+}
@@ -0,0 +1,296 @@
+// Runs untrusted build and test commands inside of Linux namespaces,
+// via bubblewrap (`bwrap`).  Inside the sandbox, the only writable
+// place is the build directory, which shows up as `BUILD_DIR`; the
+// toolchain is visible read-only, and there is no network.
+//
+// Independently of that, the build and test stages can run as a
+// throwaway uid from a pool, so that student code can't touch
+// anything belonging to the worker.  Switching uids requires the
+// worker to run as root (or with CAP_SETUID, CAP_SETGID and CAP_CHOWN).
+
+extern crate libc;
+
+use std::collections::HashSet;
+use std::default::Default;
+use std::io::Command;
+use std::io::fs::PathExtensions;
+use std::mem;
+use std::os;
+use std::sync::{Mutex, Once, ONCE_INIT};
+
+use builder::{CommandLine, run_command};
+use util::{MessagingUnwrapper, warn};
+
+/// Where the build directory is mounted inside of the sandbox.
+pub static BUILD_DIR: &'static str = "/build";
+
+/// What is made visible by default, if it exists on the host.
+static DEFAULT_TOOLCHAIN: &'static [&'static str] = &[
+    "/usr", "/bin", "/lib", "/lib64", "/etc/alternatives", "/etc/ld.so.cache"];
+
+#[deriving(Show, Clone)]
+pub struct Sandbox {
+    /// Host paths which are mounted read-only, at the same place,
+    /// inside of the sandbox.
+    pub toolchain: Vec<Path>
+}
+
+impl Default for Sandbox {
+    fn default() -> Sandbox {
+        Sandbox {
+            toolchain: DEFAULT_TOOLCHAIN.iter().map(|p| Path::new(*p)).collect()
+        }
+    }
+}
+
+impl Sandbox {
+    fn bwrap_args(&self, build_dir: &Path, binds: &[(Path, &'static str)]) -> Vec<String> {
+        let mut args: Vec<String> =
+            ["--unshare-user", "--unshare-pid", "--unshare-net",
+             "--unshare-ipc", "--unshare-uts", "--die-with-parent"]
+            .iter().map(|a| a.to_string()).collect();
+
+        for path in self.toolchain.iter().filter(|p| p.exists()) {
+            let p = path.as_str().unwrap_msg(line!()).to_string();
+            args.push("--ro-bind".to_string());
+            args.push(p.clone());
+            args.push(p);
+        }
+
+        let build_dir = os::getcwd().join(build_dir);
+        args.push_all(
+            ["--proc", "/proc", "--dev", "/dev", "--tmpfs", "/tmp",
+             "--bind", build_dir.as_str().unwrap_msg(line!()), BUILD_DIR,
+             "--chdir", BUILD_DIR]
+            .iter().map(|a| a.to_string()).collect::<Vec<String>>()
+            .as_slice());
+        for &(ref host, inside) in binds.iter() {
+            args.push("--bind".to_string());
+            args.push(os::getcwd().join(host).as_str().unwrap_msg(line!()).to_string());
+            args.push(inside.to_string());
+        }
+        args
+    }
+
+    /// Makes the given command run inside of the sandbox, with
+    /// `build_dir` as its working directory.
+    pub fn wrap(&self, build_dir: &Path, line: CommandLine) -> CommandLine {
+        self.wrap_binding(build_dir, &[], line)
+    }
+
+    /// Like `wrap`, but also makes each host directory in `binds`
+    /// writable inside of the sandbox, at the given place.
+    pub fn wrap_binding(&self, build_dir: &Path, binds: &[(Path, &'static str)],
+                        line: CommandLine) -> CommandLine {
+        line.wrapped_in("bwrap", self.bwrap_args(build_dir, binds))
+    }
+}
+
+/// Uids which nothing else on the host uses, from which each job
+/// gets its own.  Workers sharing a host need disjoint ranges.
+#[deriving(Show, PartialEq, Clone)]
+pub struct UidRange {
+    pub first: uint,
+    pub count: uint
+}
+
+impl UidRange {
+    /// Parses an inclusive range, like `20000-20099`.
+    pub fn parse(s: &str) -> Option<UidRange> {
+        let parts: Vec<&str> = s.trim().split('-').collect();
+        if parts.len() != 2 {
+            return None;
+        }
+        match (from_str::<uint>(parts[0]), from_str::<uint>(parts[1])) {
+            (Some(first), Some(last)) if first > 0 && first <= last =>
+                Some(UidRange { first: first, count: last - first + 1 }),
+            _ => None
+        }
+    }
+}
+
+struct UidPool {
+    range: Option<UidRange>,
+    in_use: HashSet<uint>
+}
+
+static mut POOL: *const Mutex<UidPool> = 0 as *const Mutex<UidPool>;
+static POOL_INIT: Once = ONCE_INIT;
+
+fn uid_pool() -> &'static Mutex<UidPool> {
+    unsafe {
+        POOL_INIT.doit(|| {
+            POOL = mem::transmute(
+                box Mutex::new(
+                    UidPool {
+                        range: None,
+                        in_use: HashSet::new()
+                    }));
+        });
+        &*POOL
+    }
+}
+
+/// Makes jobs run as uids from the given range.  Until this is called,
+/// everything runs as the worker.
+pub fn set_uid_range(range: UidRange) {
+    uid_pool().lock().range = Some(range);
+}
+
+/// A uid (and gid of the same number) which belongs to a single job
+/// until dropped.
+#[deriving(Show)]
+pub struct JobUser {
+    pub uid: uint,
+    pub gid: uint
+}
+
+impl JobUser {
+    /// Returns `None` if there is no pool, or if every uid in it is
+    /// taken.
+    pub fn allocate() -> Option<JobUser> {
+        let mut pool = uid_pool().lock();
+        let range = match pool.range {
+            Some(ref r) => r.clone(),
+            None => return None
+        };
+        for uid in ::std::iter::range(range.first, range.first + range.count) {
+            if !pool.in_use.contains(&uid) {
+                pool.in_use.insert(uid);
+                return Some(JobUser { uid: uid, gid: uid });
+            }
+        }
+        warn(format!("Every uid in {} is in use; running as the worker", range));
+        None
+    }
+
+    /// Makes this user the owner of everything in `dir`.  Symlinks
+    /// are changed themselves, rather than whatever they point to.
+    pub fn chown_command(&self, dir: &Path) -> Command {
+        chown_command(self.uid, self.gid, dir)
+    }
+
+    /// Gives everything in `dir` back to the worker.
+    pub fn reclaim_command(&self, dir: &Path) -> Command {
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        chown_command(uid as uint, gid as uint, dir)
+    }
+
+    /// Writes whatever is on stdin to `name`, relative to the working
+    /// directory, as this user, making any directories leading up to
+    /// it.  Whatever was there is replaced, rather than written
+    /// through; either way, this user can only change what it could
+    /// have changed itself.  The file ends up with `mode`.
+    pub fn write_command(&self, name: &str, mode: u32) -> Command {
+        let mut c = Command::new("sh");
+        c.arg("-c")
+            .arg("mkdir -p -- \"$(dirname -- \"$1\")\" && rm -f -- \"$1\" && \
+                  cat > \"$1\" && chmod \"$2\" \"$1\"")
+            .arg("sh").arg(name).arg(format!("{:o}", mode));
+        c.uid(self.uid);
+        c.gid(self.gid);
+        c
+    }
+
+    /// Kills everything running as this user.  `pkill` exits with 1
+    /// when there was nothing to kill.
+    pub fn kill_command(&self) -> Command {
+        let mut c = Command::new("sh");
+        c.arg("-c").arg(format!("pkill -KILL -U {} || [ $? -eq 1 ]", self.uid));
+        c
+    }
+}
+
+fn chown_command(uid: uint, gid: uint, dir: &Path) -> Command {
+    let mut c = Command::new("chown");
+    c.arg("-R").arg("-h").arg(format!("{}:{}", uid, gid));
+    c.arg(dir.as_str().unwrap_msg(line!()));
+    c
+}
+
+impl Drop for JobUser {
+    /// Anything still running as this user (e.g., something which left
+    /// its process group) is killed, so the next job doesn't inherit it.
+    #[allow(unused_must_use)]
+    fn drop(&mut self) {
+        run_command(&self.kill_command(), None, ());
+        uid_pool().lock().in_use.remove(&self.uid);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os;
+
+    use builder::CommandLine;
+    use super::{Sandbox, UidRange, JobUser, BUILD_DIR};
+
+    fn position(args: &Vec<String>, arg: &str) -> Option<uint> {
+        args.iter().position(|a| a.as_slice() == arg)
+    }
+
+    #[test]
+    fn wraps_command() {
+        let sandbox = Sandbox { toolchain: vec!(Path::new("/usr")) };
+        let line = sandbox.wrap(&Path::new("test/build"),
+                                CommandLine::new("make", &["-s", "test"]));
+        assert_eq!(line.program.as_slice(), "bwrap");
+
+        let args = &line.args;
+        let dashes = position(args, "--").unwrap();
+        assert_eq!(args.slice_from(dashes + 1),
+                   ["make".to_string(), "-s".to_string(), "test".to_string()].as_slice());
+
+        let net = position(args, "--unshare-net").unwrap();
+        assert!(net < dashes);
+
+        let bind = position(args, "--bind").unwrap();
+        let abs = os::getcwd().join("test/build");
+        assert_eq!(args[bind + 1].as_slice(), abs.as_str().unwrap());
+        assert_eq!(args[bind + 2].as_slice(), BUILD_DIR);
+
+        let ro = position(args, "--ro-bind").unwrap();
+        assert_eq!(args[ro + 1].as_slice(), "/usr");
+    }
+
+    #[test]
+    fn binds_extra_dirs() {
+        let sandbox = Sandbox { toolchain: Vec::new() };
+        let line = sandbox.wrap_binding(&Path::new("test/build"),
+                                        &[(Path::new("test/reports"), "/reports")],
+                                        CommandLine::new("true", &[]));
+        let args = &line.args;
+        let dashes = position(args, "--").unwrap();
+        let bind = args.slice_to(dashes).iter().rposition(|a| a.as_slice() == "--bind").unwrap();
+        let abs = os::getcwd().join("test/reports");
+        assert_eq!(args[bind + 1].as_slice(), abs.as_str().unwrap());
+        assert_eq!(args[bind + 2].as_slice(), "/reports");
+    }
+
+    #[test]
+    fn skips_missing_toolchain() {
+        let sandbox = Sandbox { toolchain: vec!(Path::new("/no/such/dir")) };
+        let line = sandbox.wrap(&Path::new("."), CommandLine::new("true", &[]));
+        assert!(position(&line.args, "--ro-bind").is_none());
+    }
+
+    #[test]
+    fn writes_with_mode() {
+        let user = JobUser { uid: 20000, gid: 20000 };
+        let write = user.write_command("include/grader.h", 0o644).to_string();
+        assert!(write.as_slice().contains("include/grader.h"));
+        assert!(write.as_slice().contains("644"));
+        // not in the pool, so it mustn't go back there
+        unsafe { ::std::mem::forget(user); }
+    }
+
+    #[test]
+    fn parses_uid_range() {
+        assert_eq!(UidRange::parse("20000-20099"),
+                   Some(UidRange { first: 20000, count: 100 }));
+        assert_eq!(UidRange::parse("5-5"), Some(UidRange { first: 5, count: 1 }));
+        assert_eq!(UidRange::parse("0-10"), None);
+        assert_eq!(UidRange::parse("10-5"), None);
+        assert_eq!(UidRange::parse("20000"), None);
+    }
+}
@@ -0,0 +1,485 @@
+// Syscall filtering for test commands, via seccomp.  There is no way
+// to run code between `fork` and `exec` with `std::io::process`, so the
+// filter is installed by the `gradr_exec` helper, which then runs the
+// real command.  Every build and test command goes through
+// `gradr_exec`, profile or not, as it also reports limits which were
+// hit further down the process tree.  `gradr_exec` needs to be on the
+// `PATH` (and inside the sandbox's toolchain, if one is used).
+//
+// Filters are deny lists, since `make` and the shell need a lot of
+// syscalls just to get the test program started.  A denied syscall
+// is handed to `gradr_exec`, which kills the caller with SIGSYS and,
+// once the command is done, dies of SIGSYS itself.  That way, the
+// worker finds out even when the caller wasn't the command itself
+// (e.g., the `a.out` which `make` runs).  Syscall numbers are for
+// x86_64; any other architecture is denied outright.
+
+extern crate libc;
+
+use self::libc::{c_int, c_long, c_ulong, c_ushort, pid_t};
+use std::io::{IoResult, IoError};
+use std::mem;
+use std::io::process::ExitSignal;
+
+use builder::{CommandLine, CommandLog};
+
+use self::Profile::{PureComputation, FileIo, Networking};
+
+/// Name of the helper which installs a profile and runs a command.
+pub static EXEC_HELPER: &'static str = "gradr_exec";
+
+/// What a process which made a denied syscall is killed with.
+pub static SIGSYS: int = 31;
+
+#[deriving(Show, PartialEq, Clone)]
+pub enum Profile {
+    /// No networking, and no changes to the filesystem
+    PureComputation,
+    /// No networking
+    FileIo,
+    /// Only the syscalls which are denied in every profile
+    Networking
+}
+
+// Things which student code never has any business doing
+static ALWAYS_DENIED: &'static [u32] = &[
+    101, // ptrace
+    155, // pivot_root
+    159, // adjtimex
+    161, // chroot
+    163, // acct
+    164, // settimeofday
+    165, // mount
+    166, // umount2
+    167, // swapon
+    168, // swapoff
+    169, // reboot
+    170, // sethostname
+    171, // setdomainname
+    172, // iopl
+    173, // ioperm
+    175, // init_module
+    176, // delete_module
+    227, // clock_settime
+    246, // kexec_load
+    248, // add_key
+    249, // request_key
+    250, // keyctl
+    272, // unshare
+    298, // perf_event_open
+    308, // setns
+    310, // process_vm_readv
+    311, // process_vm_writev
+    313, // finit_module
+    321, // bpf
+    425, // io_uring_setup, whose requests get around everything here
+    426, // io_uring_enter
+    427  // io_uring_register
+];
+
+static NETWORK: &'static [u32] = &[
+    41,  // socket
+    42,  // connect
+    43,  // accept
+    49,  // bind
+    50,  // listen
+    288  // accept4
+];
+
+static FILE_CHANGES: &'static [u32] = &[
+    76,  // truncate
+    77,  // ftruncate
+    82,  // rename
+    83,  // mkdir
+    84,  // rmdir
+    85,  // creat
+    86,  // link
+    87,  // unlink
+    88,  // symlink
+    90,  // chmod
+    91,  // fchmod
+    92,  // chown
+    93,  // fchown
+    94,  // lchown
+    132, // utime
+    133, // mknod
+    188, // setxattr
+    189, // lsetxattr
+    190, // fsetxattr
+    197, // removexattr
+    198, // lremovexattr
+    199, // fremovexattr
+    235, // utimes
+    258, // mkdirat
+    259, // mknodat
+    260, // fchownat
+    261, // futimesat
+    263, // unlinkat
+    264, // renameat
+    265, // linkat
+    266, // symlinkat
+    268, // fchmodat
+    280, // utimensat
+    285, // fallocate
+    316, // renameat2
+    437, // openat2, whose flags BPF can't see
+    452  // fchmodat2
+];
+
+// `open` and `openat` are only denied when asked to write
+static SYS_OPEN: u32 = 2;
+static SYS_OPENAT: u32 = 257;
+static O_WRITE_FLAGS: u32 = 0o1 | 0o2 | 0o100 | 0o1000; // WRONLY | RDWR | CREAT | TRUNC
+
+// Like `unshare`, `clone` can make new namespaces; it's only denied
+// when asked to
+static SYS_CLONE: u32 = 56;
+// NEWNS | NEWCGROUP | NEWUTS | NEWIPC | NEWUSER | NEWPID | NEWNET
+static CLONE_NEW_FLAGS: u32 = 0x00020000 | 0x02000000 | 0x04000000 | 0x08000000 |
+                              0x10000000 | 0x20000000 | 0x40000000;
+
+// `clone3` takes its flags in memory, where BPF can't see them.  It
+// fails as if the kernel were too old for it, so that the C library
+// falls back on `clone`, rather than the caller being killed.
+static SYS_CLONE3: u32 = 435;
+static ENOSYS: u32 = 38;
+
+static AUDIT_ARCH_X86_64: u32 = 0xc000003e;
+static X32_SYSCALL_BIT: u32 = 0x40000000;
+
+// Offsets into `struct seccomp_data`
+static DATA_NR: u32 = 0;
+static DATA_ARCH: u32 = 4;
+fn data_arg(n: u32) -> u32 { 16 + 8 * n } // low half; little-endian
+
+// BPF opcodes
+static LD_W_ABS: u16 = 0x20;
+static JMP_JEQ_K: u16 = 0x15;
+static JMP_JGE_K: u16 = 0x35;
+static JMP_JSET_K: u16 = 0x45;
+static RET_K: u16 = 0x06;
+
+// What a denied syscall gets: the caller waits for `gradr_exec`
+static DENY: u32 = 0x7fc00000; // SECCOMP_RET_USER_NOTIF
+static SECCOMP_RET_ERRNO: u32 = 0x00050000;
+static SECCOMP_RET_ALLOW: u32 = 0x7fff0000;
+
+static PR_SET_NO_NEW_PRIVS: c_int = 38;
+static SYS_SECCOMP: c_long = 317;
+static SECCOMP_SET_MODE_FILTER: c_ulong = 1;
+static SECCOMP_FILTER_FLAG_NEW_LISTENER: c_ulong = 8;
+static SECCOMP_IOCTL_NOTIF_RECV: c_ulong = 0xc0502100;
+static SECCOMP_IOCTL_NOTIF_SEND: c_ulong = 0xc0182101;
+static EPERM: i32 = 1;
+
+#[repr(C)]
+#[deriving(Show, PartialEq, Clone)]
+pub struct SockFilter {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32
+}
+
+#[repr(C)]
+struct SockFprog {
+    len: c_ushort,
+    filter: *const SockFilter
+}
+
+/// `struct seccomp_data`
+#[repr(C)]
+struct SeccompData {
+    nr: c_int,
+    arch: u32,
+    instruction_pointer: u64,
+    args: [u64, ..6]
+}
+
+/// `struct seccomp_notif`; a denied syscall which is waiting on
+/// `gradr_exec`.
+#[repr(C)]
+pub struct Notification {
+    id: u64,
+    pid: u32,
+    flags: u32,
+    data: SeccompData
+}
+
+/// `struct seccomp_notif_resp`
+#[repr(C)]
+struct NotificationResponse {
+    id: u64,
+    val: i64,
+    error: i32,
+    flags: u32
+}
+
+extern {
+    fn prctl(option: c_int, arg2: c_ulong, arg3: c_ulong,
+             arg4: c_ulong, arg5: c_ulong) -> c_int;
+    fn syscall(number: c_long, ...) -> c_long;
+    fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+}
+
+fn stmt(code: u16, k: u32) -> SockFilter {
+    SockFilter { code: code, jt: 0, jf: 0, k: k }
+}
+
+fn jump(code: u16, k: u32, jt: u8, jf: u8) -> SockFilter {
+    SockFilter { code: code, jt: jt, jf: jf, k: k }
+}
+
+/// Denies syscall `nr` when any of `flags` are set in argument `arg`.
+/// Leaves the syscall number loaded, as it was.
+fn deny_with_flags(prog: &mut Vec<SockFilter>, nr: u32, arg: u32, flags: u32) {
+    prog.push(jump(JMP_JEQ_K, nr, 0, 4));
+    prog.push(stmt(LD_W_ABS, data_arg(arg)));
+    prog.push(jump(JMP_JSET_K, flags, 0, 1));
+    prog.push(stmt(RET_K, DENY));
+    prog.push(stmt(LD_W_ABS, DATA_NR));
+}
+
+impl Profile {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            PureComputation => "pure_computation",
+            FileIo => "file_io",
+            Networking => "networking"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Profile> {
+        [PureComputation, FileIo, Networking].iter()
+            .find(|p| p.name() == name)
+            .map(|p| p.clone())
+    }
+
+    fn denied(&self) -> Vec<u32> {
+        let mut denied = ALWAYS_DENIED.to_vec();
+        match *self {
+            PureComputation => {
+                denied.push_all(NETWORK);
+                denied.push_all(FILE_CHANGES);
+            },
+            FileIo => denied.push_all(NETWORK),
+            Networking => ()
+        }
+        denied
+    }
+
+    /// The BPF program for this profile.
+    pub fn filter(&self) -> Vec<SockFilter> {
+        let mut prog = vec!(
+            stmt(LD_W_ABS, DATA_ARCH),
+            jump(JMP_JEQ_K, AUDIT_ARCH_X86_64, 1, 0),
+            stmt(RET_K, DENY),
+            stmt(LD_W_ABS, DATA_NR),
+            jump(JMP_JGE_K, X32_SYSCALL_BIT, 0, 1),
+            stmt(RET_K, DENY));
+
+        for nr in self.denied().iter() {
+            prog.push(jump(JMP_JEQ_K, *nr, 0, 1));
+            prog.push(stmt(RET_K, DENY));
+        }
+
+        prog.push(jump(JMP_JEQ_K, SYS_CLONE3, 0, 1));
+        prog.push(stmt(RET_K, SECCOMP_RET_ERRNO | ENOSYS));
+        deny_with_flags(&mut prog, SYS_CLONE, 0, CLONE_NEW_FLAGS);
+
+        if *self == PureComputation {
+            deny_with_flags(&mut prog, SYS_OPEN, 1, O_WRITE_FLAGS);
+            deny_with_flags(&mut prog, SYS_OPENAT, 2, O_WRITE_FLAGS);
+        }
+
+        prog.push(stmt(RET_K, SECCOMP_RET_ALLOW));
+        prog
+    }
+
+    /// Applies this profile to the calling process, and to anything
+    /// it later runs.  This cannot be undone.  Denied syscalls wait on
+    /// the returned listener, so the caller mustn't make any itself.
+    pub fn install(&self) -> IoResult<Listener> {
+        let filter = self.filter();
+        let prog = SockFprog {
+            len: filter.len() as c_ushort,
+            filter: filter.as_ptr()
+        };
+        unsafe {
+            if prctl(PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                return Err(IoError::last_error());
+            }
+            let fd = syscall(SYS_SECCOMP, SECCOMP_SET_MODE_FILTER,
+                             SECCOMP_FILTER_FLAG_NEW_LISTENER, &prog as *const SockFprog);
+            if fd < 0 {
+                return Err(IoError::last_error());
+            }
+            Ok(Listener { fd: fd as c_int })
+        }
+    }
+
+    /// Makes the given command run under this profile.
+    pub fn wrap(&self, line: CommandLine) -> CommandLine {
+        supervised(&Some(self.clone()), line)
+    }
+}
+
+/// Where the denied syscalls of everything under a profile go.
+pub struct Listener {
+    fd: c_int
+}
+
+impl Listener {
+    /// Waits for the next denied syscall.
+    pub fn next(&self) -> IoResult<Notification> {
+        unsafe {
+            let mut notification: Notification = mem::zeroed();
+            if ioctl(self.fd, SECCOMP_IOCTL_NOTIF_RECV, &mut notification as *mut Notification) != 0 {
+                return Err(IoError::last_error());
+            }
+            Ok(notification)
+        }
+    }
+
+    /// Kills whatever made the denied syscall with SIGSYS.  The syscall
+    /// fails, in case the caller is already on its way out.
+    pub fn deny(&self, notification: &Notification) {
+        let response = NotificationResponse {
+            id: notification.id,
+            val: 0,
+            error: -EPERM,
+            flags: 0
+        };
+        unsafe {
+            libc::kill(notification.pid as pid_t, SIGSYS as c_int);
+            // an error just means the caller is already gone
+            ioctl(self.fd, SECCOMP_IOCTL_NOTIF_SEND, &response as *const NotificationResponse);
+        }
+    }
+}
+
+/// Makes the given command run under `gradr_exec`, with `profile`, if
+/// there is one.
+pub fn supervised(profile: &Option<Profile>, line: CommandLine) -> CommandLine {
+    let args =
+        match *profile {
+            Some(ref p) => vec!("--profile".to_string(), p.name().to_string()),
+            None => Vec::new()
+        };
+    line.wrapped_in(EXEC_HELPER, args)
+}
+
+/// Figures out if the given command, or anything it ran, made a denied
+/// syscall; `gradr_exec` dies of SIGSYS if so.  Only the signal counts:
+/// anything on stderr could have been printed by the program itself.
+pub fn blocked_syscall(log: &CommandLog) -> bool {
+    match log.exit {
+        Some(ExitSignal(sig)) => sig == SIGSYS,
+        _ => false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::process::{ExitStatus, ExitSignal};
+
+    use builder::{CommandLine, CommandLog};
+    use builder::Stage::Test;
+    use super::{Profile, NETWORK, SECCOMP_RET_ALLOW, SECCOMP_RET_ERRNO, ENOSYS, RET_K,
+                JMP_JSET_K, SYS_CLONE, SYS_CLONE3, CLONE_NEW_FLAGS, SIGSYS,
+                blocked_syscall, supervised};
+    use super::Profile::{PureComputation, FileIo, Networking};
+
+    fn denies(profile: &Profile, nr: u32) -> bool {
+        profile.filter().iter().any(|f| f.jt == 0 && f.jf == 1 && f.k == nr)
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for p in [PureComputation, FileIo, Networking].iter() {
+            assert_eq!(Profile::from_name(p.name()), Some(p.clone()));
+        }
+        assert_eq!(Profile::from_name("nonsense"), None);
+    }
+
+    #[test]
+    fn filter_ends_in_allow() {
+        let filter = PureComputation.filter();
+        let last = filter.last().unwrap();
+        assert_eq!(last.code, RET_K);
+        assert_eq!(last.k, SECCOMP_RET_ALLOW);
+    }
+
+    #[test]
+    fn network_denied_by_profile() {
+        for nr in NETWORK.iter() {
+            assert!(denies(&PureComputation, *nr));
+            assert!(denies(&FileIo, *nr));
+            assert!(!denies(&Networking, *nr));
+        }
+    }
+
+    #[test]
+    fn file_changes_denied_only_for_pure_computation() {
+        // unlink
+        assert!(denies(&PureComputation, 87));
+        assert!(!denies(&FileIo, 87));
+    }
+
+    #[test]
+    fn other_ways_of_changing_files_denied() {
+        // ftruncate, mknod, mknodat, fallocate, openat2, fchmodat2,
+        // utime, utimes, futimesat, utimensat, and the xattr calls
+        for nr in [77, 133, 259, 285, 437, 452, 132, 235, 261, 280,
+                   188, 189, 190, 197, 198, 199].iter() {
+            assert!(denies(&PureComputation, *nr));
+            assert!(!denies(&FileIo, *nr));
+        }
+    }
+
+    #[test]
+    fn io_uring_and_new_namespaces_denied() {
+        for p in [PureComputation, FileIo, Networking].iter() {
+            for nr in [425, 426, 427].iter() {
+                assert!(denies(p, *nr));
+            }
+
+            let filter = p.filter();
+            let clone3 = filter.iter().position(|f| f.k == SYS_CLONE3).unwrap();
+            assert_eq!(filter[clone3 + 1].code, RET_K);
+            assert_eq!(filter[clone3 + 1].k, SECCOMP_RET_ERRNO | ENOSYS);
+
+            let clone = filter.iter().position(|f| f.k == SYS_CLONE).unwrap();
+            assert_eq!(filter[clone + 2].code, JMP_JSET_K);
+            assert_eq!(filter[clone + 2].k, CLONE_NEW_FLAGS);
+        }
+    }
+
+    #[test]
+    fn wraps_command() {
+        let line = FileIo.wrap(CommandLine::new("make", &["-s", "test"]));
+        assert_eq!(line, CommandLine::new(
+            "gradr_exec", &["--profile", "file_io", "--", "make", "-s", "test"]));
+        let line = supervised(&None, CommandLine::new("make", &["-s", "build"]));
+        assert_eq!(line, CommandLine::new("gradr_exec", &["--", "make", "-s", "build"]));
+    }
+
+    #[test]
+    fn blocked_only_by_signal() {
+        let mut log = CommandLog {
+            stage: Test,
+            command: "./a.out".to_string(),
+            exit: Some(ExitSignal(SIGSYS)),
+            stdout: String::new(),
+            stderr: String::new(),
+            truncated: false
+        };
+        assert!(blocked_syscall(&log));
+
+        // `make` saying so doesn't count; `gradr_exec` would have died
+        // of SIGSYS, had its `a.out` really been killed
+        log.exit = Some(ExitStatus(2));
+        log.stderr = "make: *** [makefile:5: test] Bad system call".to_string();
+        assert!(!blocked_syscall(&log));
+    }
+}
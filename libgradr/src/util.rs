@@ -1,5 +1,7 @@
 extern crate time;
 
+use std::io;
+
 pub trait MessagingUnwrapper<A> {
     fn unwrap_msg(self, orig_line: uint) -> A;
 }
@@ -31,6 +33,12 @@ impl<A> MessagingUnwrapper<A> for Option<A> {
     }
 }
 
+/// Reports something which went wrong, but which nothing fails over,
+/// on stderr.  Everything like that goes through here.
+pub fn warn(msg: String) {
+    let _ = io::stderr().write_line(msg.as_slice());
+}
+
 pub fn current_time_millis() -> i64 {
     let timespec = time::get_time();
     timespec.sec + timespec.nsec as i64 / 1000 / 1000
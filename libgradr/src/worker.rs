@@ -1,7 +1,7 @@
 // A worker thread.  Workers get items from the database,
 // and process them.
 
-use builder::{WholeBuildable, ToWholeBuildable};
+use builder::{WholeBuildable, ToWholeBuildable, is_shutting_down, clear_cancelled};
 use database::Database;
 
 /// A = key type
@@ -9,11 +9,19 @@ use database::Database;
 pub fn worker_loop_step<D : Database>(db: &D) {
     match db.get_pending() {
         Some(ref a) => {
+            clear_cancelled();
             // cannot do this as a one-liner, because we transfer ownership
             // with the first parameter to `add_test_results`, and the compiler
             // won't allow the `a.to_whole_buildable...` after that
             let res = a.to_whole_buildable().whole_build();
-            db.add_test_results(a, res);
+            if is_shutting_down() {
+                // the build was cut short, so the results are meaningless;
+                // let whichever worker is around next try again
+                db.return_pending(a);
+            } else {
+                // including cancelled builds, which say so
+                db.add_test_results(a, res);
+            }
         },
         None => ()
     }
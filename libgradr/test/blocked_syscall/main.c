@@ -0,0 +1,8 @@
+#include <stdio.h>
+#include <sys/socket.h>
+
+int main() {
+  socket(AF_INET, SOCK_STREAM, 0);
+  printf("test1: PASS\n");
+  return 0;
+}
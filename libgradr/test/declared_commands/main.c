@@ -0,0 +1,6 @@
+#include "stdio.h"
+
+int main() {
+  printf("test1:PASS\ntest2:FAIL\ntest3:PASS\n");
+  return 0;
+}
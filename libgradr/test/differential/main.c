@@ -0,0 +1,12 @@
+#include "stdio.h"
+
+int main() {
+  int n, count = 0, sum = 0;
+  // only the first three numbers are added up
+  while (scanf("%d", &n) == 1 && count < 3) {
+    sum += n;
+    count++;
+  }
+  printf("sum: %d\n", sum);
+  return 0;
+}
@@ -0,0 +1,6 @@
+# Prints one to five numbers, depending on the seed.
+i=0
+while [ $i -le $(($1 % 5)) ]; do
+  echo $(($1 + i))
+  i=$((i + 1))
+done
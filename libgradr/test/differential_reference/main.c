@@ -0,0 +1,10 @@
+#include "stdio.h"
+
+int main() {
+  int n, sum = 0;
+  while (scanf("%d", &n) == 1) {
+    sum += n;
+  }
+  printf("sum: %d\n", sum);
+  return 0;
+}
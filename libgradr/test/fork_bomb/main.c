@@ -0,0 +1,22 @@
+#include "stdio.h"
+#include "unistd.h"
+
+// Forks itself into 8 processes which spin forever.  Each records its
+// pid, so the test can check that every one of them was killed.  This
+// is bounded so that a broken test doesn't take down the machine.
+
+static void record_pid() {
+  FILE* pids = fopen("pids.txt", "a");
+  fprintf(pids, "%d\n", getpid());
+  fclose(pids);
+}
+
+int main() {
+  int i;
+  for (i = 0; i < 3; i++) {
+    fork();
+  }
+  record_pid();
+  while (1) {}
+  return 0;
+}
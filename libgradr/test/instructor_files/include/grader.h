@@ -0,0 +1 @@
+#define GRADER_VERSION 1
@@ -0,0 +1,13 @@
+#include "stdio.h"
+
+int main() {
+  int n, sum = 0, count = 0;
+  while (scanf("%d", &n) == 1) {
+    if (n < 0)
+      for (;;) {}
+    sum += n;
+    count++;
+  }
+  printf("sum: %d\nmean: %f\n", sum, count ? (double)sum / count : 0.0);
+  return 0;
+}
@@ -0,0 +1,18 @@
+#include "stdio.h"
+#include "sys/stat.h"
+
+int main() {
+  FILE* report;
+  mkdir("reports", 0755);
+  report = fopen("reports/TEST-main.xml", "w");
+  fprintf(report,
+          "<testsuite name=\"main\">\n"
+          "  <testcase classname=\"main\" name=\"test1\" time=\"0.01\"/>\n"
+          "  <testcase classname=\"main\" name=\"test2\">\n"
+          "    <failure message=\"expected 1\"/>\n"
+          "  </testcase>\n"
+          "</testsuite>\n");
+  fclose(report);
+  printf("this is ignored\n");
+  return 0;
+}
@@ -0,0 +1,14 @@
+#include "stdio.h"
+#include "stdlib.h"
+
+int main() {
+  int i;
+  int *squares = malloc(10 * sizeof(int));
+  for (i = 0; i < 10; i++) {
+    squares[i] = i * i;
+  }
+  printf("test1:PASS\n");
+  // never freed
+  squares = NULL;
+  return 0;
+}
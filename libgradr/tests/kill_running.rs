@@ -0,0 +1,45 @@
+// `kill_running` kills every command the process is running, so it's
+// tested on its own, rather than alongside everything else.  Both
+// cases are in one test, so they don't cancel each other.
+
+extern crate libgradr;
+
+use libgradr::builder::{run_command, kill_running, clear_cancelled, now_millis, CANCELLED,
+                        WholeBuildable};
+use libgradr::builder::BuildOutcome::Cancelled;
+use libgradr::builder::testing::TestingRequest;
+
+use std::io::{Command, timer};
+use std::time::Duration;
+
+fn kill_soon() {
+    spawn(proc() {
+        timer::sleep(Duration::milliseconds(500));
+        kill_running();
+    });
+}
+
+#[test]
+fn cancels_running_commands() {
+    kill_soon();
+
+    // the backgrounded sleep is in the same process group, so it goes, too
+    let mut c = Command::new("sh");
+    c.arg("-c").arg("sleep 30 & sleep 30");
+    let start = now_millis();
+    let res = run_command(&c, None, ());
+    assert_eq!(res.err().map(|e| e.desc), Some(CANCELLED));
+    assert!(now_millis() - start < 10 * 1000);
+
+    // a cancelled build says so, rather than looking like a failure
+    clear_cancelled();
+    kill_soon();
+    let r = TestingRequest::new(Path::new("test/cpu_hog"), Path::new("test/makefile"));
+    let start = now_millis();
+    match r.whole_build().outcome {
+        // most likely while testing, but the build may be slow
+        Cancelled(_) => (),
+        _ => { assert!(false); }
+    };
+    assert!(now_millis() - start < 10 * 1000);
+}
@@ -13,6 +13,7 @@ use self::github::clone_url::CloneUrl;
 
 use self::postgres::GenericConnection;
 
+use libgradr::builder::RESULTS_VERSION;
 use libgradr::database::Database;
 use libgradr::database::postgres_db::{PostgresDatabase, Build, BuildSearch,
                                       Commit, CommitSearch};
@@ -115,6 +116,9 @@ fn full_end_to_end() {
                     assert!(obj.is_some());
 
                     let obj = obj.unwrap();
+                    assert_eq!(obj.get(&"version".to_string()),
+                               Some(&RESULTS_VERSION.to_json()));
+
                     let res = obj.get(&"success".to_string());
                     assert!(res.is_some());
 
@@ -122,10 +126,14 @@ fn full_end_to_end() {
                     assert!(res.is_some());
 
                     let res = res.unwrap();
-                    assert_eq!(res.get(&"test1".to_string()),
-                               Some(&true.to_json()));
-                    assert_eq!(res.get(&"test2".to_string()),
-                               Some(&false.to_json()));
+                    let result_of = |name: &str| {
+                        res.get(&name.to_string())
+                            .and_then(|t| t.as_object())
+                            .and_then(|t| t.get(&"result".to_string()))
+                            .map(|r| r.clone())
+                    };
+                    assert_eq!(result_of("test1"), Some("pass".to_string().to_json()));
+                    assert_eq!(result_of("test2"), Some("fail".to_string().to_json()));
                     true
                 })
             }).unwrap_or(false)
@@ -0,0 +1,4 @@
+-- Commits record the SHA which was pushed, so that builds check out
+-- exactly that commit.  NULL for commits recorded before this, and for
+-- pushes which didn't come with a SHA; those build the branch.
+ALTER TABLE commits ADD COLUMN sha varchar(40);